@@ -15,6 +15,8 @@ fn main() {
         let path = src_path.join(entry.file_name());
         println!("cargo:rerun-if-changed={}", path.to_str().unwrap());
     }
+    let helpers_path = manifest_path.join("src/grammar.c");
+    println!("cargo:rerun-if-changed={}", helpers_path.to_str().unwrap());
 
     config
         .flag_if_supported("-std=c11")
@@ -28,5 +30,6 @@ fn main() {
         .define("_DEFAULT_SOURCE", None)
         .warnings(false)
         .file(src_path.join("lib.c"))
+        .file(helpers_path)
         .compile("tree-sitter");
 }
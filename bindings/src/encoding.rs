@@ -0,0 +1,238 @@
+use std::fmt;
+use std::ops;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::slice;
+
+use regex_cursor::Cursor;
+
+use crate::parser::{DecodeInputFn, InputEncoding};
+use crate::Input;
+
+/// The text encoding of an [`Input`].
+///
+/// Byte offsets of nodes always refer to the encoded text. Text predicates
+/// like `#eq?` and `#match?` decode the text of captured nodes before
+/// comparing it with the (UTF-8) strings and regexes of the query.
+#[derive(Debug, Clone, Copy, Default)]
+pub enum Encoding {
+    #[default]
+    Utf8,
+    Utf16LE,
+    Utf16BE,
+    /// An encoding decoded by a [`Decode`] implementation, see
+    /// [`Encoding::custom`].
+    Custom(CustomEncoding),
+}
+
+impl Encoding {
+    /// An encoding which is decoded by `D`.
+    pub fn custom<D: Decode>() -> Encoding {
+        Encoding::Custom(CustomEncoding {
+            raw: decode_raw::<D>,
+            decode: D::decode,
+        })
+    }
+
+    pub(crate) fn raw(self) -> (InputEncoding, Option<DecodeInputFn>) {
+        match self {
+            Encoding::Utf8 => (InputEncoding::Utf8, None),
+            Encoding::Utf16LE => (InputEncoding::Utf16LE, None),
+            Encoding::Utf16BE => (InputEncoding::Utf16BE, None),
+            Encoding::Custom(custom) => (InputEncoding::Custom, Some(custom.raw)),
+        }
+    }
+
+    /// Decode `bytes` into a string, replacing invalid sequences with
+    /// U+FFFD REPLACEMENT CHARACTER.
+    pub fn decode_lossy(self, bytes: &[u8]) -> String {
+        let decode_utf16 = |to_u16: fn([u8; 2]) -> u16| {
+            let units = bytes
+                .chunks(2)
+                .map(|unit| to_u16([unit[0], unit.get(1).copied().unwrap_or(0)]));
+            char::decode_utf16(units)
+                .map(|c| c.unwrap_or(char::REPLACEMENT_CHARACTER))
+                .collect()
+        };
+        match self {
+            Encoding::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Encoding::Utf16LE => decode_utf16(u16::from_le_bytes),
+            Encoding::Utf16BE => decode_utf16(u16::from_be_bytes),
+            Encoding::Custom(custom) => {
+                let mut res = String::with_capacity(bytes.len());
+                let mut bytes = bytes;
+                while !bytes.is_empty() {
+                    let (c, len) = (custom.decode)(bytes);
+                    res.push(c.unwrap_or(char::REPLACEMENT_CHARACTER));
+                    bytes = &bytes[len.clamp(1, bytes.len())..];
+                }
+                res
+            }
+        }
+    }
+}
+
+/// A decoder for a custom text encoding.
+///
+/// See [`Encoding::custom`].
+pub trait Decode {
+    /// Decode the first codepoint of `bytes`.
+    ///
+    /// Returns the decoded codepoint, or `None` if `bytes` doesn't start with a
+    /// valid codepoint, and the number of bytes that were consumed. `bytes` is
+    /// never empty and at least one byte must be consumed.
+    fn decode(bytes: &[u8]) -> (Option<char>, usize);
+}
+
+/// A custom [`Encoding`] created by [`Encoding::custom`].
+#[derive(Clone, Copy)]
+pub struct CustomEncoding {
+    raw: DecodeInputFn,
+    decode: fn(&[u8]) -> (Option<char>, usize),
+}
+
+impl fmt::Debug for CustomEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("CustomEncoding").finish_non_exhaustive()
+    }
+}
+
+/// The value the C library uses for codepoints which failed to decode.
+const DECODE_ERROR: i32 = -1;
+
+unsafe extern "C" fn decode_raw<D: Decode>(
+    string: *const u8,
+    length: u32,
+    code_point: *const i32,
+) -> u32 {
+    if length == 0 {
+        *(code_point as *mut i32) = DECODE_ERROR;
+        return 0;
+    }
+    let bytes = slice::from_raw_parts(string, length as usize);
+    let (c, len) = catch_unwind(AssertUnwindSafe(|| D::decode(bytes))).unwrap_or((None, 1));
+    *(code_point as *mut i32) = c.map_or(DECODE_ERROR, |c| c as i32);
+    len.clamp(1, length as usize) as u32
+}
+
+/// An [`Input`] adapter which marks the text of another input as encoded with
+/// the given [`Encoding`].
+pub struct EncodedInput<I> {
+    input: I,
+    encoding: Encoding,
+}
+
+impl<I: Input> EncodedInput<I> {
+    pub fn new(input: I, encoding: Encoding) -> Self {
+        EncodedInput { input, encoding }
+    }
+}
+
+impl<I: Input> Input for EncodedInput<I> {
+    type Cursor = I::Cursor;
+
+    fn cursor_at(&mut self, offset: u32) -> &mut I::Cursor {
+        self.input.cursor_at(offset)
+    }
+
+    fn eq(&mut self, range1: ops::Range<u32>, range2: ops::Range<u32>) -> bool {
+        // all supported encodings encode equal text to equal bytes
+        self.input.eq(range1, range2)
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+/// Decode the text within `range` of a (not UTF-8 encoded) input.
+pub(crate) fn decode_range<I: Input>(input: &mut I, range: ops::Range<u32>) -> String {
+    let encoding = input.encoding();
+    decode_cursor_range(input.cursor_at(range.start), encoding, range)
+}
+
+/// Decode the text within `range` starting at a cursor which was moved to the
+/// chunk containing `range.start`.
+pub(crate) fn decode_cursor_range<C: Cursor + ?Sized>(
+    cursor: &mut C,
+    encoding: Encoding,
+    range: ops::Range<u32>,
+) -> String {
+    let range = range.start as usize..range.end as usize;
+    let mut bytes = Vec::with_capacity(range.len());
+    loop {
+        let chunk_start = cursor.offset();
+        let chunk = cursor.chunk();
+        let start = range.start.saturating_sub(chunk_start).min(chunk.len());
+        let end = range.end.saturating_sub(chunk_start).min(chunk.len());
+        bytes.extend_from_slice(&chunk[start..end]);
+        if range.end <= chunk_start + chunk.len() || !cursor.advance() {
+            break;
+        }
+    }
+    encoding.decode_lossy(&bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_grammars::python_grammar;
+    use crate::{Decode, EncodedInput, Encoding, InactiveQueryCursor, Parser, Query, SliceInput};
+
+    /// Parse `src` and return the decoded text of all nodes captured by a
+    /// query with text predicates.
+    fn captured_text(src: &[u8], encoding: Encoding) -> Vec<String> {
+        let grammar = python_grammar();
+        let query = Query::new(
+            grammar,
+            r#"((identifier) @id (#eq? @id "café"))
+               ((identifier) @id (#match? @id "^ö+$"))
+               ((identifier) @id (#any-of? @id "x" "ÿ"))"#,
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        let input = || EncodedInput::new(SliceInput::new(src), encoding);
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(input(), None).unwrap();
+        let root = tree.root_node();
+        assert_eq!(root.end_byte() as usize, src.len());
+        assert_eq!(root.named_child_count(), 3);
+
+        let mut cursor = InactiveQueryCursor::default().execute_query(&query, &root, input());
+        let mut captured = Vec::new();
+        while let Some(mat) = cursor.next_match() {
+            for matched in mat.matched_nodes() {
+                let range = matched.node.byte_range();
+                let text = &src[range.start as usize..range.end as usize];
+                captured.push(encoding.decode_lossy(text));
+            }
+        }
+        captured.sort_unstable();
+        captured
+    }
+
+    const SRC: &str = "café = öö\nx = y\nÿ = 1\n";
+
+    #[test]
+    fn utf16() {
+        let expected = ["café", "x", "öö", "ÿ"];
+        let le: Vec<u8> = SRC.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        assert_eq!(captured_text(&le, Encoding::Utf16LE), expected);
+        let be: Vec<u8> = SRC.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        assert_eq!(captured_text(&be, Encoding::Utf16BE), expected);
+    }
+
+    struct Latin1;
+
+    impl Decode for Latin1 {
+        fn decode(bytes: &[u8]) -> (Option<char>, usize) {
+            (Some(bytes[0] as char), 1)
+        }
+    }
+
+    #[test]
+    fn custom_encoding() {
+        let latin1: Vec<u8> = SRC.chars().map(|c| c as u8).collect();
+        let captured = captured_text(&latin1, Encoding::custom::<Latin1>());
+        assert_eq!(captured, ["café", "x", "öö", "ÿ"]);
+    }
+}
@@ -0,0 +1,11 @@
+// Fields of `TSLanguage` which are not exposed by the tree-sitter API.
+
+#include "./language.h"
+
+uint32_t tree_house_language_symbol_count(const TSLanguage *self) {
+  return self->symbol_count;
+}
+
+uint32_t tree_house_language_token_count(const TSLanguage *self) {
+  return self->token_count;
+}
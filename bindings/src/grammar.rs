@@ -1,6 +1,9 @@
+use std::ffi::{c_char, CStr};
 use std::fmt;
+use std::num::NonZeroU16;
 use std::path::{Path, PathBuf};
 use std::ptr::NonNull;
+use std::slice;
 
 use libloading::{Library, Symbol};
 #[cfg(feature = "tree-sitter-language")]
@@ -45,18 +48,26 @@ impl Grammar {
                 path: library_path.to_owned(),
             })?
         };
-        let language_fn_name = format!("tree_sitter_{}", name.replace('-', "_"));
-        let language_fn: Symbol<unsafe extern "C" fn() -> NonNull<GrammarData>> = library
-            .get(language_fn_name.as_bytes())
-            .map_err(|err| Error::DlSym {
-                err,
-                symbol: name.to_owned(),
-            })?;
-        let grammar = Grammar::from_grammar_data(language_fn())?;
+        let grammar = Grammar::from_library(&library, &language_fn_name(name))?;
         std::mem::forget(library);
         Ok(grammar)
     }
 
+    /// Loads the grammar returned by the function `symbol` of `library`.
+    ///
+    /// # Safety
+    ///
+    /// `library` must be a valid tree sitter grammar and must stay loaded while
+    /// the grammar is used.
+    pub(crate) unsafe fn from_library(library: &Library, symbol: &str) -> Result<Grammar, Error> {
+        let language_fn: Symbol<unsafe extern "C" fn() -> NonNull<GrammarData>> =
+            library.get(symbol.as_bytes()).map_err(|err| Error::DlSym {
+                err,
+                symbol: symbol.to_owned(),
+            })?;
+        Grammar::from_grammar_data(language_fn())
+    }
+
     fn from_grammar_data(ptr: NonNull<GrammarData>) -> Result<Grammar, Error> {
         let grammar = Grammar { ptr };
         let version = grammar.abi_version();
@@ -75,6 +86,189 @@ impl Grammar {
         let symbol_type = unsafe { ts_language_symbol_type(self, kind_id) };
         symbol_type <= (SymbolType::Anonymous as u32)
     }
+
+    /// Get the number of distinct node kinds in this grammar.
+    pub fn node_kind_count(self) -> u32 {
+        unsafe { ts_language_symbol_count(self) }
+    }
+
+    /// Get the name of the node kind with the given numerical id.
+    pub fn node_kind_for_id(self, kind_id: u16) -> Option<&'static str> {
+        unsafe {
+            let ptr = ts_language_symbol_name(self, kind_id);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
+        }
+    }
+
+    /// Get the numerical id of the node kind with the given name.
+    ///
+    /// `named` selects between named nodes (rules in the grammar) and
+    /// anonymous nodes (string literals in the grammar), which may share the
+    /// same name.
+    pub fn id_for_node_kind(self, kind: &str, named: bool) -> Option<u16> {
+        let len = u32::try_from(kind.len()).ok()?;
+        let id = unsafe { ts_language_symbol_for_name(self, kind.as_ptr().cast(), len, named) };
+        (id != 0).then_some(id)
+    }
+
+    /// Check whether the given node kind belongs to named nodes, anonymous
+    /// nodes, supertypes or hidden (auxiliary) nodes.
+    pub fn symbol_type(self, kind_id: u16) -> SymbolType {
+        // the C API indexes the symbol metadata table without a bounds check
+        assert!(
+            u32::from(kind_id) < self.node_kind_count() || kind_id >= ERROR_REPEAT_SYMBOL,
+            "invalid node kind id {kind_id}"
+        );
+        match unsafe { ts_language_symbol_type(self, kind_id) } {
+            0 => SymbolType::Regular,
+            1 => SymbolType::Anonymous,
+            2 => SymbolType::Supertype,
+            _ => SymbolType::Auxiliary,
+        }
+    }
+
+    /// Check whether the given node kind is a terminal symbol (a token
+    /// produced by the lexer) rather than a rule of the grammar.
+    ///
+    /// Lookahead iterators yield both the tokens and the rules that are valid
+    /// in a parse state.
+    pub fn is_token(self, kind_id: u16) -> bool {
+        u32::from(kind_id) < unsafe { tree_house_language_token_count(self) }
+    }
+
+    /// Get the number of distinct field names in this grammar.
+    pub fn field_count(self) -> u32 {
+        unsafe { ts_language_field_count(self) }
+    }
+
+    /// Iterate over all fields defined by this grammar.
+    pub fn fields(self) -> impl ExactSizeIterator<Item = (FieldId, &'static str)> {
+        (1..=self.field_count() as u16).map(move |id| {
+            let field = FieldId::new(id).unwrap();
+            (field, self.field_name_for_id(field).unwrap_or_default())
+        })
+    }
+
+    /// Get the node kind ids of all supertypes in this grammar.
+    pub fn supertypes(self) -> &'static [u16] {
+        unsafe {
+            let mut len = 0u32;
+            let ptr = ts_language_supertypes(self, &mut len);
+            if len == 0 || ptr.is_null() {
+                &[]
+            } else {
+                slice::from_raw_parts(ptr, len as usize)
+            }
+        }
+    }
+
+    /// Get the node kind ids of all subtypes of the given supertype.
+    ///
+    /// See [`Grammar::supertypes`] for fetching all supertypes.
+    pub fn subtypes(self, supertype: u16) -> &'static [u16] {
+        // the C API indexes the supertype map without a bounds check
+        if u32::from(supertype) >= self.node_kind_count() {
+            return &[];
+        }
+        unsafe {
+            let mut len = 0u32;
+            let ptr = ts_language_subtypes(self, supertype, &mut len);
+            if len == 0 || ptr.is_null() {
+                &[]
+            } else {
+                slice::from_raw_parts(ptr, len as usize)
+            }
+        }
+    }
+
+    /// Get the number of valid parse states in this grammar.
+    pub fn state_count(self) -> u32 {
+        unsafe { ts_language_state_count(self) }
+    }
+
+    /// Get the parse state that follows `state` after consuming a node of the
+    /// given kind.
+    ///
+    /// Use [`Node::grammar_id`](crate::Node::grammar_id) rather than
+    /// [`Node::kind_id`](crate::Node::kind_id) for nodes which may be aliased.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `state` is not a parse state of this grammar or if `kind_id`
+    /// is not a node kind of this grammar. The ids of aliases are rejected as
+    /// well.
+    pub fn next_state(self, state: u16, kind_id: u16) -> u16 {
+        // the C API indexes the parse tables without a bounds check
+        assert!(
+            u32::from(state) < self.state_count(),
+            "invalid parse state {state}"
+        );
+        assert!(
+            u32::from(kind_id) < unsafe { tree_house_language_symbol_count(self) }
+                || kind_id >= ERROR_REPEAT_SYMBOL,
+            "invalid node kind id {kind_id}"
+        );
+        unsafe { ts_language_next_state(self, state, kind_id) }
+    }
+
+    /// Get the name this grammar was declared with.
+    ///
+    /// This returns `None` for grammars generated with ABI versions older than
+    /// 15.
+    pub fn name(self) -> Option<&'static str> {
+        unsafe {
+            let ptr = ts_language_name(self);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
+        }
+    }
+
+    /// Get the semantic version declared in the grammar's `tree-sitter.json`.
+    ///
+    /// This returns `None` for grammars generated with ABI versions older than
+    /// 15.
+    pub fn semantic_version(self) -> Option<SemanticVersion> {
+        unsafe { ts_language_metadata(self).map(|metadata| *metadata.as_ptr()) }
+    }
+
+    /// Get the numerical id for the field with the given name.
+    ///
+    /// Returns `None` if the grammar does not define a field with this name.
+    pub fn field_id_for_name(self, name: &str) -> Option<FieldId> {
+        let len = u32::try_from(name.len()).ok()?;
+        unsafe { ts_language_field_id_for_name(self, name.as_ptr().cast(), len) }
+    }
+
+    /// Get the name of the field with the given numerical id.
+    pub fn field_name_for_id(self, field_id: FieldId) -> Option<&'static str> {
+        unsafe {
+            let ptr = ts_language_field_name_for_id(self, field_id);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
+        }
+    }
+}
+
+/// The name of the function which returns the grammar with name `name`.
+pub(crate) fn language_fn_name(name: &str) -> String {
+    format!("tree_sitter_{}", name.replace('-', "_"))
+}
+
+/// A numerical id identifying a field name in a [`Grammar`].
+///
+/// Tree-sitter reserves the id zero to mean "no field" so the id is stored as a
+/// `NonZeroU16`. This allows `Option<FieldId>` to be passed to and from the C
+/// library directly.
+#[repr(transparent)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct FieldId(NonZeroU16);
+
+impl FieldId {
+    pub fn new(id: u16) -> Option<FieldId> {
+        NonZeroU16::new(id).map(FieldId)
+    }
+
+    pub fn get(self) -> u16 {
+        self.0.get()
+    }
 }
 
 #[cfg(feature = "tree-sitter-language")]
@@ -123,15 +317,39 @@ impl fmt::Display for IncompatibleGrammarError {
 }
 impl std::error::Error for IncompatibleGrammarError {}
 
+/// The symbol of `ERROR` nodes' repetition helper. Symbol ids at or above this
+/// value are builtin symbols which are valid for all grammars.
+const ERROR_REPEAT_SYMBOL: u16 = u16::MAX - 1;
+
+/// The kind of a symbol (node kind) in a [`Grammar`].
 #[repr(u32)]
-#[allow(dead_code)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum SymbolType {
+    /// A named node, corresponding to a named rule in the grammar.
     Regular,
+    /// An anonymous node, corresponding to a string literal in the grammar.
     Anonymous,
+    /// A supertype, which is a hidden rule that groups a set of subtypes.
     Supertype,
+    /// A hidden node which is never returned from the API.
     Auxiliary,
 }
 
+/// The semantic version of a grammar as declared in its `tree-sitter.json`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SemanticVersion {
+    pub major: u8,
+    pub minor: u8,
+    pub patch: u8,
+}
+
+impl fmt::Display for SemanticVersion {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{}.{}", self.major, self.minor, self.patch)
+    }
+}
+
 extern "C" {
     /// Get the ABI version number for this language. This version number
     /// is used to ensure that languages were generated by a compatible version of
@@ -143,4 +361,120 @@ extern "C" {
     ///
     /// See also `ts_node_is_named`. Hidden nodes are never returned from the API.
     pub fn ts_language_symbol_type(grammar: Grammar, symbol: u16) -> u32;
+
+    /// Get the number of distinct node types in the language.
+    fn ts_language_symbol_count(grammar: Grammar) -> u32;
+
+    /// Get the number of valid states in this language.
+    fn ts_language_state_count(grammar: Grammar) -> u32;
+
+    /// Get the numerical id for the given node type string.
+    fn ts_language_symbol_for_name(
+        grammar: Grammar,
+        string: *const c_char,
+        length: u32,
+        is_named: bool,
+    ) -> u16;
+
+    /// Get a node type string for the given numerical id.
+    fn ts_language_symbol_name(grammar: Grammar, symbol: u16) -> *const c_char;
+
+    /// Get the number of distinct field names in the language.
+    fn ts_language_field_count(grammar: Grammar) -> u32;
+
+    /// Get a list of all supertype symbols for the language.
+    fn ts_language_supertypes(grammar: Grammar, length: &mut u32) -> *const u16;
+
+    /// Get a list of all subtype symbol ids for a given supertype symbol.
+    fn ts_language_subtypes(grammar: Grammar, supertype: u16, length: &mut u32) -> *const u16;
+
+    /// Get the next parse state. Combine this with lookahead iterators to generate
+    /// completion suggestions or valid symbols in error nodes.
+    fn ts_language_next_state(grammar: Grammar, state: u16, symbol: u16) -> u16;
+
+    /// Get the name of this language. This returns `NULL` in older parsers.
+    fn ts_language_name(grammar: Grammar) -> *const c_char;
+
+    /// Get the metadata for this language. This returns `NULL` in older parsers.
+    fn ts_language_metadata(grammar: Grammar) -> Option<NonNull<SemanticVersion>>;
+
+    /// Get the field name string for the given numerical id.
+    fn ts_language_field_name_for_id(grammar: Grammar, id: FieldId) -> *const c_char;
+
+    /// Get the numerical id for the given field name string.
+    fn ts_language_field_id_for_name(
+        grammar: Grammar,
+        name: *const c_char,
+        name_length: u32,
+    ) -> Option<FieldId>;
+}
+
+// defined in grammar.c
+extern "C" {
+    /// Get the number of symbols in the language, excluding aliases.
+    fn tree_house_language_symbol_count(grammar: Grammar) -> u32;
+
+    /// Get the number of terminal symbols in the language.
+    fn tree_house_language_token_count(grammar: Grammar) -> u32;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SymbolType;
+    use crate::test_grammars::python_grammar;
+
+    #[test]
+    fn node_kinds() {
+        let grammar = python_grammar();
+        let id = grammar
+            .id_for_node_kind("function_definition", true)
+            .unwrap();
+        assert_eq!(grammar.node_kind_for_id(id), Some("function_definition"));
+        assert_eq!(grammar.symbol_type(id), SymbolType::Regular);
+        assert!(u32::from(id) < grammar.node_kind_count());
+
+        let def = grammar.id_for_node_kind("def", false).unwrap();
+        assert_eq!(grammar.symbol_type(def), SymbolType::Anonymous);
+        assert!(grammar.is_token(def));
+        assert!(grammar.is_token(grammar.id_for_node_kind("identifier", true).unwrap()));
+        assert!(!grammar.is_token(id));
+        assert!(grammar.id_for_node_kind("def", true).is_none());
+        assert!(grammar.id_for_node_kind("not_a_node_kind", true).is_none());
+    }
+
+    #[test]
+    fn fields_and_supertypes() {
+        let grammar = python_grammar();
+        let fields: Vec<_> = grammar.fields().map(|(_, name)| name).collect();
+        assert_eq!(fields.len() as u32, grammar.field_count());
+        assert!(fields.contains(&"body"));
+
+        let expression = grammar.id_for_node_kind("expression", true).unwrap();
+        assert!(grammar.supertypes().contains(&expression));
+        assert_eq!(grammar.symbol_type(expression), SymbolType::Supertype);
+        let subtypes: Vec<_> = grammar
+            .subtypes(expression)
+            .iter()
+            .filter_map(|&id| grammar.node_kind_for_id(id))
+            .collect();
+        assert!(subtypes.contains(&"primary_expression"), "{subtypes:?}");
+        assert!(grammar.subtypes(u16::MAX - 2).is_empty());
+        assert!(grammar.state_count() > 0);
+    }
+
+    #[test]
+    fn next_state() {
+        let grammar = python_grammar();
+        let def = grammar.id_for_node_kind("def", false).unwrap();
+        assert_ne!(grammar.next_state(1, def), 0);
+        // errors never lead to a valid state
+        assert_eq!(grammar.next_state(1, u16::MAX), 0);
+    }
+
+    #[test]
+    #[should_panic = "invalid parse state"]
+    fn next_state_invalid_state() {
+        let grammar = python_grammar();
+        grammar.next_state(grammar.state_count() as u16, 1);
+    }
 }
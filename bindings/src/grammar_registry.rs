@@ -0,0 +1,523 @@
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::Hasher;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, OnceLock, PoisonError, RwLock, Weak};
+use std::{fs, io, mem};
+
+use libloading::Library;
+
+use crate::grammar::{self, language_fn_name};
+use crate::Grammar;
+
+/// The grammars loaded by all registries, used to find the library of the
+/// grammar of a tree, query, parser or lookahead iterator. This is only initialized once a
+/// registry loads a grammar.
+static LOADED_GRAMMARS: OnceLock<RwLock<HashMap<Grammar, Weak<LoadedGrammar>>>> = OnceLock::new();
+
+#[derive(Debug)]
+struct LoadedGrammar {
+    _library: Arc<Library>,
+    /// The number of [`GrammarRef`]s. The `Arc` itself is also owned by every
+    /// registry which loaded the grammar so its count can't be used instead.
+    users: AtomicUsize,
+}
+
+/// Keeps the library of a grammar loaded by a [`GrammarRegistry`] alive.
+///
+/// This is held by trees, queries, parsers and lookahead iterators so that the
+/// registry can tell whether a grammar is still in use. It's empty for grammars
+/// which were not loaded by a registry.
+#[derive(Debug, Default)]
+pub(crate) struct GrammarRef(Option<Arc<LoadedGrammar>>);
+
+impl GrammarRef {
+    pub(crate) fn new(grammar: Grammar) -> GrammarRef {
+        let Some(loaded) = LOADED_GRAMMARS.get() else {
+            return GrammarRef(None);
+        };
+        let loaded = loaded.read().unwrap_or_else(PoisonError::into_inner);
+        GrammarRef::from_loaded(loaded.get(&grammar).and_then(Weak::upgrade))
+    }
+
+    fn from_loaded(loaded: Option<Arc<LoadedGrammar>>) -> GrammarRef {
+        if let Some(loaded) = &loaded {
+            loaded.users.fetch_add(1, Ordering::Relaxed);
+        }
+        GrammarRef(loaded)
+    }
+
+    pub(crate) fn is_loaded(&self) -> bool {
+        self.0.is_some()
+    }
+}
+
+impl Clone for GrammarRef {
+    fn clone(&self) -> GrammarRef {
+        GrammarRef::from_loaded(self.0.clone())
+    }
+}
+
+impl Drop for GrammarRef {
+    fn drop(&mut self) {
+        if let Some(loaded) = &self.0 {
+            loaded.users.fetch_sub(1, Ordering::Release);
+        }
+    }
+}
+
+/// Where a grammar in a [`GrammarRegistry`] was loaded from.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GrammarInfo {
+    path: PathBuf,
+    symbol: String,
+    checksum: u64,
+}
+
+impl GrammarInfo {
+    /// The canonicalized path of the shared library.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The name of the function in the shared library which returned the
+    /// grammar, for example `tree_sitter_rust`.
+    pub fn symbol(&self) -> &str {
+        &self.symbol
+    }
+
+    /// A checksum of the contents of the shared library at the time it was
+    /// loaded.
+    pub fn checksum(&self) -> u64 {
+        self.checksum
+    }
+}
+
+struct Entry {
+    grammar: Grammar,
+    info: GrammarInfo,
+    loaded: Arc<LoadedGrammar>,
+}
+
+impl Entry {
+    /// The number of trees, queries, parsers and lookahead iterators using the
+    /// grammar.
+    fn references(&self) -> usize {
+        self.loaded.users.load(Ordering::Acquire)
+    }
+}
+
+/// A set of grammars loaded from shared libraries.
+///
+/// Unlike [`Grammar::new`], which never unloads the shared library of a
+/// grammar, the registry owns the libraries it loads. A grammar can be unloaded
+/// with [`GrammarRegistry::unload`] or replaced by a rebuilt version of its
+/// library with [`GrammarRegistry::reload`]. Trees, queries, parsers and
+/// lookahead iterators keep track of the grammar they use so that a grammar is
+/// never unloaded while it is in use.
+///
+/// Loading the same grammar twice, from the same path or from a copy of its
+/// library, returns the same [`Grammar`]. Libraries are identified by a
+/// checksum of their contents and are only opened once.
+///
+/// Dropping the registry does not unload any grammars.
+#[derive(Default)]
+pub struct GrammarRegistry {
+    entries: Vec<Entry>,
+    /// Replaced versions of reloaded grammars which were still in use. They
+    /// are unloaded once they are unused.
+    retired: Vec<Entry>,
+}
+
+impl GrammarRegistry {
+    pub fn new() -> GrammarRegistry {
+        GrammarRegistry::default()
+    }
+
+    /// Loads the grammar with name `name` from the shared library at `path`.
+    ///
+    /// If the grammar was already loaded from the same file, and the file did
+    /// not change since, the existing grammar is returned.
+    ///
+    /// # Safety
+    ///
+    /// `path` must be a valid tree sitter grammar
+    pub unsafe fn load(&mut self, name: &str, path: &Path) -> Result<Grammar, RegistryError> {
+        let (path, checksum) = read_library(path)?;
+        let symbol = language_fn_name(name);
+        if let Some(entry) = self
+            .entries
+            .iter()
+            .find(|entry| entry.info.checksum == checksum && entry.info.symbol == symbol)
+        {
+            return Ok(entry.grammar);
+        }
+        let info = GrammarInfo {
+            path,
+            symbol,
+            checksum,
+        };
+        let entry = self.open(info)?;
+        let grammar = entry.grammar;
+        if self.entry(grammar).is_err() {
+            self.entries.push(entry);
+        }
+        Ok(grammar)
+    }
+
+    unsafe fn open(&self, info: GrammarInfo) -> Result<Entry, RegistryError> {
+        let mut loaded_entries = self.entries.iter().chain(&self.retired);
+        let library = match loaded_entries
+            .clone()
+            .find(|entry| entry.info.checksum == info.checksum)
+        {
+            Some(entry) => entry.loaded._library.clone(),
+            // The dynamic loader returns the library which is already loaded
+            // when the same path is opened again, so a rebuilt library is
+            // opened from a copy instead.
+            None if loaded_entries.any(|entry| entry.info.path == info.path) => {
+                Arc::new(open_copy(&info)?)
+            }
+            None => Arc::new(
+                Library::new(&info.path).map_err(|err| grammar::Error::DlOpen {
+                    err,
+                    path: info.path.clone(),
+                })?,
+            ),
+        };
+        let grammar = Grammar::from_library(&library, &info.symbol)?;
+
+        let mut loaded = LOADED_GRAMMARS
+            .get_or_init(Default::default)
+            .write()
+            .unwrap_or_else(PoisonError::into_inner);
+        loaded.retain(|_, loaded| loaded.strong_count() > 0);
+        // The library may already be loaded by another registry, in which case
+        // the grammar is the same as well.
+        let loaded_grammar = match loaded.get(&grammar).and_then(Weak::upgrade) {
+            Some(loaded_grammar) => loaded_grammar,
+            None => {
+                let loaded_grammar = Arc::new(LoadedGrammar {
+                    _library: library,
+                    users: AtomicUsize::new(0),
+                });
+                loaded.insert(grammar, Arc::downgrade(&loaded_grammar));
+                loaded_grammar
+            }
+        };
+        Ok(Entry {
+            grammar,
+            info,
+            loaded: loaded_grammar,
+        })
+    }
+
+    fn entry(&self, grammar: Grammar) -> Result<usize, RegistryError> {
+        self.entries
+            .iter()
+            .position(|entry| entry.grammar == grammar)
+            .ok_or(RegistryError::UnknownGrammar)
+    }
+
+    /// Where the grammar was loaded from, or `None` if the grammar was not
+    /// loaded by this registry.
+    pub fn info(&self, grammar: Grammar) -> Option<&GrammarInfo> {
+        let i = self.entry(grammar).ok()?;
+        Some(&self.entries[i].info)
+    }
+
+    /// Iterates over all loaded grammars.
+    pub fn grammars(&self) -> impl ExactSizeIterator<Item = (Grammar, &GrammarInfo)> {
+        self.entries
+            .iter()
+            .map(|entry| (entry.grammar, &entry.info))
+    }
+
+    /// The number of trees, queries, parsers and lookahead iterators which
+    /// currently use the grammar. Users of the grammar are counted across all
+    /// registries which loaded it.
+    pub fn references(&self, grammar: Grammar) -> Result<usize, RegistryError> {
+        let i = self.entry(grammar)?;
+        Ok(self.entries[i].references())
+    }
+
+    /// Checks whether the library of the grammar changed since it was loaded.
+    pub fn is_outdated(&self, grammar: Grammar) -> Result<bool, RegistryError> {
+        let entry = &self.entries[self.entry(grammar)?];
+        let (_, checksum) = read_library(&entry.info.path)?;
+        Ok(checksum != entry.info.checksum)
+    }
+
+    /// Loads the grammar again from its library, for example after the grammar
+    /// was rebuilt.
+    ///
+    /// If the library did not change the same grammar is returned. Otherwise
+    /// the new grammar replaces the old grammar in the registry. The library of
+    /// the old grammar stays loaded while trees, queries or parsers use it and
+    /// is unloaded by a later call to [`reload`](Self::reload) or
+    /// [`unload`](Self::unload) once they are dropped.
+    ///
+    /// # Safety
+    ///
+    /// The library must still be a valid tree sitter grammar and the old
+    /// grammar, or any strings borrowed from it, must not be used after the
+    /// trees, queries and parsers using it are dropped.
+    pub unsafe fn reload(&mut self, grammar: Grammar) -> Result<Grammar, RegistryError> {
+        let i = self.entry(grammar)?;
+        let (path, checksum) = read_library(&self.entries[i].info.path)?;
+        if checksum == self.entries[i].info.checksum {
+            return Ok(grammar);
+        }
+        let info = GrammarInfo {
+            path,
+            symbol: self.entries[i].info.symbol.clone(),
+            checksum,
+        };
+        let entry = self.open(info)?;
+        let new_grammar = entry.grammar;
+        let old = mem::replace(&mut self.entries[i], entry);
+        if old.grammar != new_grammar {
+            self.retired.push(old);
+        }
+        self.retired.retain(|entry| entry.references() > 0);
+        Ok(new_grammar)
+    }
+
+    /// Unloads the grammar and closes its library if no other grammar uses it.
+    ///
+    /// Fails with [`RegistryError::InUse`] if the grammar is still used by a
+    /// tree, query, parser or lookahead iterator.
+    ///
+    /// # Safety
+    ///
+    /// The grammar, or any strings borrowed from it, must not be used after it
+    /// is unloaded.
+    pub unsafe fn unload(&mut self, grammar: Grammar) -> Result<(), RegistryError> {
+        let i = self.entry(grammar)?;
+        let references = self.entries[i].references();
+        if references != 0 {
+            let info = &self.entries[i].info;
+            return Err(RegistryError::InUse {
+                path: info.path.clone(),
+                symbol: info.symbol.clone(),
+                references,
+            });
+        }
+        self.entries.swap_remove(i);
+        self.retired.retain(|entry| entry.references() > 0);
+        Ok(())
+    }
+}
+
+impl Drop for GrammarRegistry {
+    fn drop(&mut self) {
+        // Grammars are `Copy` so they may still be used anywhere. Only the
+        // unsafe `unload` and `reload` functions close libraries.
+        for entry in self.entries.drain(..).chain(self.retired.drain(..)) {
+            mem::forget(entry.loaded);
+        }
+    }
+}
+
+/// Opens a copy of the library so that it's loaded even if a different version
+/// of the library was already loaded from the same path.
+unsafe fn open_copy(info: &GrammarInfo) -> Result<Library, RegistryError> {
+    let file_name = info.path.file_name().unwrap_or_default().to_string_lossy();
+    let copy = std::env::temp_dir().join(format!(
+        "{}-{:016x}-{file_name}",
+        std::process::id(),
+        info.checksum
+    ));
+    fs::copy(&info.path, &copy).map_err(|err| RegistryError::Read {
+        err,
+        path: info.path.clone(),
+    })?;
+    let library = Library::new(&copy).map_err(|err| grammar::Error::DlOpen {
+        err,
+        path: info.path.clone(),
+    });
+    // the library stays mapped on unix, elsewhere removing it fails which is fine
+    let _ = fs::remove_file(&copy);
+    Ok(library?)
+}
+
+fn read_library(path: &Path) -> Result<(PathBuf, u64), RegistryError> {
+    let read_err = |err| RegistryError::Read {
+        err,
+        path: path.to_owned(),
+    };
+    let path = path.canonicalize().map_err(read_err)?;
+    let contents = fs::read(&path).map_err(read_err)?;
+    let mut hasher = DefaultHasher::new();
+    hasher.write(&contents);
+    Ok((path, hasher.finish()))
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RegistryError {
+    #[error("Error reading grammar library {path:?}: {err}")]
+    Read {
+        #[source]
+        err: io::Error,
+        path: PathBuf,
+    },
+    #[error(transparent)]
+    Load(#[from] grammar::Error),
+    #[error("The grammar was not loaded by this registry")]
+    UnknownGrammar,
+    #[error(
+        "Grammar {symbol} from {path:?} is still used by {references} trees, queries or parsers"
+    )]
+    InUse {
+        path: PathBuf,
+        symbol: String,
+        references: usize,
+    },
+}
+
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::io::Write;
+    use std::path::PathBuf;
+
+    use super::{GrammarRegistry, RegistryError};
+    use crate::test_grammars::python_library;
+    use crate::{LookaheadIterator, Parser, Query};
+
+    /// Copies the python grammar to a temporary directory. The other tests load
+    /// the grammar from the test-grammars directory with `Grammar::new` and the
+    /// dynamic loader would return the same library for the same file.
+    fn python_copy(test: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("tree-house-registry-{test}-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("python.so");
+        fs::copy(python_library(), &path).unwrap();
+        path
+    }
+
+    fn parse(parser: &mut Parser, src: &str) -> crate::Tree {
+        parser.parse(src, None).unwrap()
+    }
+
+    #[test]
+    fn load_and_unload() {
+        let path = python_copy("load");
+        let mut registry = GrammarRegistry::new();
+        let grammar = unsafe { registry.load("python", &path) }.unwrap();
+        assert_eq!(unsafe { registry.load("python", &path) }.unwrap(), grammar);
+        // libraries are deduplicated by their checksum
+        assert_eq!(
+            unsafe { registry.load("python", python_library()) }.unwrap(),
+            grammar
+        );
+        assert_eq!(registry.grammars().len(), 1);
+        let info = registry.info(grammar).unwrap();
+        assert_eq!(info.symbol(), "tree_sitter_python");
+        assert_eq!(info.path(), path.canonicalize().unwrap());
+
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parse(&mut parser, "x = 1");
+        let query = Query::new(grammar, "(identifier) @id", |_, _| Ok(())).unwrap();
+        assert_eq!(registry.references(grammar).unwrap(), 3);
+        drop(parser);
+        let copy = tree.clone();
+        drop(tree);
+        assert_eq!(registry.references(grammar).unwrap(), 2);
+
+        let err = unsafe { registry.unload(grammar) }.unwrap_err();
+        assert!(matches!(err, RegistryError::InUse { references: 2, .. }));
+        drop((copy, query));
+        assert!(!registry.is_outdated(grammar).unwrap());
+        unsafe { registry.unload(grammar) }.unwrap();
+        assert!(registry.info(grammar).is_none());
+        assert!(matches!(
+            unsafe { registry.unload(grammar) },
+            Err(RegistryError::UnknownGrammar)
+        ));
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn multiple_registries() {
+        let path = python_copy("multiple");
+        let mut first = GrammarRegistry::new();
+        let mut second = GrammarRegistry::new();
+        let grammar = unsafe { first.load("python", &path) }.unwrap();
+        assert_eq!(unsafe { second.load("python", &path) }.unwrap(), grammar);
+        assert_eq!(first.references(grammar).unwrap(), 0);
+
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parse(&mut parser, "x = 1");
+        let lookahead = LookaheadIterator::new(grammar, 1).unwrap();
+        drop(parser);
+        // users are shared by the registries
+        assert_eq!(first.references(grammar).unwrap(), 2);
+        assert_eq!(second.references(grammar).unwrap(), 2);
+        assert!(matches!(
+            unsafe { first.unload(grammar) },
+            Err(RegistryError::InUse { references: 2, .. })
+        ));
+        drop((tree, lookahead));
+        unsafe { first.unload(grammar) }.unwrap();
+        // the library is still loaded by the second registry
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        assert_eq!(parse(&mut parser, "x = 1").root_node().kind(), "module");
+        drop(parser);
+
+        // dropping a registry leaks its grammars but doesn't keep them in use
+        drop(second);
+        let mut third = GrammarRegistry::new();
+        assert_eq!(unsafe { third.load("python", &path) }.unwrap(), grammar);
+        assert_eq!(third.references(grammar).unwrap(), 0);
+        unsafe { third.unload(grammar) }.unwrap();
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn reload() {
+        let path = python_copy("reload");
+        let dir = path.parent().unwrap();
+        let mut registry = GrammarRegistry::new();
+        let old = unsafe { registry.load("python", &path) }.unwrap();
+        assert_eq!(unsafe { registry.reload(old) }.unwrap(), old);
+        let mut parser = Parser::new();
+        parser.set_grammar(old).unwrap();
+        let tree = parse(&mut parser, "x = 1");
+        drop(parser);
+
+        // "rebuild" the grammar by replacing the library with a different file
+        let rebuilt = dir.join("python.so.tmp");
+        fs::copy(python_library(), &rebuilt).unwrap();
+        fs::OpenOptions::new()
+            .append(true)
+            .open(&rebuilt)
+            .unwrap()
+            .write_all(b"rebuilt")
+            .unwrap();
+        fs::rename(&rebuilt, &path).unwrap();
+        assert!(registry.is_outdated(old).unwrap());
+
+        let new = unsafe { registry.reload(old) }.unwrap();
+        assert_ne!(new, old);
+        assert!(registry.info(old).is_none());
+        assert_eq!(registry.grammars().len(), 1);
+        assert!(!registry.is_outdated(new).unwrap());
+        // the old grammar stays loaded while the tree uses it
+        assert_eq!(tree.root_node().kind(), "module");
+
+        let mut parser = Parser::new();
+        parser.set_grammar(new).unwrap();
+        let new_tree = parse(&mut parser, "x = 1");
+        assert_eq!(new_tree.root_node().to_sexp(), tree.root_node().to_sexp());
+        drop((tree, new_tree, parser));
+        unsafe { registry.unload(new) }.unwrap();
+        assert!(registry.retired.is_empty());
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,358 @@
+use std::borrow::Cow;
+use std::ops;
+
+use regex_cursor::Cursor;
+
+use crate::{Encoding, Input, IntoInput};
+
+/// An [`Input`] for source text that is stored in a single contiguous buffer
+/// like a `&str`, `String`, `&[u8]`, `Vec<u8>` or a memory mapped file.
+pub struct SliceInput<T> {
+    cursor: SliceCursor<T>,
+}
+
+impl<T: AsRef<[u8]>> SliceInput<T> {
+    pub fn new(src: T) -> Self {
+        SliceInput {
+            cursor: SliceCursor(src),
+        }
+    }
+}
+
+impl<T: AsRef<[u8]>> Input for SliceInput<T> {
+    type Cursor = SliceCursor<T>;
+
+    fn cursor_at(&mut self, offset: u32) -> &mut SliceCursor<T> {
+        debug_assert!(
+            offset as usize <= self.cursor.chunk().len(),
+            "parser offset out of bounds: {offset} > {}",
+            self.cursor.chunk().len()
+        );
+        &mut self.cursor
+    }
+
+    fn eq(&mut self, range1: ops::Range<u32>, range2: ops::Range<u32>) -> bool {
+        let src = self.cursor.chunk();
+        src[range1.start as usize..range1.end as usize]
+            == src[range2.start as usize..range2.end as usize]
+    }
+}
+
+/// The cursor of a [`SliceInput`]: a single chunk spanning the whole text.
+pub struct SliceCursor<T>(T);
+
+impl<T: AsRef<[u8]>> Cursor for SliceCursor<T> {
+    fn chunk(&self) -> &[u8] {
+        self.0.as_ref()
+    }
+
+    fn advance(&mut self) -> bool {
+        false
+    }
+
+    fn backtrack(&mut self) -> bool {
+        false
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        Some(self.chunk().len())
+    }
+
+    fn offset(&self) -> usize {
+        0
+    }
+}
+
+impl<'a> IntoInput for &'a str {
+    type Input = SliceInput<&'a str>;
+
+    fn into_input(self) -> Self::Input {
+        SliceInput::new(self)
+    }
+}
+
+impl<'a> IntoInput for &'a String {
+    type Input = SliceInput<&'a str>;
+
+    fn into_input(self) -> Self::Input {
+        SliceInput::new(self.as_str())
+    }
+}
+
+impl IntoInput for String {
+    type Input = SliceInput<String>;
+
+    fn into_input(self) -> Self::Input {
+        SliceInput::new(self)
+    }
+}
+
+impl<'a> IntoInput for &'a [u8] {
+    type Input = SliceInput<&'a [u8]>;
+
+    fn into_input(self) -> Self::Input {
+        SliceInput::new(self)
+    }
+}
+
+impl IntoInput for Vec<u8> {
+    type Input = SliceInput<Vec<u8>>;
+
+    fn into_input(self) -> Self::Input {
+        SliceInput::new(self)
+    }
+}
+
+/// An [`Input`] for source text that is stored in multiple discontiguous
+/// chunks, for example a piece table or a list of lines.
+///
+/// The chunks are collected once when the input is created, looking up the
+/// chunk for an offset is a binary search. The tree-sitter lexer can't decode
+/// codepoints that are split across chunks so for UTF-8 text the bytes of a
+/// codepoint that is split by a chunk boundary are copied to the end of the
+/// previous chunk.
+pub struct ChunkedInput<'a> {
+    cursor: ChunkedCursor<'a>,
+    encoding: Encoding,
+}
+
+impl<'a> ChunkedInput<'a> {
+    /// Create an input for UTF-8 encoded chunks.
+    pub fn new(chunks: impl IntoIterator<Item = &'a [u8]>) -> Self {
+        Self::with_encoding(chunks, Encoding::Utf8)
+    }
+
+    /// Create an input for chunks of text in the given encoding.
+    ///
+    /// Codepoints split across chunks are only repaired for UTF-8. The chunks
+    /// of text in other encodings must not split a codepoint.
+    pub fn with_encoding(chunks: impl IntoIterator<Item = &'a [u8]>, encoding: Encoding) -> Self {
+        let utf8 = matches!(encoding, Encoding::Utf8);
+        let mut joined: Vec<Cow<'a, [u8]>> = Vec::new();
+        for mut chunk in chunks {
+            // A chunk boundary splits a codepoint if the next chunk starts
+            // with a continuation byte.
+            let continuation_bytes = chunk
+                .iter()
+                .take(3)
+                .take_while(|&&byte| utf8 && (byte as i8) < -0x40)
+                .count();
+            if let Some(prev) = joined.last_mut().filter(|_| continuation_bytes != 0) {
+                prev.to_mut()
+                    .extend_from_slice(&chunk[..continuation_bytes]);
+                chunk = &chunk[continuation_bytes..];
+            }
+            // cursors must never yield an empty chunk unless the text is empty
+            if !chunk.is_empty() {
+                joined.push(Cow::Borrowed(chunk));
+            }
+        }
+        let mut len = 0;
+        let offsets = joined
+            .iter()
+            .map(|chunk| {
+                let offset = len;
+                len += chunk.len();
+                offset
+            })
+            .collect();
+        ChunkedInput {
+            cursor: ChunkedCursor {
+                chunks: joined.into_boxed_slice(),
+                offsets,
+                idx: 0,
+                len,
+            },
+            encoding,
+        }
+    }
+
+    /// Iterate over the bytes within the given range.
+    fn bytes(&self, range: ops::Range<usize>) -> impl Iterator<Item = u8> + '_ {
+        let cursor = &self.cursor;
+        let start_chunk = cursor.chunk_idx(range.start);
+        cursor.chunks[start_chunk..]
+            .iter()
+            .zip(&cursor.offsets[start_chunk..])
+            .flat_map(move |(chunk, &offset)| {
+                let start = range.start.saturating_sub(offset).min(chunk.len());
+                let end = range.end.saturating_sub(offset).min(chunk.len());
+                &chunk[start..end]
+            })
+            .copied()
+    }
+}
+
+impl<'a> Input for ChunkedInput<'a> {
+    type Cursor = ChunkedCursor<'a>;
+
+    fn cursor_at(&mut self, offset: u32) -> &mut ChunkedCursor<'a> {
+        let offset = offset as usize;
+        debug_assert!(
+            offset <= self.cursor.len,
+            "parser offset out of bounds: {offset} > {}",
+            self.cursor.len
+        );
+        // reads during parsing are mostly sequential, avoid the binary search
+        // if the offset is in the current or next chunk
+        let cursor = &mut self.cursor;
+        if !cursor.contains(offset) {
+            if cursor.idx + 1 < cursor.chunks.len() && cursor.offsets[cursor.idx + 1] <= offset {
+                cursor.idx += 1;
+            }
+            if !cursor.contains(offset) {
+                cursor.idx = cursor.chunk_idx(offset);
+            }
+        }
+        cursor
+    }
+
+    fn eq(&mut self, range1: ops::Range<u32>, range2: ops::Range<u32>) -> bool {
+        let range1 = range1.start as usize..range1.end as usize;
+        let range2 = range2.start as usize..range2.end as usize;
+        range1.len() == range2.len() && self.bytes(range1).eq(self.bytes(range2))
+    }
+
+    fn encoding(&self) -> Encoding {
+        self.encoding
+    }
+}
+
+/// The cursor of a [`ChunkedInput`].
+pub struct ChunkedCursor<'a> {
+    chunks: Box<[Cow<'a, [u8]>]>,
+    /// The byte offset of each chunk.
+    offsets: Box<[usize]>,
+    idx: usize,
+    len: usize,
+}
+
+impl ChunkedCursor<'_> {
+    fn contains(&self, offset: usize) -> bool {
+        match self.chunks.get(self.idx) {
+            Some(chunk) => {
+                let start = self.offsets[self.idx];
+                start <= offset && offset < start + chunk.len()
+            }
+            None => false,
+        }
+    }
+
+    /// Find the chunk containing `offset`. Offsets past the end of the text
+    /// map to the last chunk.
+    fn chunk_idx(&self, offset: usize) -> usize {
+        self.offsets
+            .partition_point(|&start| start <= offset)
+            .saturating_sub(1)
+    }
+}
+
+impl Cursor for ChunkedCursor<'_> {
+    fn chunk(&self) -> &[u8] {
+        self.chunks.get(self.idx).map_or(&[], |chunk| chunk)
+    }
+
+    fn advance(&mut self) -> bool {
+        if self.idx + 1 < self.chunks.len() {
+            self.idx += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn backtrack(&mut self) -> bool {
+        if self.idx > 0 {
+            self.idx -= 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        Some(self.len)
+    }
+
+    fn offset(&self) -> usize {
+        self.offsets.get(self.idx).copied().unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChunkedInput;
+    use crate::test_grammars::python_grammar;
+    use crate::{Encoding, InactiveQueryCursor, IntoInput, Parser, Query};
+
+    const SRC: &str = "näme = öö\nx = x\ny = z\n";
+
+    /// Parse and query `SRC` with a fresh input from `input` for each step,
+    /// returning the text of all captured nodes.
+    fn captured_text<I: IntoInput>(input: impl Fn() -> I) -> Vec<&'static str> {
+        let grammar = python_grammar();
+        let query = Query::new(
+            grammar,
+            r#"((identifier) @id (#eq? @id "näme"))
+               ((identifier) @id (#match? @id "^ö+$"))
+               ((identifier) @a (identifier) @b (#eq? @a @b))"#,
+            |_, _| Ok(()),
+        )
+        .unwrap();
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(input(), None).unwrap();
+        let root = tree.root_node();
+        assert_eq!(root.end_byte() as usize, SRC.len());
+        assert_eq!(root.named_child_count(), 3);
+
+        let mut cursor = InactiveQueryCursor::default().execute_query(&query, &root, input());
+        let mut captured = Vec::new();
+        while let Some(mat) = cursor.next_match() {
+            for matched in mat.matched_nodes() {
+                let range = matched.node.byte_range();
+                captured.push(&SRC[range.start as usize..range.end as usize]);
+            }
+        }
+        captured.sort_unstable();
+        captured
+    }
+
+    const EXPECTED: [&str; 4] = ["näme", "x", "x", "öö"];
+
+    #[test]
+    fn slice_inputs() {
+        assert_eq!(captured_text(|| SRC), EXPECTED);
+        assert_eq!(captured_text(|| SRC.to_owned()), EXPECTED);
+        assert_eq!(captured_text(|| SRC.as_bytes()), EXPECTED);
+        assert_eq!(captured_text(|| SRC.as_bytes().to_vec()), EXPECTED);
+    }
+
+    #[test]
+    fn chunked_input() {
+        // chunk boundaries within tokens and codepoints
+        for chunk_size in [1, 2, 3, 5] {
+            let captured = captured_text(|| ChunkedInput::new(SRC.as_bytes().chunks(chunk_size)));
+            assert_eq!(captured, EXPECTED, "chunk size {chunk_size}");
+        }
+        let captured =
+            captured_text(|| ChunkedInput::new(SRC.split_inclusive('\n').map(str::as_bytes)));
+        assert_eq!(captured, EXPECTED);
+    }
+
+    #[test]
+    fn chunked_utf16_input() {
+        // the low byte of `ʀ` looks like a UTF-8 continuation byte
+        let src = "xʀ = 1\n";
+        let utf16: Vec<u8> = src.encode_utf16().flat_map(u16::to_le_bytes).collect();
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        let input = ChunkedInput::with_encoding(utf16.chunks(2), Encoding::Utf16LE);
+        let tree = parser.parse(input, None).unwrap();
+        let root = tree.root_node();
+        assert!(!root.has_error());
+        let identifier = root.descendant_for_byte_range(0, 0).unwrap();
+        assert_eq!(identifier.kind(), "identifier");
+        assert_eq!(identifier.byte_range(), 0..4);
+    }
+}
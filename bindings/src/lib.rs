@@ -1,8 +1,15 @@
+mod encoding;
 mod grammar;
+mod grammar_registry;
+mod input;
+mod lookahead_iterator;
 mod node;
 mod parser;
 pub mod query;
 mod query_cursor;
+mod sexp;
+#[cfg(test)]
+mod test_grammars;
 mod tree;
 mod tree_cursor;
 
@@ -13,16 +20,27 @@ pub use ropey::RopeInput;
 
 use std::ops;
 
-pub use grammar::{Grammar, IncompatibleGrammarError};
-pub use node::Node;
-pub use parser::{ParseOptions, ParseState, Parser, ParserInputRaw};
-pub use query::{Capture, Pattern, Query, QueryStr};
-pub use query_cursor::{InactiveQueryCursor, MatchedNode, MatchedNodeIdx, QueryCursor, QueryMatch};
+pub use encoding::{CustomEncoding, Decode, EncodedInput, Encoding};
+pub use grammar::{FieldId, Grammar, IncompatibleGrammarError, SemanticVersion, SymbolType};
+pub use grammar_registry::{GrammarInfo, GrammarRegistry, RegistryError};
+pub use input::{ChunkedCursor, ChunkedInput, SliceCursor, SliceInput};
+pub use lookahead_iterator::LookaheadIterator;
+pub use node::{Node, OwnedNode};
+pub use parser::{
+    LogType, ParseOptions, ParseOutcome, ParseState, Parser, ParserInputRaw, ParserSuspension,
+};
+pub use query::{Capture, CaptureMask, Pattern, PatternMask, Query, QueryStr};
+pub use query_cursor::{
+    InactiveQueryCursor, MatchedNode, MatchedNodeIdx, QueryCursor, QueryCursorOptions,
+    QueryCursorState, QueryMatch,
+};
+pub use sexp::{assert_tree_matches, Sexp, SexpMismatch, SexpParseError};
 pub use tree::{InputEdit, Tree};
-pub use tree_cursor::TreeCursor;
+pub use tree_cursor::{TraversalEvent, Traverse, TreeCursor};
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Point {
     pub row: u32,
     pub col: u32,
@@ -38,6 +56,7 @@ impl Point {
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Range {
     pub start_point: Point,
     pub end_point: Point,
@@ -62,6 +81,12 @@ pub trait Input {
     type Cursor: regex_cursor::Cursor;
     fn cursor_at(&mut self, offset: u32) -> &mut Self::Cursor;
     fn eq(&mut self, range1: ops::Range<u32>, range2: ops::Range<u32>) -> bool;
+    /// The encoding of the text. Defaults to UTF-8.
+    ///
+    /// See [`EncodedInput`] for parsing text in other encodings.
+    fn encoding(&self) -> Encoding {
+        Encoding::Utf8
+    }
 }
 
 pub trait IntoInput {
@@ -0,0 +1,183 @@
+use std::ffi::{c_char, CStr};
+use std::fmt;
+use std::ptr::NonNull;
+
+use crate::grammar_registry::GrammarRef;
+use crate::Grammar;
+
+// opaque pointer
+enum LookaheadIteratorData {}
+
+/// An iterator over the symbols (node kind ids) which are valid in a given
+/// parse state.
+///
+/// Lookahead iterators can be used to generate suggestions and improve syntax
+/// error diagnostics. To get the symbols valid in an `ERROR` node, use the
+/// lookahead iterator on the parse state of its first leaf node. For `MISSING`
+/// nodes, a lookahead iterator created on the previous non-extra leaf node may
+/// be appropriate.
+///
+/// A freshly created (or reset) iterator points at the `ERROR` symbol, see
+/// [`LookaheadIterator::current_symbol`]. Iteration starts at the first valid
+/// symbol of the state.
+pub struct LookaheadIterator {
+    ptr: NonNull<LookaheadIteratorData>,
+    grammar: GrammarRef,
+}
+
+impl LookaheadIterator {
+    /// Create a new lookahead iterator for the given grammar and parse state.
+    ///
+    /// Returns `None` if the state is invalid for the grammar. Parse states
+    /// can be obtained from [`Node::parse_state`](crate::Node::parse_state) and
+    /// [`Node::next_parse_state`](crate::Node::next_parse_state).
+    pub fn new(grammar: Grammar, state: u16) -> Option<LookaheadIterator> {
+        let ptr = unsafe { ts_lookahead_iterator_new(grammar, state) }?;
+        Some(LookaheadIterator {
+            ptr,
+            grammar: GrammarRef::new(grammar),
+        })
+    }
+
+    /// The grammar this iterator was created for.
+    pub fn grammar(&self) -> Grammar {
+        unsafe { ts_lookahead_iterator_language(self.ptr) }
+    }
+
+    /// The symbol the iterator currently points at.
+    pub fn current_symbol(&self) -> u16 {
+        unsafe { ts_lookahead_iterator_current_symbol(self.ptr) }
+    }
+
+    /// The name of the symbol the iterator currently points at.
+    pub fn current_symbol_name(&self) -> &'static str {
+        unsafe { CStr::from_ptr(ts_lookahead_iterator_current_symbol_name(self.ptr)) }
+            .to_str()
+            .unwrap()
+    }
+
+    /// Reset the iterator to the given parse state of the current grammar.
+    ///
+    /// Returns `false` (and leaves the iterator unchanged) if the state is
+    /// invalid.
+    pub fn reset_state(&mut self, state: u16) -> bool {
+        unsafe { ts_lookahead_iterator_reset_state(self.ptr, state) }
+    }
+
+    /// Reset the iterator to the given parse state of another grammar.
+    ///
+    /// Returns `false` (and leaves the iterator unchanged) if the state is
+    /// invalid.
+    pub fn reset(&mut self, grammar: Grammar, state: u16) -> bool {
+        let reset = unsafe { ts_lookahead_iterator_reset(self.ptr, grammar, state) };
+        if reset {
+            self.grammar = GrammarRef::new(grammar);
+        }
+        reset
+    }
+
+    /// Iterate over the names of the remaining symbols instead of their ids.
+    pub fn names(&mut self) -> impl Iterator<Item = &'static str> + '_ {
+        std::iter::from_fn(move || {
+            self.next()?;
+            Some(self.current_symbol_name())
+        })
+    }
+}
+
+impl Iterator for LookaheadIterator {
+    type Item = u16;
+
+    fn next(&mut self) -> Option<u16> {
+        unsafe { ts_lookahead_iterator_next(self.ptr) }.then(|| self.current_symbol())
+    }
+}
+
+impl fmt::Debug for LookaheadIterator {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{{LookaheadIterator {}}}", self.current_symbol_name())
+    }
+}
+
+impl Drop for LookaheadIterator {
+    fn drop(&mut self) {
+        unsafe { ts_lookahead_iterator_delete(self.ptr) }
+    }
+}
+
+unsafe impl Send for LookaheadIterator {}
+unsafe impl Sync for LookaheadIterator {}
+
+extern "C" {
+    /// Create a new lookahead iterator for the given language and parse state.
+    ///
+    /// This returns `NULL` if state is invalid for the language.
+    fn ts_lookahead_iterator_new(
+        grammar: Grammar,
+        state: u16,
+    ) -> Option<NonNull<LookaheadIteratorData>>;
+    /// Delete a lookahead iterator freeing all the memory used.
+    fn ts_lookahead_iterator_delete(self_: NonNull<LookaheadIteratorData>);
+    /// Reset the lookahead iterator to another state.
+    ///
+    /// This returns `true` if the iterator was reset to the given state and
+    /// `false` otherwise.
+    fn ts_lookahead_iterator_reset_state(self_: NonNull<LookaheadIteratorData>, state: u16)
+        -> bool;
+    /// Reset the lookahead iterator.
+    ///
+    /// This returns `true` if the language was set successfully and `false`
+    /// otherwise.
+    fn ts_lookahead_iterator_reset(
+        self_: NonNull<LookaheadIteratorData>,
+        grammar: Grammar,
+        state: u16,
+    ) -> bool;
+    /// Get the current language of the lookahead iterator.
+    fn ts_lookahead_iterator_language(self_: NonNull<LookaheadIteratorData>) -> Grammar;
+    /// Advance the lookahead iterator to the next symbol.
+    ///
+    /// This returns `true` if there is a new symbol and `false` otherwise.
+    fn ts_lookahead_iterator_next(self_: NonNull<LookaheadIteratorData>) -> bool;
+    /// Get the current symbol of the lookahead iterator.
+    fn ts_lookahead_iterator_current_symbol(self_: NonNull<LookaheadIteratorData>) -> u16;
+    /// Get the current symbol type of the lookahead iterator as a null
+    /// terminated string.
+    fn ts_lookahead_iterator_current_symbol_name(
+        self_: NonNull<LookaheadIteratorData>,
+    ) -> *const c_char;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_grammars::python_grammar;
+    use crate::{LookaheadIterator, Parser};
+
+    #[test]
+    fn expected_symbols_after_node() {
+        let grammar = python_grammar();
+        let src = "import a\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let import = tree
+            .root_node()
+            .descendant_for_byte_range(0, 6)
+            .expect("import keyword");
+        assert_eq!(import.kind(), "import");
+
+        let mut lookahead = LookaheadIterator::new(grammar, import.next_parse_state()).unwrap();
+        assert_eq!(lookahead.current_symbol_name(), "ERROR");
+        assert_eq!(lookahead.grammar(), grammar);
+        let expected: Vec<_> = lookahead.names().collect();
+        assert!(expected.contains(&"identifier"), "{expected:?}");
+        assert!(!expected.contains(&"import"), "{expected:?}");
+
+        assert!(lookahead.reset_state(import.parse_state()));
+        let expected: Vec<_> = lookahead.names().collect();
+        assert!(expected.contains(&"import"), "{expected:?}");
+
+        assert!(!lookahead.reset_state(u16::MAX));
+        assert!(LookaheadIterator::new(grammar, u16::MAX).is_none());
+    }
+}
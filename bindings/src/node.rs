@@ -4,9 +4,9 @@ use std::marker::PhantomData;
 use std::ops::Range;
 use std::ptr::NonNull;
 
-use crate::tree::Tree;
-use crate::tree_cursor::TreeCursor;
-use crate::Grammar;
+use crate::tree::{InputEdit, Tree};
+use crate::tree_cursor::{TraversalEvent, TraversalState, TreeCursor};
+use crate::{FieldId, Grammar, Point};
 
 #[repr(C)]
 #[derive(Debug, Clone, Copy)]
@@ -80,12 +80,45 @@ impl<'tree> Node<'tree> {
         unsafe { ts_node_symbol(self.as_raw()) }
     }
 
+    /// Get this node's type as it appears in the grammar, ignoring aliases.
+    #[inline]
+    pub fn grammar_name(&self) -> &'tree str {
+        unsafe { CStr::from_ptr(ts_node_grammar_type(self.as_raw())) }
+            .to_str()
+            .unwrap()
+    }
+
+    /// Get this node's type as a numerical id as it appears in the grammar,
+    /// ignoring aliases.
+    ///
+    /// This is the id which should be passed to [`Grammar::next_state`]
+    /// instead of [`Node::kind_id`].
+    #[inline]
+    pub fn grammar_id(&self) -> u16 {
+        unsafe { ts_node_grammar_symbol(self.as_raw()) }
+    }
+
     /// Get the [`Grammar`] that was used to parse this node's syntax tree.
     #[inline]
     pub fn grammar(&self) -> Grammar {
         unsafe { ts_node_language(self.as_raw()) }
     }
 
+    /// Get the parse state of this node.
+    ///
+    /// Combined with [`LookaheadIterator`](crate::LookaheadIterator) this can be
+    /// used to find the symbols that were valid at this node.
+    #[inline]
+    pub fn parse_state(&self) -> u16 {
+        unsafe { ts_node_parse_state(self.as_raw()) }
+    }
+
+    /// Get the parse state after this node.
+    #[inline]
+    pub fn next_parse_state(&self) -> u16 {
+        unsafe { ts_node_next_parse_state(self.as_raw()) }
+    }
+
     /// Check if this node is *named*.
     ///
     /// Named nodes correspond to named rules in the grammar, whereas
@@ -121,6 +154,28 @@ impl<'tree> Node<'tree> {
         unsafe { ts_node_is_extra(self.as_raw()) }
     }
 
+    /// Check if this node is a syntax error (an `ERROR` node).
+    #[inline]
+    pub fn is_error(&self) -> bool {
+        unsafe { ts_node_is_error(self.as_raw()) }
+    }
+
+    /// Check if this node is a syntax error or contains any syntax errors.
+    ///
+    /// This is cheap, so it can be used to skip subtrees without errors when
+    /// collecting diagnostics.
+    #[inline]
+    pub fn has_error(&self) -> bool {
+        unsafe { ts_node_has_error(self.as_raw()) }
+    }
+
+    /// Check if this node has been edited with [`Tree::edit`] since the tree
+    /// was parsed.
+    #[inline]
+    pub fn has_changes(&self) -> bool {
+        unsafe { ts_node_has_changes(self.as_raw()) }
+    }
+
     /// Get the byte offsets where this node starts.
     #[inline(always)]
     pub fn start_byte(&self) -> u32 {
@@ -151,6 +206,30 @@ impl<'tree> Node<'tree> {
         self.start_byte()..self.end_byte()
     }
 
+    /// Get this node's start position in terms of rows and columns.
+    #[inline]
+    pub fn start_point(&self) -> Point {
+        unsafe { ts_node_start_point(self.as_raw()) }
+    }
+
+    /// Get this node's end position in terms of rows and columns.
+    #[inline]
+    pub fn end_point(&self) -> Point {
+        unsafe { ts_node_end_point(self.as_raw()) }
+    }
+
+    /// Get the range of source code that this node represents, both in terms of
+    /// raw bytes and of row/column coordinates.
+    #[inline]
+    pub fn range(&self) -> crate::Range {
+        crate::Range {
+            start_point: self.start_point(),
+            end_point: self.end_point(),
+            start_byte: self.start_byte(),
+            end_byte: self.end_byte(),
+        }
+    }
+
     /// Get the node's child at the given index, where zero represents the first
     /// child.
     ///
@@ -179,6 +258,13 @@ impl<'tree> Node<'tree> {
         unsafe { Node::from_raw(ts_node_named_child(self.as_raw(), i)) }
     }
 
+    /// Get the number of descendants of this node, including one for the node
+    /// itself.
+    #[inline]
+    pub fn descendant_count(&self) -> u32 {
+        unsafe { ts_node_descendant_count(self.as_raw()) }
+    }
+
     /// Get this node's number of *named* children.
     ///
     /// See also [`Node::is_named`].
@@ -187,6 +273,49 @@ impl<'tree> Node<'tree> {
         unsafe { ts_node_named_child_count(self.as_raw()) }
     }
 
+    /// Get the first child with the given field name.
+    ///
+    /// If multiple children may have the same field name, access them using
+    /// [`Node::children_by_field_name`].
+    #[inline]
+    pub fn child_by_field_name(&self, field_name: &str) -> Option<Node<'tree>> {
+        let len = u32::try_from(field_name.len()).ok()?;
+        unsafe {
+            Node::from_raw(ts_node_child_by_field_name(
+                self.as_raw(),
+                field_name.as_ptr().cast(),
+                len,
+            ))
+        }
+    }
+
+    /// Get this node's child with the given numerical field id.
+    ///
+    /// See also [`Node::child_by_field_name`]. You can convert a field name to
+    /// an id using [`Grammar::field_id_for_name`].
+    #[inline]
+    pub fn child_by_field_id(&self, field_id: FieldId) -> Option<Node<'tree>> {
+        unsafe { Node::from_raw(ts_node_child_by_field_id(self.as_raw(), field_id)) }
+    }
+
+    /// Get the field name of this node's child at the given index.
+    #[inline]
+    pub fn field_name_for_child(&self, i: u32) -> Option<&'tree str> {
+        unsafe {
+            let ptr = ts_node_field_name_for_child(self.as_raw(), i);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
+        }
+    }
+
+    /// Get the field name of this node's *named* child at the given index.
+    #[inline]
+    pub fn field_name_for_named_child(&self, i: u32) -> Option<&'tree str> {
+        unsafe {
+            let ptr = ts_node_field_name_for_named_child(self.as_raw(), i);
+            (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
+        }
+    }
+
     #[inline]
     unsafe fn map(&self, f: unsafe extern "C" fn(NodeRaw) -> NodeRaw) -> Option<Node<'tree>> {
         Node::from_raw(f(self.as_raw()))
@@ -198,6 +327,22 @@ impl<'tree> Node<'tree> {
         unsafe { self.map(ts_node_parent) }
     }
 
+    /// Get the child of this node which contains `descendant`.
+    ///
+    /// Note that this returns `descendant` itself if it is a child of this
+    /// node. Repeatedly calling this function is a more efficient way to find
+    /// the path from a node to one of its descendants than walking up from the
+    /// descendant with [`Node::parent`].
+    #[inline]
+    pub fn child_with_descendant(&self, descendant: &Node<'tree>) -> Option<Self> {
+        unsafe {
+            Self::from_raw(ts_node_child_with_descendant(
+                self.as_raw(),
+                descendant.as_raw(),
+            ))
+        }
+    }
+
     /// Get this node's next sibling.
     #[inline]
     pub fn next_sibling(&self) -> Option<Self> {
@@ -222,6 +367,20 @@ impl<'tree> Node<'tree> {
         unsafe { self.map(ts_node_prev_named_sibling) }
     }
 
+    /// Get this node's first child that contains or starts after the given
+    /// byte offset.
+    #[inline]
+    pub fn first_child_for_byte(&self, byte: u32) -> Option<Self> {
+        unsafe { Self::from_raw(ts_node_first_child_for_byte(self.as_raw(), byte)) }
+    }
+
+    /// Get this node's first named child that contains or starts after the
+    /// given byte offset.
+    #[inline]
+    pub fn first_named_child_for_byte(&self, byte: u32) -> Option<Self> {
+        unsafe { Self::from_raw(ts_node_first_named_child_for_byte(self.as_raw(), byte)) }
+    }
+
     /// Get the smallest node within this node that spans the given range.
     #[inline]
     pub fn descendant_for_byte_range(&self, start: u32, end: u32) -> Option<Self> {
@@ -240,6 +399,31 @@ impl<'tree> Node<'tree> {
         }
     }
 
+    /// Get the smallest node within this node that spans the given point range.
+    #[inline]
+    pub fn descendant_for_point_range(&self, start: Point, end: Point) -> Option<Self> {
+        unsafe {
+            Self::from_raw(ts_node_descendant_for_point_range(
+                self.as_raw(),
+                start,
+                end,
+            ))
+        }
+    }
+
+    /// Get the smallest named node within this node that spans the given point
+    /// range.
+    #[inline]
+    pub fn named_descendant_for_point_range(&self, start: Point, end: Point) -> Option<Self> {
+        unsafe {
+            Self::from_raw(ts_node_named_descendant_for_point_range(
+                self.as_raw(),
+                start,
+                end,
+            ))
+        }
+    }
+
     /// Iterate over this node's children.
     ///
     /// A [`TreeCursor`] is used to retrieve the children efficiently. Obtain
@@ -259,6 +443,61 @@ impl<'tree> Node<'tree> {
         })
     }
 
+    /// Iterate over this node's children with the given field name.
+    ///
+    /// Unlike [`Node::child_by_field_name`] this yields every child with the
+    /// field name, which is useful for fields which are repeated in the
+    /// grammar.
+    pub fn children_by_field_name(&self, field_name: &str) -> impl Iterator<Item = Node<'tree>> {
+        let field_id = self.grammar().field_id_for_name(field_name);
+        self.children_by_field_id(field_id)
+    }
+
+    /// Iterate over this node's children with the given numerical field id.
+    ///
+    /// See also [`Node::children_by_field_name`]. Passing `None` yields no
+    /// children.
+    pub fn children_by_field_id(
+        &self,
+        field_id: Option<FieldId>,
+    ) -> impl Iterator<Item = Node<'tree>> {
+        let mut cursor = TreeCursor::new(self);
+        let mut done = field_id.is_none() || !cursor.goto_first_child();
+        std::iter::from_fn(move || {
+            while !done {
+                let matches = cursor.field_id() == field_id;
+                let node = cursor.node();
+                done = !cursor.goto_next_sibling();
+                if matches {
+                    return Some(node);
+                }
+            }
+            None
+        })
+    }
+
+    /// Iterate over this node and all of its descendants in pre-order (depth-first, parents
+    /// before their children).
+    ///
+    /// See [`TreeCursor::traverse`] for a traversal which also visits nodes after their
+    /// children.
+    pub fn descendants(&self) -> impl Iterator<Item = Node<'tree>> {
+        let mut cursor = TreeCursor::new(self);
+        let mut state = TraversalState::default();
+        std::iter::from_fn(move || loop {
+            match state.next(&mut cursor)? {
+                TraversalEvent::Enter { node, .. } => return Some(node),
+                TraversalEvent::Leave { .. } => continue,
+            }
+        })
+    }
+
+    /// Iterate over this node and its ancestors, from this node up to the root
+    /// of the tree.
+    pub fn ancestors(&self) -> impl Iterator<Item = Node<'tree>> {
+        std::iter::successors(Some(self.clone()), |node| node.parent())
+    }
+
     pub fn walk(&self) -> TreeCursor<'tree> {
         TreeCursor::new(self)
     }
@@ -275,6 +514,124 @@ impl Eq for Node<'_> {}
 unsafe impl Send for Node<'_> {}
 unsafe impl Sync for Node<'_> {}
 
+/// A [`Node`] which owns a (cheap, shallow) copy of its [`Tree`] instead of
+/// borrowing it.
+///
+/// Owned nodes can be stored in long-lived structures like jump lists and
+/// outline caches. When the document is edited the node's position can be kept
+/// in sync with [`OwnedNode::edit`] and the equivalent node of the re-parsed
+/// tree can be found with [`OwnedNode::resolve`].
+pub struct OwnedNode {
+    /// The node. Its tree pointer points to `tree`.
+    raw: NodeRaw,
+    tree: Tree,
+}
+
+impl OwnedNode {
+    pub fn new(node: Node<'_>) -> OwnedNode {
+        let tree = unsafe { Tree::copy_raw(node.tree.cast()) };
+        let raw = OwnedNode::move_to_tree(node.as_raw(), &tree);
+        OwnedNode { raw, tree }
+    }
+
+    /// Points a node at a copy of its tree.
+    fn move_to_tree(mut raw: NodeRaw, tree: &Tree) -> NodeRaw {
+        // Copies of a tree share all nodes except for the root node, which is
+        // part of the tree itself.
+        let node = unsafe { Node::from_raw(raw).unwrap() };
+        if node.parent().is_none() {
+            raw.id = tree.root_node().id.as_ptr();
+        }
+        raw.tree = tree.as_raw().as_ptr().cast_const().cast();
+        raw
+    }
+
+    /// Borrow the node.
+    pub fn node(&self) -> Node<'_> {
+        unsafe { Node::from_raw(self.raw).unwrap() }
+    }
+
+    /// The tree that the node belongs to.
+    pub fn tree(&self) -> &Tree {
+        &self.tree
+    }
+
+    /// Adjust the node and its copy of the tree for an edit of the source code.
+    ///
+    /// The edit must also be applied to the tree that the node is later
+    /// resolved against (see [`Tree::edit`]).
+    pub fn edit(&mut self, edit: &InputEdit) {
+        // Editing the tree may replace the node with an edited copy, so the
+        // node is looked up again by its position among its ancestors.
+        let mut path = Vec::new();
+        let node = self.node();
+        let mut ancestor = self.tree.root_node();
+        while ancestor != node {
+            let child = ancestor.child_with_descendant(&node).unwrap();
+            let i = (0..ancestor.child_count())
+                .find(|&i| ancestor.child(i).as_ref() == Some(&child))
+                .unwrap();
+            path.push(i);
+            ancestor = child;
+        }
+        self.tree.edit(edit);
+        let mut node = self.tree.root_node();
+        for i in path {
+            node = node.child(i).unwrap();
+        }
+        self.raw = node.as_raw();
+    }
+
+    /// Find the node in another version of the tree, typically the tree which
+    /// was parsed after the edits passed to [`OwnedNode::edit`].
+    ///
+    /// Returns the outermost node of `tree` with the same kind and byte range,
+    /// or `None` if there is no such node (for example because the node was
+    /// changed by an edit).
+    pub fn resolve<'tree>(&self, tree: &'tree Tree) -> Option<Node<'tree>> {
+        let node = self.node();
+        let range = node.byte_range();
+        let mut candidate = tree
+            .root_node()
+            .descendant_for_byte_range(range.start, range.end)?;
+        let mut resolved = None;
+        while candidate.byte_range() == range {
+            if candidate.kind_id() == node.kind_id() {
+                resolved = Some(candidate.clone());
+            }
+            match candidate.parent() {
+                Some(parent) => candidate = parent,
+                None => break,
+            }
+        }
+        resolved
+    }
+}
+
+impl<'tree> From<Node<'tree>> for OwnedNode {
+    fn from(node: Node<'tree>) -> OwnedNode {
+        OwnedNode::new(node)
+    }
+}
+
+impl Clone for OwnedNode {
+    fn clone(&self) -> Self {
+        // The node must point to the copied tree, the original may be dropped first.
+        let tree = self.tree.clone();
+        let raw = OwnedNode::move_to_tree(self.raw, &tree);
+        OwnedNode { raw, tree }
+    }
+}
+
+impl fmt::Debug for OwnedNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{{OwnedNode {:?}}}", self.node())
+    }
+}
+
+unsafe impl Send for OwnedNode {}
+unsafe impl Sync for OwnedNode {}
+
 extern "C" {
     /// Get the node's type as a null-terminated string.
     fn ts_node_type(node: NodeRaw) -> *const c_char;
@@ -285,6 +642,15 @@ extern "C" {
     /// Get the node's language.
     fn ts_node_language(node: NodeRaw) -> Grammar;
 
+    /// Get the node's type as it appears in the grammar ignoring aliases as a
+    /// null-terminated string.
+    fn ts_node_grammar_type(node: NodeRaw) -> *const c_char;
+
+    /// Get the node's type as a numerical id as it appears in the grammar
+    /// ignoring aliases. This should be used in `ts_language_next_state`
+    /// instead of `ts_node_symbol`.
+    fn ts_node_grammar_symbol(node: NodeRaw) -> u16;
+
     /// Check if the node is *named*. Named nodes correspond to named rules in
     /// the grammar, whereas *anonymous* nodes correspond to string literals in
     /// the grammar
@@ -300,9 +666,29 @@ extern "C" {
     /// grammar, but can appear anywhere.
     fn ts_node_is_extra(node: NodeRaw) -> bool;
 
+    /// Check if a syntax node has been edited.
+    fn ts_node_has_changes(node: NodeRaw) -> bool;
+
+    /// Check if the node is a syntax error or contains any syntax errors.
+    fn ts_node_has_error(node: NodeRaw) -> bool;
+
+    /// Check if the node is a syntax error.
+    fn ts_node_is_error(node: NodeRaw) -> bool;
+
+    /// Get this node's parse state.
+    fn ts_node_parse_state(node: NodeRaw) -> u16;
+
+    /// Get the parse state after this node.
+    fn ts_node_next_parse_state(node: NodeRaw) -> u16;
+
     /// Get the node's immediate parent
     fn ts_node_parent(node: NodeRaw) -> NodeRaw;
 
+    /// Get the node that contains `descendant`.
+    ///
+    /// Note that this can return `descendant` itself.
+    fn ts_node_child_with_descendant(node: NodeRaw, descendant: NodeRaw) -> NodeRaw;
+
     /// Get the node's child at the given index, where zero represents the first
     /// child
     fn ts_node_child(node: NodeRaw, child_index: u32) -> NodeRaw;
@@ -317,6 +703,21 @@ extern "C" {
     /// Get the node's number of *named* children. See also [`ts_node_is_named`]
     fn ts_node_named_child_count(node: NodeRaw) -> u32;
 
+    /// Get the field name for node's child at the given index, where zero
+    /// represents the first child. Returns NULL, if no field is found.
+    fn ts_node_field_name_for_child(node: NodeRaw, child_index: u32) -> *const c_char;
+
+    /// Get the field name for node's named child at the given index, where zero
+    /// represents the first named child. Returns NULL, if no field is found.
+    fn ts_node_field_name_for_named_child(node: NodeRaw, named_child_index: u32) -> *const c_char;
+
+    /// Get the node's child with the given field name.
+    fn ts_node_child_by_field_name(node: NodeRaw, name: *const c_char, name_length: u32)
+        -> NodeRaw;
+
+    /// Get the node's child with the given numerical field id.
+    fn ts_node_child_by_field_id(node: NodeRaw, field_id: FieldId) -> NodeRaw;
+
     /// Get the node's next sibling
     fn ts_node_next_sibling(node: NodeRaw) -> NodeRaw;
 
@@ -335,6 +736,242 @@ extern "C" {
     /// of bytes or (row, column) positions
     fn ts_node_named_descendant_for_byte_range(node: NodeRaw, start: u32, end: u32) -> NodeRaw;
 
+    /// Get the smallest node within this node that spans the given range of
+    /// (row, column) positions
+    fn ts_node_descendant_for_point_range(node: NodeRaw, start: Point, end: Point) -> NodeRaw;
+
+    /// Get the smallest named node within this node that spans the given range
+    /// of (row, column) positions
+    fn ts_node_named_descendant_for_point_range(node: NodeRaw, start: Point, end: Point)
+        -> NodeRaw;
+
     /// Get the node's end byte.
     fn ts_node_end_byte(node: NodeRaw) -> u32;
+
+    /// Get the node's first child that contains or starts after the given byte
+    /// offset.
+    fn ts_node_first_child_for_byte(node: NodeRaw, byte: u32) -> NodeRaw;
+
+    /// Get the node's first named child that contains or starts after the given
+    /// byte offset.
+    fn ts_node_first_named_child_for_byte(node: NodeRaw, byte: u32) -> NodeRaw;
+
+    /// Get the node's number of descendants, including one for the node itself.
+    fn ts_node_descendant_count(node: NodeRaw) -> u32;
+
+    /// Get the node's start position in terms of rows and columns.
+    fn ts_node_start_point(node: NodeRaw) -> Point;
+
+    /// Get the node's end position in terms of rows and columns.
+    fn ts_node_end_point(node: NodeRaw) -> Point;
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::test_grammars::python_grammar;
+    use crate::{InputEdit, OwnedNode, Parser, Point};
+
+    #[test]
+    fn child_by_field() {
+        let grammar = python_grammar();
+        let src = "def foo(a, b):\n    return a\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let function = tree.root_node().child(0).unwrap();
+        assert_eq!(function.kind(), "function_definition");
+
+        let name = function.child_by_field_name("name").unwrap();
+        assert_eq!(
+            &src[name.start_byte() as usize..name.end_byte() as usize],
+            "foo"
+        );
+        let body = function.child_by_field_name("body").unwrap();
+        assert_eq!(body.kind(), "block");
+        assert!(function.child_by_field_name("not_a_field").is_none());
+
+        let body_id = grammar.field_id_for_name("body").unwrap();
+        assert_eq!(grammar.field_name_for_id(body_id), Some("body"));
+        assert_eq!(function.child_by_field_id(body_id), Some(body.clone()));
+
+        let field_names: Vec<_> = (0..function.child_count())
+            .map(|i| function.field_name_for_child(i))
+            .collect();
+        assert_eq!(
+            field_names,
+            [None, Some("name"), Some("parameters"), None, Some("body")]
+        );
+
+        let mut cursor = function.walk();
+        assert!(cursor.goto_last_child());
+        assert_eq!(cursor.field_id(), Some(body_id));
+    }
+
+    #[test]
+    fn children_by_field_name() {
+        let grammar = python_grammar();
+        let src = "import a, b, c\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let import = tree.root_node().child(0).unwrap();
+        assert_eq!(import.kind(), "import_statement");
+
+        let names: Vec<_> = import
+            .children_by_field_name("name")
+            .map(|node| &src[node.start_byte() as usize..node.end_byte() as usize])
+            .collect();
+        assert_eq!(names, ["a", "b", "c"]);
+        assert_eq!(import.children_by_field_name("not_a_field").count(), 0);
+    }
+
+    #[test]
+    fn point_positions() {
+        let grammar = python_grammar();
+        let src = "x = 1\nyy = [1, 2]\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+
+        let second = root.child(1).unwrap();
+        assert_eq!(second.start_point(), Point { row: 1, col: 0 });
+        assert_eq!(second.end_point(), Point { row: 1, col: 11 });
+        let range = second.range();
+        assert_eq!(range.start_byte..range.end_byte, second.byte_range());
+        assert_eq!(range.start_point, second.start_point());
+
+        let list = root
+            .named_descendant_for_point_range(Point { row: 1, col: 5 }, Point { row: 1, col: 11 })
+            .unwrap();
+        assert_eq!(list.kind(), "list");
+        let bracket = root
+            .descendant_for_point_range(Point { row: 1, col: 5 }, Point { row: 1, col: 6 })
+            .unwrap();
+        assert_eq!(bracket.kind(), "[");
+
+        let mut cursor = root.walk();
+        assert_eq!(
+            cursor.goto_first_child_for_point(Point { row: 1, col: 3 }),
+            Some(1)
+        );
+        assert_eq!(cursor.node(), second);
+    }
+
+    #[test]
+    fn errors_and_descendants() {
+        let grammar = python_grammar();
+        let src = "x = (1\ny = 2\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let mut tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+        assert!(root.has_error());
+        assert!(!root.is_error());
+        let error = root.descendants().find(|node| node.is_error()).unwrap();
+        assert!(error.has_error());
+        assert!(root.descendants().all(|node| !node.has_changes()));
+        assert_eq!(root.descendant_count() as usize, root.descendants().count());
+
+        let ancestor = root.child_with_descendant(&error).unwrap();
+        assert!(ancestor == error || ancestor.descendants().any(|node| node == error));
+        let child = root.first_child_for_byte(8).unwrap();
+        assert!(child.end_byte() > 8);
+        assert!(child
+            .prev_sibling()
+            .map_or(true, |prev| prev.end_byte() <= 8));
+        assert!(root.first_named_child_for_byte(src.len() as u32).is_none());
+        assert_eq!(error.grammar_name(), error.kind());
+
+        let offset = tree.root_node_with_offset(10, Point { row: 2, col: 0 });
+        assert_eq!(offset.byte_range(), 10..src.len() as u32 + 10);
+        assert_eq!(offset.start_point(), Point { row: 2, col: 0 });
+        assert_eq!(tree.included_ranges().len(), 1);
+
+        tree.edit(&InputEdit {
+            start_byte: 11,
+            old_end_byte: 12,
+            new_end_byte: 12,
+            start_point: Point { row: 1, col: 4 },
+            old_end_point: Point { row: 1, col: 5 },
+            new_end_point: Point { row: 1, col: 5 },
+        });
+        let root = tree.root_node();
+        assert!(root.has_changes());
+    }
+
+    #[test]
+    fn owned_node() {
+        let grammar = python_grammar();
+        let src = "def a():\n    pass\ndef b():\n    pass\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let mut tree = parser.parse(src, None).unwrap();
+        let mut b = OwnedNode::new(tree.root_node().named_child(1).unwrap());
+        let a = OwnedNode::from(tree.root_node().named_child(0).unwrap());
+        assert_eq!(b.node().kind(), "function_definition");
+        assert_eq!(b.node().byte_range(), 18..35);
+
+        // insert a line at the start and delete the body of `a`
+        let edits = [
+            InputEdit {
+                start_byte: 13,
+                old_end_byte: 17,
+                new_end_byte: 13,
+                start_point: Point { row: 1, col: 4 },
+                old_end_point: Point { row: 1, col: 8 },
+                new_end_point: Point { row: 1, col: 4 },
+            },
+            InputEdit {
+                start_byte: 0,
+                old_end_byte: 0,
+                new_end_byte: 6,
+                start_point: Point { row: 0, col: 0 },
+                old_end_point: Point { row: 0, col: 0 },
+                new_end_point: Point { row: 1, col: 0 },
+            },
+        ];
+        let new_src = "x = 1\ndef a():\n    \ndef b():\n    pass\n";
+        for edit in &edits {
+            tree.edit(edit);
+            b.edit(edit);
+        }
+        assert_eq!(b.node().start_byte(), 20);
+        // the node's copy of the tree is edited as well
+        assert_eq!(b.tree().root_node().byte_range(), 6..38);
+        assert_eq!(b.node().parent().unwrap().byte_range(), 6..38);
+        assert_eq!(
+            b.node().child_by_field_name("name").unwrap().byte_range(),
+            24..25
+        );
+        let new_tree = parser.parse(new_src, Some(&tree)).unwrap();
+        drop(tree);
+        let resolved = b.resolve(&new_tree).unwrap();
+        assert_eq!(resolved.kind(), "function_definition");
+        assert_eq!(resolved.byte_range(), 20..37);
+        assert_eq!(
+            resolved.child_by_field_name("name").unwrap().byte_range(),
+            24..25
+        );
+        // the original tree is kept alive by the owned node
+        assert_eq!(a.node().byte_range(), 0..17);
+        assert!(a.resolve(&new_tree).is_none());
+
+        // a clone owns its own copy of the tree
+        let clone = a.clone();
+        drop(a);
+        assert_eq!(
+            clone.raw.tree,
+            clone.tree.as_raw().as_ptr().cast_const().cast()
+        );
+        let name = clone.node().child_by_field_name("name").unwrap();
+        assert_eq!(name.byte_range(), 4..5);
+        assert_eq!(name.parent().unwrap().byte_range(), 0..17);
+
+        // the root node belongs to the copy of the tree
+        let root = OwnedNode::new(new_tree.root_node());
+        drop(new_tree);
+        assert_eq!(root.node(), root.tree().root_node());
+        assert_eq!(root.clone().node().kind(), "module");
+    }
 }
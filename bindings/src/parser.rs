@@ -1,7 +1,11 @@
+use std::any::Any;
 use std::cell::Cell;
+use std::ffi::{c_char, CStr};
 use std::ops::ControlFlow;
-use std::os::raw::c_void;
-use std::panic::{catch_unwind, AssertUnwindSafe};
+#[cfg(unix)]
+use std::os::fd::{AsFd, IntoRawFd};
+use std::os::raw::{c_int, c_void};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
 use std::ptr::NonNull;
 use std::time::{Duration, Instant};
 use std::{fmt, mem, ptr};
@@ -9,6 +13,7 @@ use std::{fmt, mem, ptr};
 use regex_cursor::Cursor;
 
 use crate::grammar::IncompatibleGrammarError;
+use crate::grammar_registry::GrammarRef;
 use crate::tree::{SyntaxTreeData, Tree};
 use crate::{Grammar, Input, IntoInput, Point, Range};
 
@@ -30,10 +35,22 @@ impl Drop for RawParser {
     }
 }
 
+type LogFn = dyn FnMut(LogType, &str) + Send;
+
+struct Logger {
+    log: Box<LogFn>,
+    /// The payload of a panic of `log`, resumed once the parse returns.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
 /// A stateful object that this is used to produce a [`Tree`] based on some
 /// source code.
 pub struct Parser {
     ptr: NonNull<ParserData>,
+    /// The logger installed with [`Parser::set_logger`]. It's boxed so that
+    /// it can be passed to the C library as a thin pointer.
+    logger: Option<Box<Logger>>,
+    grammar: GrammarRef,
 }
 
 impl Parser {
@@ -48,14 +65,21 @@ impl Parser {
             }
             None => unsafe { ts_parser_new() },
         };
-        Parser { ptr }
+        Parser {
+            ptr,
+            logger: None,
+            grammar: GrammarRef::default(),
+        }
     }
 
     /// Set the language that the parser should use for parsing.
     pub fn set_grammar(&mut self, grammar: Grammar) -> Result<(), IncompatibleGrammarError> {
-        if unsafe { ts_parser_set_language(self.ptr, grammar) } {
+        if unsafe { ts_parser_set_language(self.ptr, Some(grammar)) } {
+            self.grammar = GrammarRef::new(grammar);
             Ok(())
         } else {
+            // the C library unsets the previous grammar on failure
+            self.grammar = GrammarRef::default();
             Err(IncompatibleGrammarError {
                 abi_version: grammar.abi_version(),
             })
@@ -83,6 +107,84 @@ impl Parser {
         }
     }
 
+    /// Set a callback which receives the parser's debug log messages.
+    ///
+    /// The parser logs the actions it takes (shifts, reductions, error
+    /// recovery, ...) with [`LogType::Parse`] and the characters consumed by
+    /// the lexer with [`LogType::Lex`]. This replaces any previously set
+    /// logger.
+    ///
+    /// A panic of the logger is resumed once the parse returns. The logger is
+    /// not called for the rest of that parse.
+    pub fn set_logger(&mut self, logger: impl FnMut(LogType, &str) + Send + 'static) {
+        self.install_logger(Box::new(Logger {
+            log: Box::new(logger),
+            panic: None,
+        }));
+    }
+
+    fn install_logger(&mut self, mut logger: Box<Logger>) {
+        unsafe extern "C" fn log(payload: *mut c_void, log_type: c_int, buffer: *const c_char) {
+            let logger: &mut Logger = &mut *payload.cast();
+            // A panic must not unwind into C so it is stashed until the parse returns.
+            if logger.panic.is_some() {
+                return;
+            }
+            let log_type = if log_type == 1 {
+                LogType::Lex
+            } else {
+                LogType::Parse
+            };
+            let message = CStr::from_ptr(buffer).to_string_lossy();
+            if let Err(payload) =
+                catch_unwind(AssertUnwindSafe(|| (logger.log)(log_type, &message)))
+            {
+                logger.panic = Some(payload);
+            }
+        }
+
+        let raw = RawLogger {
+            payload: ptr::addr_of_mut!(*logger).cast(),
+            log: Some(log),
+        };
+        unsafe { ts_parser_set_logger(self.ptr, raw) };
+        self.logger = Some(logger);
+    }
+
+    /// Continue a panic of the logger once control is back in Rust.
+    fn resume_logger_panic(&mut self) {
+        if let Some(payload) = self.logger.as_mut().and_then(|logger| logger.panic.take()) {
+            resume_unwind(payload)
+        }
+    }
+
+    /// Remove the logger installed with [`Parser::set_logger`], if any.
+    pub fn remove_logger(&mut self) {
+        if self.logger.is_some() {
+            unsafe { ts_parser_set_logger(self.ptr, RawLogger::default()) };
+            self.logger = None;
+        }
+    }
+
+    /// Write debugging graphs of the parse stack to the given file during
+    /// parsing.
+    ///
+    /// The graphs are formatted in the DOT language. You may want to pipe
+    /// these graphs directly to a `dot(1)` process in order to generate SVG
+    /// output. The file descriptor is duplicated so the caller keeps ownership
+    /// of `file`. This is only available on unix.
+    #[cfg(unix)]
+    pub fn print_dot_graphs(&mut self, file: &impl AsFd) -> std::io::Result<()> {
+        let fd = file.as_fd().try_clone_to_owned()?.into_raw_fd();
+        unsafe { ts_parser_print_dot_graphs(self.ptr, fd) }
+        Ok(())
+    }
+
+    /// Stop writing debugging graphs started with [`Parser::print_dot_graphs`].
+    pub fn stop_printing_dot_graphs(&mut self) {
+        unsafe { ts_parser_print_dot_graphs(self.ptr, -1) }
+    }
+
     #[must_use]
     pub fn parse<I: Input>(
         &mut self,
@@ -90,6 +192,7 @@ impl Parser {
         old_tree: Option<&Tree>,
     ) -> Option<Tree> {
         let mut input = input.into_input();
+        let (encoding, decode) = input.encoding().raw();
         unsafe extern "C" fn read<C: Input>(
             payload: NonNull<c_void>,
             byte_index: u32,
@@ -118,26 +221,99 @@ impl Parser {
         let raw_input = ParserInputRaw {
             payload: NonNull::from(&mut input).cast(),
             read: read::<I>,
-            encoding: InputEncoding::Utf8,
-            decode: None,
+            encoding,
+            decode,
         };
 
-        unsafe {
+        let tree = unsafe {
             let old_tree = old_tree.map(|tree| tree.as_raw());
             ts_parser_parse(self.ptr, old_tree, raw_input).map(|raw| Tree::from_raw(raw))
-        }
+        };
+        self.resume_logger_panic();
+        tree
     }
 
     /// Parse with a progress/cancellation callback. The callback receives the current
     /// [`ParseState`] and returns [`ControlFlow::Break`] to cancel parsing.
+    ///
+    /// A cancelled parse is discarded. Use [`Parser::parse_resumable`] to continue a cancelled
+    /// parse later instead.
     #[must_use]
     pub fn parse_with_options<I: Input>(
         &mut self,
         input: impl IntoInput<Input = I>,
         old_tree: Option<&Tree>,
+        options: ParseOptions<'_>,
+    ) -> Option<Tree> {
+        let tree = self.parse_with_options_impl(input.into_input(), old_tree, options);
+        if tree.is_none() {
+            // Otherwise the next parse would continue the cancelled parse, even for a different
+            // input.
+            unsafe { ts_parser_reset(self.ptr) }
+        }
+        self.resume_logger_panic();
+        tree
+    }
+
+    /// Parse like [`Parser::parse_with_options`] but keep the parser's state if the callback
+    /// cancels parsing, so that the parse can be continued later with
+    /// [`ParserSuspension::resume`].
+    ///
+    /// The partially parsed state is moved into the returned [`ParserSuspension`]. This parser is
+    /// replaced with a fresh parser with the same grammar, included ranges and logger so that it
+    /// can be used for other parses in the meantime. Debug graphs enabled with
+    /// `print_dot_graphs` stay with the suspended parse.
+    pub fn parse_resumable<I: Input>(
+        &mut self,
+        input: impl IntoInput<Input = I>,
+        old_tree: Option<&Tree>,
+        options: ParseOptions<'_>,
+    ) -> ParseOutcome {
+        let (tree, suspended_at) = parse_cancellable(self, input.into_input(), old_tree, options);
+        let outcome = match (tree, suspended_at) {
+            (Some(tree), _) => ParseOutcome::Complete(tree),
+            (None, Some(current_byte_offset)) => ParseOutcome::Suspended(ParserSuspension {
+                parser: self.detach(),
+                current_byte_offset,
+            }),
+            (None, None) => {
+                unsafe { ts_parser_reset(self.ptr) }
+                ParseOutcome::Failed
+            }
+        };
+        // the suspended parse is discarded if the logger panicked
+        self.resume_logger_panic();
+        outcome
+    }
+
+    /// Replace this parser with a fresh parser with the same grammar, included ranges and logger
+    /// and return the old parser.
+    fn detach(&mut self) -> Parser {
+        let mut parser = Parser::new();
+        unsafe {
+            if let Some(grammar) = ts_parser_language(self.ptr) {
+                ts_parser_set_language(parser.ptr, Some(grammar));
+                parser.grammar = self.grammar.clone();
+            }
+            let mut count = 0;
+            let ranges = ts_parser_included_ranges(self.ptr, &mut count);
+            ts_parser_set_included_ranges(parser.ptr, ranges, count);
+        }
+        if let Some(logger) = self.logger.take() {
+            unsafe { ts_parser_set_logger(self.ptr, RawLogger::default()) };
+            parser.install_logger(logger);
+        }
+        mem::swap(self, &mut parser);
+        parser
+    }
+
+    fn parse_with_options_impl<I: Input>(
+        &mut self,
+        mut input: I,
+        old_tree: Option<&Tree>,
         mut options: ParseOptions<'_>,
     ) -> Option<Tree> {
-        let mut input = input.into_input();
+        let (encoding, decode) = input.encoding().raw();
         unsafe extern "C" fn read<C: Input>(
             payload: NonNull<c_void>,
             byte_index: u32,
@@ -166,8 +342,8 @@ impl Parser {
         let raw_input = ParserInputRaw {
             payload: NonNull::from(&mut input).cast(),
             read: read::<I>,
-            encoding: InputEncoding::Utf8,
-            decode: None,
+            encoding,
+            decode,
         };
 
         // The payload is a thin pointer to the fat pointer stored in options.callback.
@@ -230,10 +406,103 @@ unsafe impl Send for Parser {}
 
 impl Drop for Parser {
     fn drop(&mut self) {
+        // Debugging output must not leak into other users of the cached parser.
+        self.remove_logger();
+        self.stop_printing_dot_graphs();
+        // The grammar may be unloaded by its registry once the parser is dropped.
+        if self.grammar.is_loaded() {
+            unsafe { ts_parser_set_language(self.ptr, None) };
+        }
         PARSER_CACHE.set(Some(RawParser { ptr: self.ptr }));
     }
 }
 
+/// Run a parse which can be cancelled by the callback of `options`. Returns the byte offset at
+/// which parsing was cancelled if the callback cancelled it.
+fn parse_cancellable<I: Input>(
+    parser: &mut Parser,
+    input: I,
+    old_tree: Option<&Tree>,
+    options: ParseOptions<'_>,
+) -> (Option<Tree>, Option<u32>) {
+    let mut cancelled_at = None;
+    let callback = options.callback;
+    let mut check = |state: &ParseState| {
+        let flow = callback(state);
+        if flow.is_break() {
+            cancelled_at = Some(state.current_byte_offset);
+        }
+        flow
+    };
+    let tree = parser.parse_with_options_impl(input, old_tree, ParseOptions::new(&mut check));
+    (tree, cancelled_at)
+}
+
+/// The result of [`Parser::parse_resumable`] and [`ParserSuspension::resume`].
+#[derive(Debug)]
+pub enum ParseOutcome {
+    /// Parsing finished.
+    Complete(Tree),
+    /// Parsing was cancelled by the progress callback and can be continued with
+    /// [`ParserSuspension::resume`].
+    Suspended(ParserSuspension),
+    /// Parsing failed, for example because no grammar was set or the external scanner failed.
+    Failed,
+}
+
+/// A parse which was cancelled by [`Parser::parse_resumable`] and kept its state.
+///
+/// The suspended parse owns the parser which holds its state. Dropping the suspension discards
+/// the partial parse.
+pub struct ParserSuspension {
+    parser: Parser,
+    current_byte_offset: u32,
+}
+
+impl ParserSuspension {
+    /// The byte offset the parser had reached when it was suspended.
+    pub fn current_byte_offset(&self) -> u32 {
+        self.current_byte_offset
+    }
+
+    /// Continue parsing where the parse left off.
+    ///
+    /// `input` must contain the same text that was parsed before the parse was suspended. The old
+    /// tree passed to [`Parser::parse_resumable`] is retained by the suspended parse.
+    pub fn resume<I: Input>(
+        mut self,
+        input: impl IntoInput<Input = I>,
+        options: ParseOptions<'_>,
+    ) -> ParseOutcome {
+        let (tree, suspended_at) =
+            parse_cancellable(&mut self.parser, input.into_input(), None, options);
+        self.parser.resume_logger_panic();
+        match (tree, suspended_at) {
+            (Some(tree), _) => ParseOutcome::Complete(tree),
+            (None, Some(current_byte_offset)) => {
+                self.current_byte_offset = current_byte_offset;
+                ParseOutcome::Suspended(self)
+            }
+            (None, None) => ParseOutcome::Failed,
+        }
+    }
+}
+
+impl fmt::Debug for ParserSuspension {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("ParserSuspension")
+            .field("current_byte_offset", &self.current_byte_offset)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Drop for ParserSuspension {
+    fn drop(&mut self) {
+        // The parser is cached once it's dropped, the next user must not continue this parse.
+        unsafe { ts_parser_reset(self.parser.ptr) }
+    }
+}
+
 /// State passed to the progress callback during parsing.
 #[derive(Debug, Clone, Copy)]
 pub struct ParseState {
@@ -254,6 +523,33 @@ impl<'a> ParseOptions<'a> {
     }
 }
 
+/// The kind of message passed to the logger set with [`Parser::set_logger`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LogType {
+    /// A message about the parser's actions: shifts, reductions, error
+    /// recovery and so on.
+    Parse,
+    /// A message about the characters consumed by the lexer.
+    Lex,
+}
+
+// `TSLogger`
+#[repr(C)]
+#[derive(Debug)]
+struct RawLogger {
+    payload: *mut c_void,
+    log: Option<unsafe extern "C" fn(payload: *mut c_void, log_type: c_int, buffer: *const c_char)>,
+}
+
+impl Default for RawLogger {
+    fn default() -> Self {
+        RawLogger {
+            payload: ptr::null_mut(),
+            log: None,
+        }
+    }
+}
+
 /// An error that occurred when trying to assign an incompatible [`Grammar`] to
 /// a [`Parser`].
 #[derive(Debug, PartialEq, Eq)]
@@ -275,7 +571,7 @@ type TreeSitterReadFn = unsafe extern "C" fn(
 
 /// A function that reads one code point from the given string, returning the number of bytes
 /// consumed.
-type DecodeInputFn =
+pub(crate) type DecodeInputFn =
     unsafe extern "C" fn(string: *const u8, length: u32, code_point: *const i32) -> u32;
 
 #[repr(C)]
@@ -331,7 +627,7 @@ extern "C" {
     /// an incompatible version of the Tree-sitter CLI. Check the language's version using
     /// `ts_language_version` and compare it to this library's `TREE_SITTER_LANGUAGE_VERSION`
     /// and `TREE_SITTER_MIN_COMPATIBLE_LANGUAGE_VERSION` constants.
-    fn ts_parser_set_language(parser: NonNull<ParserData>, language: Grammar) -> bool;
+    fn ts_parser_set_language(parser: NonNull<ParserData>, language: Option<Grammar>) -> bool;
     /// Set the ranges of text that the parser should include when parsing. By default, the parser
     /// will always include entire documents. This function allows you to parse only a *portion*
     /// of a document but still return a syntax tree whose ranges match up with the document as a
@@ -350,6 +646,33 @@ extern "C" {
         count: u32,
     ) -> bool;
 
+    /// Set the logger that a parser should use during parsing. The parser does
+    /// not take ownership over the logger payload. If a logger was previously
+    /// assigned, the caller is responsible for releasing any memory owned by
+    /// the previous logger.
+    fn ts_parser_set_logger(parser: NonNull<ParserData>, logger: RawLogger);
+
+    /// Set the file descriptor to which the parser should write debugging
+    /// graphs during parsing. The graphs are formatted in the DOT language.
+    /// You can turn off this logging by passing a negative number. The parser
+    /// takes ownership of the file descriptor and closes it when it is
+    /// replaced.
+    fn ts_parser_print_dot_graphs(parser: NonNull<ParserData>, fd: c_int);
+
+    /// Get the parser's current language.
+    fn ts_parser_language(parser: NonNull<ParserData>) -> Option<Grammar>;
+    /// Get the ranges of text that the parser will include when parsing. The returned pointer is
+    /// owned by the parser. The caller should not free it or write to it. The length of the array
+    /// will be written to the given `count` pointer.
+    fn ts_parser_included_ranges(parser: NonNull<ParserData>, count: &mut u32) -> *const Range;
+
+    /// Instruct the parser to start the next parse from the beginning. If the parser previously
+    /// failed because of a timeout or a cancellation, then by default, it will resume where it
+    /// left off on the next call to `ts_parser_parse` or other parsing functions. If you don't
+    /// want to resume, and instead intend to use this parser to parse some other document, you
+    /// must call `ts_parser_reset` first.
+    fn ts_parser_reset(parser: NonNull<ParserData>);
+
     fn ts_parser_parse(
         parser: NonNull<ParserData>,
         old_tree: Option<NonNull<SyntaxTreeData>>,
@@ -368,3 +691,141 @@ extern "C" {
         parse_options: RawParseOptions,
     ) -> Option<NonNull<SyntaxTreeData>>;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::panic::{catch_unwind, AssertUnwindSafe};
+    use std::sync::{Arc, Mutex};
+
+    use std::ops::ControlFlow;
+
+    use crate::test_grammars::python_grammar;
+    use crate::{LogType, ParseOptions, ParseOutcome, Parser, Tree};
+
+    #[test]
+    fn logger() {
+        let messages = Arc::new(Mutex::new(Vec::new()));
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        let log = messages.clone();
+        parser.set_logger(move |log_type, message| {
+            log.lock().unwrap().push((log_type, message.to_owned()))
+        });
+        parser.parse("x = 1\n", None).unwrap();
+
+        let logged = messages.lock().unwrap().clone();
+        assert!(logged.iter().any(|(ty, _)| *ty == LogType::Lex));
+        assert!(logged
+            .iter()
+            .any(|(ty, msg)| *ty == LogType::Parse && msg.starts_with("shift")));
+
+        parser.remove_logger();
+        messages.lock().unwrap().clear();
+        parser.parse("x = 1\n", None).unwrap();
+        assert!(messages.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn logger_panic_is_resumed() {
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        let calls = Arc::new(Mutex::new(0));
+        let count = calls.clone();
+        parser.set_logger(move |_, _| {
+            *count.lock().unwrap() += 1;
+            panic!("logger")
+        });
+        let panic = catch_unwind(AssertUnwindSafe(|| parser.parse("x = 1\n", None))).unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"logger"));
+        // the logger isn't called again after it panicked
+        assert_eq!(*calls.lock().unwrap(), 1);
+
+        let mut cancel = |_: &_| ControlFlow::Break(());
+        let panic = catch_unwind(AssertUnwindSafe(|| {
+            parser.parse_resumable("x = 1\n", None, ParseOptions::new(&mut cancel))
+        }))
+        .unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"logger"));
+
+        parser.remove_logger();
+        let tree = parser.parse("x = 1\n", None).unwrap();
+        assert_eq!(tree.root_node().end_byte(), 6);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn dot_graphs() {
+        let dir = std::env::temp_dir();
+        let parse_path = dir.join(format!("tree-house-parse-{}.dot", std::process::id()));
+        let tree_path = dir.join(format!("tree-house-tree-{}.dot", std::process::id()));
+
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        let file = std::fs::File::create(&parse_path).unwrap();
+        parser.print_dot_graphs(&file).unwrap();
+        let tree = parser.parse("x = 1\n", None).unwrap();
+        parser.stop_printing_dot_graphs();
+        drop(file);
+
+        let file = std::fs::File::create(&tree_path).unwrap();
+        tree.print_dot_graph(&file);
+        drop(file);
+
+        let parse_graphs = std::fs::read_to_string(&parse_path).unwrap();
+        let tree_graph = std::fs::read_to_string(&tree_path).unwrap();
+        std::fs::remove_file(parse_path).unwrap();
+        std::fs::remove_file(tree_path).unwrap();
+        assert!(parse_graphs.contains("digraph stack"), "{parse_graphs}");
+        assert!(tree_graph.starts_with("digraph tree"), "{tree_graph}");
+        assert!(tree_graph.contains("assignment"), "{tree_graph}");
+    }
+
+    #[test]
+    fn resume_suspended_parse() {
+        let src = "def f(x):\n    return [x, x + 1]\n\n".repeat(200);
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        let expected = parser.parse(&src, None).unwrap();
+
+        // suspend after every few progress callbacks
+        let mut calls = 0;
+        let mut suspend = |_: &_| {
+            calls += 1;
+            if calls % 3 == 0 {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
+        };
+        let mut outcome = parser.parse_resumable(&src, None, ParseOptions::new(&mut suspend));
+        let mut suspensions = 0;
+        let mut offset = 0;
+        let tree = loop {
+            match outcome {
+                ParseOutcome::Complete(tree) => break tree,
+                ParseOutcome::Suspended(suspension) => {
+                    assert!(suspension.current_byte_offset() >= offset);
+                    offset = suspension.current_byte_offset();
+                    suspensions += 1;
+                    // the parser can be used for other parses in the meantime
+                    let other = parser.parse("x = 1\n", None).unwrap();
+                    assert_eq!(other.root_node().end_byte(), 6);
+                    outcome = suspension.resume(&src, ParseOptions::new(&mut suspend));
+                }
+                ParseOutcome::Failed => panic!("parse failed"),
+            }
+        };
+        assert!(suspensions > 1, "{suspensions}");
+        assert_eq!(tree.root_node().end_byte() as usize, src.len());
+        assert_eq!(tree.root_node().child_count(), 200);
+        assert_eq!(Tree::changed_ranges(&expected, &tree).count(), 0);
+
+        // a cancelled parse which is not resumable must not leak into the next parse
+        let mut cancel = |_: &_| ControlFlow::Break(());
+        assert!(parser
+            .parse_with_options(&src, None, ParseOptions::new(&mut cancel))
+            .is_none());
+        let tree = parser.parse("x = 1\n", None).unwrap();
+        assert_eq!(tree.root_node().end_byte(), 6);
+    }
+}
@@ -3,10 +3,19 @@ use std::ops::Range;
 use std::ptr::NonNull;
 use std::{slice, str};
 
-use crate::query::predicate::TextPredicate;
-pub use crate::query::predicate::{InvalidPredicateError, Predicate};
+use regex_cursor::engines::meta::Regex;
+
+use crate::grammar_registry::GrammarRef;
+pub use crate::query::mask::{CaptureMask, PatternMask};
+use crate::query::predicate::{CustomPredicate, TextPredicate};
+pub use crate::query::predicate::{
+    InputCursor, InvalidPredicateError, Predicate, PredicateArg, PredicateEvaluation,
+};
 use crate::Grammar;
 
+pub mod ast;
+mod lua_pattern;
+mod mask;
 mod predicate;
 mod property;
 
@@ -21,9 +30,75 @@ pub enum UserPredicate<'a> {
         key: &'a str,
         val: Option<&'a str>,
     },
+    /// `(#offset! @capture start_row start_col end_row end_col)` moves the
+    /// start and end of the captured range by the given number of rows and
+    /// columns.
+    Offset {
+        capture: Capture,
+        capture_name: &'a str,
+        start_row: i32,
+        start_col: i32,
+        end_row: i32,
+        end_col: i32,
+    },
+    /// `(#gsub! @capture "pattern" "replacement")` replaces all matches of a
+    /// Lua pattern in the captured text.
+    ///
+    /// `regex` is the Lua pattern translated to a regex. The replacement uses
+    /// Lua's syntax: `%0` to `%9` refer to the match and its groups and `%%` is
+    /// a literal `%`.
+    Gsub {
+        capture: Capture,
+        capture_name: &'a str,
+        /// The Lua pattern as written in the query.
+        pattern: &'a str,
+        regex: Regex,
+        replacement: &'a str,
+    },
+    /// `(#strip! @capture "pattern")` removes all matches of a Lua pattern
+    /// from the captured text.
+    ///
+    /// `regex` is the Lua pattern translated to a regex.
+    Strip {
+        capture: Capture,
+        capture_name: &'a str,
+        /// The Lua pattern as written in the query.
+        pattern: &'a str,
+        regex: Regex,
+    },
+    /// `(#trim! @capture [start_linewise start_charwise end_linewise end_charwise])`
+    /// trims whitespace from the captured range.
+    ///
+    /// Linewise trimming removes blank lines while charwise trimming removes
+    /// all whitespace. Without the optional arguments only blank lines at the
+    /// end are trimmed.
+    Trim {
+        capture: Capture,
+        capture_name: &'a str,
+        start_linewise: bool,
+        start_charwise: bool,
+        end_linewise: bool,
+        end_charwise: bool,
+    },
     Other(Predicate<'a>),
 }
 
+impl UserPredicate<'_> {
+    /// The name of the predicate without the leading `#`.
+    pub fn name(&self) -> &str {
+        match self {
+            UserPredicate::IsPropertySet { negate: true, .. } => "is-not?",
+            UserPredicate::IsPropertySet { negate: false, .. } => "is?",
+            UserPredicate::SetProperty { .. } => "set!",
+            UserPredicate::Offset { .. } => "offset!",
+            UserPredicate::Gsub { .. } => "gsub!",
+            UserPredicate::Strip { .. } => "strip!",
+            UserPredicate::Trim { .. } => "trim!",
+            UserPredicate::Other(predicate) => predicate.name(),
+        }
+    }
+}
+
 impl Display for UserPredicate<'_> {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match *self {
@@ -36,6 +111,40 @@ impl Display for UserPredicate<'_> {
                 let spacer = if val.is_some() { " " } else { "" };
                 write!(f, "(#set! {key}{spacer}{})", val.unwrap_or(""))
             }
+            UserPredicate::Offset {
+                capture_name,
+                start_row,
+                start_col,
+                end_row,
+                end_col,
+                ..
+            } => write!(
+                f,
+                "(#offset! @{capture_name} {start_row} {start_col} {end_row} {end_col})"
+            ),
+            UserPredicate::Gsub {
+                capture_name,
+                pattern,
+                replacement,
+                ..
+            } => write!(f, "(#gsub! @{capture_name} {pattern:?} {replacement:?})"),
+            UserPredicate::Strip {
+                capture_name,
+                pattern,
+                ..
+            } => write!(f, "(#strip! @{capture_name} {pattern:?})"),
+            UserPredicate::Trim {
+                capture_name,
+                start_linewise,
+                start_charwise,
+                end_linewise,
+                end_charwise,
+                ..
+            } => write!(
+                f,
+                "(#trim! @{capture_name} {} {} {} {})",
+                start_linewise as u8, start_charwise as u8, end_linewise as u8, end_charwise as u8
+            ),
             UserPredicate::Other(ref predicate) => {
                 write!(f, "#{}", predicate.name())
             }
@@ -44,6 +153,7 @@ impl Display for UserPredicate<'_> {
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Pattern(pub(crate) u32);
 
 impl Pattern {
@@ -67,7 +177,11 @@ pub struct Query {
     num_captures: u32,
     num_strings: u32,
     text_predicates: Vec<TextPredicate>,
+    /// Predicates evaluated by functions registered with
+    /// [`Query::register_predicate`], sorted by pattern.
+    custom_predicates: Vec<CustomPredicate>,
     patterns: Box<[PatternData]>,
+    grammar: GrammarRef,
 }
 
 unsafe impl Send for Query {}
@@ -85,134 +199,163 @@ impl Query {
         source: &str,
         mut custom_predicate: impl FnMut(Pattern, UserPredicate) -> Result<(), InvalidPredicateError>,
     ) -> Result<Self, ParseError> {
-        assert!(
-            source.len() <= i32::MAX as usize,
-            "TreeSitter queries must be smaller then 2 GiB (is {})",
-            source.len() as f64 / 1024.0 / 1024.0 / 1024.0
-        );
-        let mut error_offset = 0u32;
-        let mut error_kind = RawQueryError::None;
-        let bytes = source.as_bytes();
-
-        // Compile the query.
-        let ptr = unsafe {
-            ts_query_new(
-                grammar,
-                bytes.as_ptr(),
-                bytes.len() as u32,
-                &mut error_offset,
-                &mut error_kind,
-            )
-        };
+        let raw = compile(grammar, source)
+            .map_err(|(offset, error_kind)| compile_error(source, offset, error_kind))?;
+        let mut query = Query::from_raw(raw);
+        query.grammar = GrammarRef::new(grammar);
+        let patterns: Result<_, ParseError> = (0..query.patterns_len())
+            .map(|pattern| {
+                query
+                    .parse_pattern_predicates(Pattern(pattern), &mut custom_predicate, Err)
+                    .map_err(|err| query.predicate_error(source, 0, Pattern(pattern), err))
+            })
+            .collect();
+        query.patterns = patterns?;
+        Ok(query)
+    }
 
-        let Some(raw) = ptr else {
-            let offset = error_offset as usize;
-            let error_word = || {
-                source[offset..]
-                    .chars()
-                    .take_while(|&c| c.is_alphanumeric() || matches!(c, '_' | '-'))
-                    .collect()
-            };
-            let err = match error_kind {
-                RawQueryError::NodeType => {
-                    let node: String = error_word();
-                    ParseError::InvalidNodeType {
-                        location: ParserErrorLocation::new(source, offset, node.chars().count()),
-                        node,
-                    }
-                }
-                RawQueryError::Field => {
-                    let field = error_word();
-                    ParseError::InvalidFieldName {
-                        location: ParserErrorLocation::new(source, offset, field.chars().count()),
-                        field,
-                    }
-                }
-                RawQueryError::Capture => {
-                    let capture = error_word();
-                    ParseError::InvalidCaptureName {
-                        location: ParserErrorLocation::new(source, offset, capture.chars().count()),
-                        capture,
-                    }
-                }
-                RawQueryError::Syntax => {
-                    ParseError::SyntaxError(ParserErrorLocation::new(source, offset, 0))
-                }
-                RawQueryError::Structure => {
-                    ParseError::ImpossiblePattern(ParserErrorLocation::new(source, offset, 0))
-                }
-                RawQueryError::None => {
-                    unreachable!("tree-sitter returned a null pointer but did not set an error")
-                }
-                RawQueryError::Language => unreachable!("should be handled at grammar load"),
-            };
-            return Err(err);
+    /// Check a query for errors.
+    ///
+    /// Unlike [`Query::new`], which stops at the first error, this collects the
+    /// errors of all patterns: invalid node types, fields, captures and
+    /// predicates as well as syntax errors. The errors are ordered by their
+    /// position in the query and an empty list means that the query is valid.
+    ///
+    /// If the query does not compile, each top-level pattern is compiled on its
+    /// own. A syntax error which affects the rest of the query (like an
+    /// unclosed parenthesis) hides the errors of the following patterns. In
+    /// that case the patterns passed to `custom_predicate` are not the
+    /// pattern indices of the full query.
+    pub fn diagnose(
+        grammar: Grammar,
+        source: &str,
+        mut custom_predicate: impl FnMut(Pattern, UserPredicate) -> Result<(), InvalidPredicateError>,
+    ) -> Vec<ParseError> {
+        let mut errors = Vec::new();
+        let (offset, error_kind) = match compile(grammar, source) {
+            Ok(raw) => {
+                Query::from_raw(raw).diagnose_predicates(
+                    source,
+                    0,
+                    &mut custom_predicate,
+                    &mut errors,
+                );
+                return errors;
+            }
+            Err(err) => err,
         };
+        // Compile the patterns one by one. The offsets of the errors are
+        // relative to the pattern and are moved to the pattern's position.
+        for range in top_level_patterns(source) {
+            match compile(grammar, &source[range.clone()]) {
+                Ok(raw) => Query::from_raw(raw).diagnose_predicates(
+                    source,
+                    range.start,
+                    &mut custom_predicate,
+                    &mut errors,
+                ),
+                Err((offset, error_kind)) => {
+                    errors.push(compile_error(source, range.start + offset, error_kind))
+                }
+            }
+        }
+        if errors.is_empty() {
+            errors.push(compile_error(source, offset, error_kind));
+        }
+        errors
+    }
 
+    fn from_raw(raw: NonNull<QueryData>) -> Query {
         // I am not going to bother with safety comments here, all of these are
         // safe as long as TS is not buggy because raw is a properly constructed query
         let num_captures = unsafe { ts_query_capture_count(raw) };
         let num_strings = unsafe { ts_query_string_count(raw) };
-        let num_patterns = unsafe { ts_query_pattern_count(raw) };
-
-        let mut query = Query {
+        Query {
             raw,
             num_captures,
             num_strings,
             text_predicates: Vec::new(),
+            custom_predicates: Vec::new(),
             patterns: Box::default(),
-        };
-        let patterns: Result<_, ParseError> = (0..num_patterns)
-            .map(|pattern| {
-                query
-                    .parse_pattern_predicates(Pattern(pattern), &mut custom_predicate)
-                    .map_err(|err| {
-                        let pattern_start =
-                            unsafe { ts_query_start_byte_for_pattern(query.raw, pattern) as usize };
-                        match err {
-                            InvalidPredicateError::UnknownPredicate { name } => {
-                                let offset = source[pattern_start..]
-                                    .find(&*name)
-                                    .expect("predicate name is a substring of the query text")
-                                    + pattern_start
-                                    // Subtract a byte for b'#'.
-                                    - 1;
-                                ParseError::InvalidPredicate {
-                                    message: format!("unknown predicate #{name}"),
-                                    location: ParserErrorLocation::new(
-                                        source,
-                                        offset,
-                                        // Add one char for the '#'.
-                                        name.chars().count() + 1,
-                                    ),
-                                }
-                            }
-                            InvalidPredicateError::UnknownProperty { property } => {
-                                // TODO: this is naive. We should ensure that it is within a
-                                // `#set!` or `#is(-not)?`.
-                                let offset = source[pattern_start..]
-                                    .find(&*property)
-                                    .expect("property name is a substring of the query text")
-                                    + pattern_start;
-                                ParseError::InvalidPredicate {
-                                    message: format!("unknown property '{property}'"),
-                                    location: ParserErrorLocation::new(
-                                        source,
-                                        offset,
-                                        property.chars().count(),
-                                    ),
-                                }
-                            }
-                            InvalidPredicateError::Other { msg } => ParseError::InvalidPredicate {
-                                message: msg.into(),
-                                location: ParserErrorLocation::new(source, pattern_start, 0),
-                            },
-                        }
-                    })
-            })
-            .collect();
-        query.patterns = patterns?;
-        Ok(query)
+            grammar: GrammarRef::default(),
+        }
+    }
+
+    fn patterns_len(&self) -> u32 {
+        unsafe { ts_query_pattern_count(self.raw) }
+    }
+
+    /// Reports the predicate errors of a query compiled from
+    /// `source[offset..]`.
+    fn diagnose_predicates(
+        &mut self,
+        source: &str,
+        offset: usize,
+        custom_predicate: &mut impl FnMut(Pattern, UserPredicate) -> Result<(), InvalidPredicateError>,
+        errors: &mut Vec<ParseError>,
+    ) {
+        let mut predicate_errors = Vec::new();
+        for pattern in 0..self.patterns_len() {
+            let pattern = Pattern(pattern);
+            // the returned result is always `Ok` since errors are reported
+            let _ = self.parse_pattern_predicates(pattern, &mut *custom_predicate, |err| {
+                predicate_errors.push(err);
+                Ok(())
+            });
+            errors.extend(
+                predicate_errors
+                    .drain(..)
+                    .map(|err| self.predicate_error(source, offset, pattern, err)),
+            );
+        }
+    }
+
+    /// Creates the error for an invalid predicate of a query compiled from
+    /// `source[source_offset..]`.
+    fn predicate_error(
+        &self,
+        source: &str,
+        source_offset: usize,
+        pattern: Pattern,
+        err: InvalidPredicateError,
+    ) -> ParseError {
+        let pattern_start = source_offset
+            + unsafe { ts_query_start_byte_for_pattern(self.raw, pattern.0) as usize };
+        match err {
+            InvalidPredicateError::UnknownPredicate { name } => {
+                let offset = source[pattern_start..]
+                    .find(&*name)
+                    .expect("predicate name is a substring of the query text")
+                    + pattern_start
+                    // Subtract a byte for b'#'.
+                    - 1;
+                ParseError::InvalidPredicate {
+                    message: format!("unknown predicate #{name}"),
+                    location: ParserErrorLocation::new(
+                        source,
+                        offset,
+                        // Add one char for the '#'.
+                        name.chars().count() + 1,
+                    ),
+                }
+            }
+            InvalidPredicateError::UnknownProperty { property } => {
+                let pattern_end = source_offset
+                    + unsafe { ts_query_end_byte_for_pattern(self.raw, pattern.0) as usize };
+                let offset = property_offset(&source[pattern_start..pattern_end], &property)
+                    .or_else(|| source[pattern_start..].find(&*property))
+                    .expect("property name is a substring of the query text")
+                    + pattern_start;
+                ParseError::InvalidPredicate {
+                    message: format!("unknown property '{property}'"),
+                    location: ParserErrorLocation::new(source, offset, property.chars().count()),
+                }
+            }
+            InvalidPredicateError::Other { msg } => ParseError::InvalidPredicate {
+                message: msg.into(),
+                location: ParserErrorLocation::new(source, pattern_start, 0),
+            },
+        }
     }
 
     #[inline]
@@ -285,6 +428,74 @@ impl Query {
         unsafe { ts_query_start_byte_for_pattern(self.raw, pattern.0) as usize }
     }
 
+    /// Get the byte offset where the given pattern ends in the query's
+    /// source.
+    #[doc(alias = "ts_query_end_byte_for_pattern")]
+    #[must_use]
+    pub fn end_byte_for_pattern(&self, pattern: Pattern) -> usize {
+        assert!(
+            pattern.0 < self.patterns.len() as u32,
+            "Pattern index is {pattern:?} but the pattern count is {}",
+            self.patterns.len(),
+        );
+        unsafe { ts_query_end_byte_for_pattern(self.raw, pattern.0) as usize }
+    }
+
+    /// Get how many nodes the given capture may capture within a single match
+    /// of the given pattern.
+    ///
+    /// Captures that don't occur in the pattern have the quantifier
+    /// [`Quantifier::Zero`].
+    #[doc(alias = "ts_query_capture_quantifier_for_id")]
+    #[must_use]
+    pub fn capture_quantifier(&self, pattern: Pattern, capture: Capture) -> Quantifier {
+        assert!(
+            pattern.0 < self.patterns.len() as u32,
+            "Pattern index is {pattern:?} but the pattern count is {}",
+            self.patterns.len(),
+        );
+        // need an assertions because the ts c api does not do bounds check
+        assert!(capture.0 < self.num_captures, "invalid capture index");
+        match unsafe { ts_query_capture_quantifier_for_id(self.raw, pattern.0, capture.0) } {
+            1 => Quantifier::ZeroOrOne,
+            2 => Quantifier::ZeroOrMore,
+            3 => Quantifier::One,
+            4 => Quantifier::OneOrMore,
+            _ => Quantifier::Zero,
+        }
+    }
+
+    /// Check if the given pattern within the query has a single root node.
+    #[doc(alias = "ts_query_is_pattern_rooted")]
+    #[must_use]
+    pub fn is_pattern_rooted(&self, pattern: Pattern) -> bool {
+        unsafe { ts_query_is_pattern_rooted(self.raw, pattern.0) }
+    }
+
+    /// Check if the given pattern within the query is "non-local".
+    ///
+    /// A non-local pattern has multiple root nodes and can match within a
+    /// repeating sequence of nodes, as specified by the grammar. Non-local
+    /// patterns disable certain optimizations that would otherwise be possible
+    /// when executing a query on a specific range of a syntax tree.
+    #[doc(alias = "ts_query_is_pattern_non_local")]
+    #[must_use]
+    pub fn is_pattern_non_local(&self, pattern: Pattern) -> bool {
+        unsafe { ts_query_is_pattern_non_local(self.raw, pattern.0) }
+    }
+
+    /// Check if the pattern step at the given byte offset of the query's
+    /// source is guaranteed to match once a previous step of its pattern
+    /// matched.
+    #[doc(alias = "ts_query_is_pattern_guaranteed_at_step")]
+    #[must_use]
+    pub fn is_pattern_guaranteed_at_step(&self, byte_offset: usize) -> bool {
+        let Ok(byte_offset) = u32::try_from(byte_offset) else {
+            return false;
+        };
+        unsafe { ts_query_is_pattern_guaranteed_at_step(self.raw, byte_offset) }
+    }
+
     /// Get the number of patterns in the query.
     #[must_use]
     pub fn pattern_count(&self) -> usize {
@@ -300,7 +511,8 @@ impl Query {
     ///
     /// This prevents the capture from being returned in matches, and also avoids
     /// any resource usage associated with recording the capture. Currently, there
-    /// is no way to undo this.
+    /// is no way to undo this. A [`CaptureMask`] disables captures for a single
+    /// execution of a query cursor instead.
     pub fn disable_capture(&mut self, name: &str) {
         let bytes = name.as_bytes();
         unsafe {
@@ -311,7 +523,9 @@ impl Query {
     /// Disable a certain pattern within a query.
     ///
     /// This prevents the pattern from matching and removes most of the overhead
-    /// associated with the pattern. Currently, there is no way to undo this.
+    /// associated with the pattern. Currently, there is no way to undo this. A
+    /// [`PatternMask`] disables patterns for a single execution of a query
+    /// cursor instead.
     pub fn disable_pattern(&mut self, pattern: Pattern) {
         unsafe {
             ts_query_disable_pattern(self.raw, pattern.0);
@@ -319,14 +533,177 @@ impl Query {
     }
 }
 
+/// Finds the offset of `property` within the `#set!`, `#is?` or `#is-not?`
+/// predicates of a pattern.
+fn property_offset(pattern: &str, property: &str) -> Option<usize> {
+    let file = ast::parse(pattern).ok()?;
+    let offset = file
+        .patterns
+        .iter()
+        .flat_map(|pattern| pattern.predicates())
+        .filter(|predicate| matches!(&*predicate.name.name, "set!" | "is?" | "is-not?"))
+        .flat_map(|predicate| &predicate.args)
+        .find_map(|arg| match arg {
+            ast::PredicateArg::Ident(ident) if ident.name == property => Some(ident.span.start),
+            // skip the opening quote
+            ast::PredicateArg::String(str) if str.value == property => Some(str.span.start + 1),
+            _ => None,
+        })?;
+    Some(offset as usize)
+}
+
+/// Compiles a query, returning the offset and kind of the error on failure.
+fn compile(grammar: Grammar, source: &str) -> Result<NonNull<QueryData>, (usize, RawQueryError)> {
+    assert!(
+        source.len() <= i32::MAX as usize,
+        "TreeSitter queries must be smaller then 2 GiB (is {})",
+        source.len() as f64 / 1024.0 / 1024.0 / 1024.0
+    );
+    let mut error_offset = 0u32;
+    let mut error_kind = RawQueryError::None;
+    let bytes = source.as_bytes();
+
+    // Compile the query.
+    let ptr = unsafe {
+        ts_query_new(
+            grammar,
+            bytes.as_ptr(),
+            bytes.len() as u32,
+            &mut error_offset,
+            &mut error_kind,
+        )
+    };
+    ptr.ok_or((error_offset as usize, error_kind))
+}
+
+fn compile_error(source: &str, offset: usize, error_kind: RawQueryError) -> ParseError {
+    let error_word = || {
+        source[offset..]
+            .chars()
+            .take_while(|&c| c.is_alphanumeric() || matches!(c, '_' | '-'))
+            .collect()
+    };
+    match error_kind {
+        RawQueryError::NodeType => {
+            let node: String = error_word();
+            ParseError::InvalidNodeType {
+                location: ParserErrorLocation::new(source, offset, node.chars().count()),
+                node,
+            }
+        }
+        RawQueryError::Field => {
+            let field = error_word();
+            ParseError::InvalidFieldName {
+                location: ParserErrorLocation::new(source, offset, field.chars().count()),
+                field,
+            }
+        }
+        RawQueryError::Capture => {
+            let capture = error_word();
+            ParseError::InvalidCaptureName {
+                location: ParserErrorLocation::new(source, offset, capture.chars().count()),
+                capture,
+            }
+        }
+        RawQueryError::Syntax => {
+            ParseError::SyntaxError(ParserErrorLocation::new(source, offset, 0))
+        }
+        RawQueryError::Structure => {
+            ParseError::ImpossiblePattern(ParserErrorLocation::new(source, offset, 0))
+        }
+        RawQueryError::None => {
+            unreachable!("tree-sitter returned a null pointer but did not set an error")
+        }
+        RawQueryError::Language => unreachable!("should be handled at grammar load"),
+    }
+}
+
+/// Splits a query into the byte ranges of its top-level patterns, including
+/// their captures and quantifiers. Comments between patterns are skipped.
+fn top_level_patterns(source: &str) -> Vec<Range<usize>> {
+    let bytes = source.as_bytes();
+    let mut patterns: Vec<Range<usize>> = Vec::new();
+    let mut depth = 0u32;
+    let mut i = 0;
+    while i < bytes.len() {
+        let token_start = i;
+        let depth_before = depth;
+        let c = bytes[i];
+        i += 1;
+        match c {
+            b';' => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+                continue;
+            }
+            _ if c.is_ascii_whitespace() => continue,
+            b'"' => {
+                while i < bytes.len() {
+                    match bytes[i] {
+                        b'\\' => i += 2,
+                        b'"' => {
+                            i += 1;
+                            break;
+                        }
+                        _ => i += 1,
+                    }
+                }
+                i = i.min(bytes.len());
+            }
+            b'(' | b'[' => depth += 1,
+            b')' | b']' => depth = depth.saturating_sub(1),
+            _ => {
+                while i < bytes.len()
+                    && !bytes[i].is_ascii_whitespace()
+                    && !b"()[]\";".contains(&bytes[i])
+                {
+                    i += 1;
+                }
+            }
+        }
+        // captures and quantifiers after a pattern belong to it
+        let continues_pattern =
+            depth_before != 0 || matches!(c, b'@' | b'*' | b'+' | b'?' | b')' | b']');
+        match patterns.last_mut() {
+            Some(pattern) if continues_pattern => pattern.end = i,
+            _ => patterns.push(token_start..i),
+        }
+    }
+    patterns
+}
+
 impl Drop for Query {
     fn drop(&mut self) {
         unsafe { ts_query_delete(self.raw) }
     }
 }
 
+/// How many nodes a capture may capture within a single match of a pattern.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Quantifier {
+    /// The capture does not occur in the pattern.
+    Zero,
+    /// The capture is optional (`?`).
+    ZeroOrOne,
+    /// The capture may occur any number of times (`*`).
+    ZeroOrMore,
+    /// The capture occurs exactly once.
+    One,
+    /// The capture occurs at least once (`+`).
+    OneOrMore,
+}
+
+impl Quantifier {
+    /// Whether the capture may capture more than one node within a match.
+    pub fn is_repeated(self) -> bool {
+        matches!(self, Quantifier::ZeroOrMore | Quantifier::OneOrMore)
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
 #[repr(transparent)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Capture(u32);
 
 impl Capture {
@@ -350,6 +727,8 @@ impl QueryStr {
 
 #[derive(Debug, PartialEq, Eq)]
 pub struct ParserErrorLocation {
+    /// at which byte offset in the query source the error starts
+    pub offset: u32,
     /// at which line the error occurred
     pub line: u32,
     /// at which codepoints/columns the errors starts in the line
@@ -396,6 +775,7 @@ impl ParserErrorLocation {
         }
 
         ParserErrorLocation {
+            offset: start as u32,
             line: line as u32,
             column: column as u32,
             len: len as u32,
@@ -469,6 +849,34 @@ pub enum ParseError {
     ImpossiblePattern(ParserErrorLocation),
 }
 
+impl ParseError {
+    /// The location of the error in the query source, if it is known.
+    pub fn location(&self) -> Option<&ParserErrorLocation> {
+        match self {
+            ParseError::UnexpectedEof => None,
+            ParseError::SyntaxError(location)
+            | ParseError::InvalidNodeType { location, .. }
+            | ParseError::InvalidFieldName { location, .. }
+            | ParseError::InvalidCaptureName { location, .. }
+            | ParseError::InvalidPredicate { location, .. }
+            | ParseError::ImpossiblePattern(location) => Some(location),
+        }
+    }
+
+    /// The location of the error in the query source, if it is known.
+    pub fn location_mut(&mut self) -> Option<&mut ParserErrorLocation> {
+        match self {
+            ParseError::UnexpectedEof => None,
+            ParseError::SyntaxError(location)
+            | ParseError::InvalidNodeType { location, .. }
+            | ParseError::InvalidFieldName { location, .. }
+            | ParseError::InvalidCaptureName { location, .. }
+            | ParseError::InvalidPredicate { location, .. }
+            | ParseError::ImpossiblePattern(location) => Some(location),
+        }
+    }
+}
+
 #[repr(C)]
 // warns about never being constructed but it's constructed by C code
 // and wrwitten into a mutable reference
@@ -513,9 +921,27 @@ extern "C" {
     /// source code strings.
     fn ts_query_start_byte_for_pattern(query: NonNull<QueryData>, pattern_index: u32) -> u32;
 
-    // fn ts_query_is_pattern_rooted(query: NonNull<QueryData>, pattern_index: u32) -> bool;
-    // fn ts_query_is_pattern_non_local(query: NonNull<QueryData>, pattern_index: u32) -> bool;
-    // fn ts_query_is_pattern_guaranteed_at_step(query: NonNull<QueryData>, byte_offset: u32) -> bool;
+    /// Get the byte offset where the given pattern ends in the query's
+    /// source.
+    fn ts_query_end_byte_for_pattern(query: NonNull<QueryData>, pattern_index: u32) -> u32;
+
+    /// Check if the given pattern in the query has a single root node.
+    fn ts_query_is_pattern_rooted(query: NonNull<QueryData>, pattern_index: u32) -> bool;
+    /// Check if the given pattern in the query is 'non local'.
+    fn ts_query_is_pattern_non_local(query: NonNull<QueryData>, pattern_index: u32) -> bool;
+    /// Check if a given pattern is guaranteed to match once a given step is
+    /// reached. The step is specified by its byte offset in the query's source
+    /// code.
+    fn ts_query_is_pattern_guaranteed_at_step(query: NonNull<QueryData>, byte_offset: u32) -> bool;
+
+    /// Get the quantifier of the query's captures. Each capture is associated
+    /// with a numeric id based on the order that it appeared in the query's
+    /// source.
+    fn ts_query_capture_quantifier_for_id(
+        query: NonNull<QueryData>,
+        pattern_index: u32,
+        capture_index: u32,
+    ) -> u32;
     /// Get the name and length of one of the query's captures, or one of the
     /// query's string literals. Each capture and string is associated with a
     /// numeric id based on the order that it appeared in the query's source.
@@ -543,3 +969,158 @@ extern "C" {
     /// associated with the pattern. Currently, there is no way to undo this.
     fn ts_query_disable_pattern(self_: NonNull<QueryData>, pattern_index: u32);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{InvalidPredicateError, ParseError, PredicateArg, Quantifier, UserPredicate};
+    use crate::test_grammars::python_grammar;
+    use crate::{Pattern, Query};
+
+    #[test]
+    fn pattern_introspection() {
+        let source = r#"(import_statement name: (dotted_name)+ @name) @import
+((identifier) @id (#eq? @id "x"))
+((expression_statement) (expression_statement))
+"#;
+        let query = Query::new(python_grammar(), source, |_, _| Ok(())).unwrap();
+        let import = query.get_capture("import").unwrap();
+        let name = query.get_capture("name").unwrap();
+        let id = query.get_capture("id").unwrap();
+
+        let first = Pattern(0);
+        assert_eq!(query.capture_quantifier(first, import), Quantifier::One);
+        assert_eq!(query.capture_quantifier(first, name), Quantifier::OneOrMore);
+        assert_eq!(query.capture_quantifier(first, id), Quantifier::Zero);
+        assert_eq!(query.start_byte_for_pattern(first), 0);
+        assert_eq!(
+            query.end_byte_for_pattern(first),
+            source.find('\n').unwrap() + 1
+        );
+        assert!(query.is_pattern_rooted(first));
+        assert!(!query.is_pattern_non_local(first));
+        assert!(query.pattern_predicates(first).next().is_none());
+
+        let second = Pattern(1);
+        assert_eq!(query.capture_quantifier(second, id), Quantifier::One);
+        let predicates: Vec<_> = query.pattern_predicates(second).collect();
+        assert_eq!(predicates.len(), 1);
+        assert_eq!(predicates[0].name(), "eq?");
+        let args: Vec<_> = predicates[0].args().collect();
+        assert!(matches!(args[0], PredicateArg::Capture(capture) if capture == id));
+        assert!(matches!(args[1], PredicateArg::String(str) if str.get(&query) == "x"));
+
+        let third = Pattern(2);
+        assert!(!query.is_pattern_rooted(third));
+        assert!(query.is_pattern_non_local(third));
+
+        let source = "(function_definition name: (identifier) @name body: (block) @body)";
+        let query = Query::new(python_grammar(), source, |_, _| Ok(())).unwrap();
+        assert!(!query.is_pattern_guaranteed_at_step(0));
+        let name = source.find("(identifier)").unwrap();
+        assert!(query.is_pattern_guaranteed_at_step(name));
+    }
+
+    #[test]
+    fn directives() {
+        let source = r#"((string) @a @b @c
+  (#offset! @a 0 1 0 -1)
+  (#gsub! @b "^%.(.*)" "%1")
+  (#strip! @c "^%s+")
+  (#trim! @a))
+"#;
+        let mut directives = Vec::new();
+        Query::new(python_grammar(), source, |_, predicate| {
+            if let UserPredicate::Strip { regex, .. } = &predicate {
+                // the Lua pattern is translated to a regex
+                assert!(regex.is_match(regex_cursor::Input::new(" \tx")));
+                assert!(!regex.is_match(regex_cursor::Input::new("%sx")));
+            }
+            directives.push(predicate.to_string());
+            Ok(())
+        })
+        .unwrap();
+        assert_eq!(
+            directives,
+            [
+                "(#offset! @a 0 1 0 -1)",
+                r#"(#gsub! @b "^%.(.*)" "%1")"#,
+                r#"(#strip! @c "^%s+")"#,
+                "(#trim! @a 0 0 1 0)"
+            ]
+        );
+
+        let invalid = [
+            r#"((string) @a (#offset! @a 0 x 0 0))"#,
+            r#"((string) @a (#gsub! @a "%f[%w]" ""))"#,
+            r#"((string) @a (#gsub! @a "a" "%x"))"#,
+            r#"((string) @a (#strip! @a "%f[%w]"))"#,
+            r#"((string) @a (#trim! @a 1 2))"#,
+        ];
+        for source in invalid {
+            assert!(Query::new(python_grammar(), source, |_, _| Ok(())).is_err());
+        }
+    }
+
+    #[test]
+    fn diagnose() {
+        let diagnose = |source| {
+            Query::diagnose(python_grammar(), source, |_, predicate| {
+                Err(InvalidPredicateError::unknown(predicate))
+            })
+        };
+        let source = r#"(identifier) @a
+(not_a_node) @b
+; a comment (with an unclosed parenthesis
+((identifier) @c (#eq? @c))
+(call wrong_field: (identifier))+
+((string) @d (#frobnicate! @d))
+"#;
+        let errors: Vec<_> = diagnose(source)
+            .iter()
+            .map(|err| match err {
+                ParseError::InvalidNodeType { node, location } => (node.clone(), location.line),
+                ParseError::InvalidFieldName { field, location } => (field.clone(), location.line),
+                ParseError::InvalidPredicate { message, location } => {
+                    (message.clone(), location.line)
+                }
+                err => panic!("unexpected error {err}"),
+            })
+            .collect();
+        assert_eq!(
+            errors,
+            [
+                ("not_a_node".to_owned(), 1),
+                ("expected 2 arguments for #eq?, got 1".to_owned(), 3),
+                ("wrong_field".to_owned(), 4),
+                ("unknown predicate #frobnicate!".to_owned(), 5),
+            ]
+        );
+        let offsets: Vec<_> = diagnose(source)
+            .iter()
+            .map(|err| err.location().unwrap().offset as usize)
+            .collect();
+        let expected_offsets = [
+            "not_a_node",
+            "((identifier) @c",
+            "wrong_field",
+            "#frobnicate!",
+        ]
+        .map(|text| source.find(text).unwrap());
+        assert_eq!(offsets, expected_offsets);
+
+        // an unclosed parenthesis swallows the rest of the query
+        let errors = diagnose("(not_a_node)\n(call (identifier)\n(string)\n");
+        assert_eq!(errors.len(), 2);
+        assert!(matches!(errors[0], ParseError::InvalidNodeType { .. }));
+        assert!(matches!(errors[1], ParseError::SyntaxError(_)));
+        assert_eq!(errors[1].location().unwrap().line, 2);
+
+        assert!(diagnose("(identifier) @a (string) @b").is_empty());
+
+        let source = "((identifier) @id (#set! id))";
+        let [ParseError::InvalidPredicate { location, .. }] = &*diagnose(source) else {
+            panic!("expected an invalid predicate");
+        };
+        assert_eq!(location.offset as usize, source.rfind("id").unwrap());
+    }
+}
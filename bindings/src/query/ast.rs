@@ -0,0 +1,607 @@
+//! A parser for the tree-sitter query language which does not depend on a
+//! grammar.
+//!
+//! [`Query::new`](crate::Query::new) compiles a query against a grammar and
+//! only keeps what is needed to run it. [`parse`] instead keeps the structure
+//! of the source (patterns, fields, quantifiers, anchors, captures, predicates
+//! and comments) along with the byte range of each element. This makes it
+//! suitable for tools like formatters and linters.
+
+use std::ops;
+
+use crate::query::{ParserErrorLocation, Quantifier};
+
+/// A byte range within the query source.
+pub type Span = ops::Range<u32>;
+
+/// A parsed query file, see [`parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct QueryFile {
+    /// The top-level patterns in the order they occur in the source.
+    pub patterns: Vec<Expr>,
+    /// All comments, including those within patterns, in source order.
+    pub comments: Vec<Comment>,
+}
+
+/// A `;` comment which extends to the end of the line.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Comment {
+    /// The text of the comment including the leading semicolons.
+    pub text: String,
+    pub span: Span,
+}
+
+/// A name: a node kind, field, capture or predicate name.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Ident {
+    /// The name without its sigil (`@`, `#` or `!`).
+    pub name: String,
+    /// The span of the name including its sigil.
+    pub span: Span,
+}
+
+/// A string literal.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Str {
+    /// The unescaped contents of the string.
+    pub value: String,
+    /// The span of the string including the quotes.
+    pub span: Span,
+}
+
+/// A pattern together with the field, quantifier and captures attached to it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Expr {
+    /// `field: pattern`
+    pub field: Option<Ident>,
+    pub kind: ExprKind,
+    /// The quantifier (`?`, `*` or `+`) following the pattern.
+    /// [`Quantifier::One`] if there is none.
+    pub quantifier: Quantifier,
+    /// `pattern @capture...`
+    pub captures: Vec<Ident>,
+    /// The span of the whole expression, including the field and captures.
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ExprKind {
+    /// `(kind children...)`, `(_ children...)` or `(supertype/kind ...)`.
+    Named(NamedNode),
+    /// `(MISSING)`, `(MISSING kind)` or `(MISSING "token")`.
+    Missing(Option<NodeName>),
+    /// `"token"`
+    Anonymous(Str),
+    /// `_`, any named or anonymous node.
+    Wildcard,
+    /// `((first) (second) ...)`, a sequence of sibling patterns.
+    Group(Vec<Child>),
+    /// `[(first) (second) ...]`, one of several patterns.
+    Alternation(Vec<Child>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NamedNode {
+    /// The kind of the node, `_` for any named node.
+    pub kind: Ident,
+    /// The supertype in `(supertype/kind)`.
+    pub supertype: Option<Ident>,
+    pub children: Vec<Child>,
+}
+
+/// The kind of a `MISSING` node.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum NodeName {
+    Named(Ident),
+    Anonymous(Str),
+}
+
+/// An element within a node, group or alternation.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Child {
+    Expr(Expr),
+    /// `.`, anchors the adjacent patterns to the first/last child or to
+    /// each other.
+    Anchor(Span),
+    /// `!field`, the node must not have a child with this field.
+    NegatedField(Ident),
+    Predicate(Predicate),
+}
+
+/// A predicate or directive like `(#eq? @capture "text")` or
+/// `(#set! key value)`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Predicate {
+    /// The name without the `#`, for example `eq?` or `set!`.
+    pub name: Ident,
+    pub args: Vec<PredicateArg>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PredicateArg {
+    Capture(Ident),
+    String(Str),
+    /// An unquoted argument like a property name or a number.
+    Ident(Ident),
+}
+
+impl PredicateArg {
+    pub fn span(&self) -> Span {
+        match self {
+            PredicateArg::Capture(ident) | PredicateArg::Ident(ident) => ident.span.clone(),
+            PredicateArg::String(str) => str.span.clone(),
+        }
+    }
+
+    /// The value of a string or unquoted argument.
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            PredicateArg::Capture(_) => None,
+            PredicateArg::String(str) => Some(&str.value),
+            PredicateArg::Ident(ident) => Some(&ident.name),
+        }
+    }
+}
+
+impl Expr {
+    /// The children of a named node, group or alternation.
+    pub fn children(&self) -> &[Child] {
+        match &self.kind {
+            ExprKind::Named(node) => &node.children,
+            ExprKind::Group(children) | ExprKind::Alternation(children) => children,
+            ExprKind::Missing(_) | ExprKind::Anonymous(_) | ExprKind::Wildcard => &[],
+        }
+    }
+
+    /// Calls `f` for this expression and all expressions nested within it in
+    /// source order.
+    pub fn walk<'a>(&'a self, f: &mut impl FnMut(&'a Expr)) {
+        f(self);
+        for child in self.children() {
+            if let Child::Expr(expr) = child {
+                expr.walk(f);
+            }
+        }
+    }
+
+    /// Iterates the predicates of this expression and all nested expressions.
+    pub fn predicates(&self) -> impl Iterator<Item = &Predicate> {
+        let mut predicates = Vec::new();
+        self.walk(&mut |expr| {
+            predicates.extend(expr.children().iter().filter_map(|child| match child {
+                Child::Predicate(predicate) => Some(predicate),
+                _ => None,
+            }))
+        });
+        predicates.into_iter()
+    }
+}
+
+/// An invalid query, see [`parse`].
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+#[error("{message}\n{location}")]
+pub struct SyntaxError {
+    pub message: &'static str,
+    pub location: ParserErrorLocation,
+}
+
+/// Parse the source of a query (`.scm` file).
+///
+/// Only the syntax of the query is checked: node kinds, fields and predicates
+/// are not validated.
+pub fn parse(source: &str) -> Result<QueryFile, SyntaxError> {
+    assert!(
+        source.len() <= u32::MAX as usize,
+        "queries must be smaller than 4 GiB"
+    );
+    let mut parser = Parser {
+        src: source,
+        pos: 0,
+        comments: Vec::new(),
+    };
+    let mut patterns = Vec::new();
+    loop {
+        parser.skip_trivia();
+        if parser.rest().is_empty() {
+            break;
+        }
+        let start = parser.pos;
+        match parser.child()? {
+            Child::Expr(expr) => patterns.push(expr),
+            Child::Predicate(_) => {
+                parser.pos = start;
+                return Err(parser.error("predicates must be within a pattern"));
+            }
+            Child::Anchor(_) | Child::NegatedField(_) => {
+                parser.pos = start;
+                return Err(parser.error("expected a pattern"));
+            }
+        }
+    }
+    Ok(QueryFile {
+        patterns,
+        comments: parser.comments,
+    })
+}
+
+fn is_ident_start(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-')
+}
+
+fn is_ident_char(c: char) -> bool {
+    c.is_alphanumeric() || matches!(c, '_' | '-' | '.' | '?' | '!')
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    pos: usize,
+    comments: Vec<Comment>,
+}
+
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> SyntaxError {
+        SyntaxError {
+            message,
+            location: ParserErrorLocation::new(self.src, self.pos, 0),
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.rest().chars().next()
+    }
+
+    fn span(&self, start: usize) -> Span {
+        start as u32..self.pos as u32
+    }
+
+    /// Skips whitespace and comments, recording the comments.
+    fn skip_trivia(&mut self) {
+        loop {
+            self.pos = self.src.len() - self.rest().trim_start().len();
+            if !self.rest().starts_with(';') {
+                break;
+            }
+            let start = self.pos;
+            self.pos += self.rest().find('\n').unwrap_or(self.rest().len());
+            let text = self.src[start..self.pos].trim_end_matches('\r');
+            self.comments.push(Comment {
+                text: text.to_owned(),
+                span: start as u32..(start + text.len()) as u32,
+            });
+        }
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_trivia();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parses a name after a sigil of `sigil_len` bytes.
+    fn ident(&mut self, sigil_len: usize) -> Result<Ident, SyntaxError> {
+        let start = self.pos;
+        self.pos += sigil_len;
+        if !self.peek().is_some_and(is_ident_start) {
+            return Err(self.error("expected a name"));
+        }
+        let len = self
+            .rest()
+            .find(|c| !is_ident_char(c))
+            .unwrap_or(self.rest().len());
+        self.pos += len;
+        Ok(Ident {
+            name: self.src[start + sigil_len..self.pos].to_owned(),
+            span: self.span(start),
+        })
+    }
+
+    fn string(&mut self) -> Result<Str, SyntaxError> {
+        let start = self.pos;
+        self.pos += 1;
+        let mut value = String::new();
+        let mut chars = self.rest().char_indices();
+        loop {
+            let Some((i, c)) = chars.next() else {
+                self.pos = self.src.len();
+                return Err(self.error("unclosed string"));
+            };
+            match c {
+                '"' => {
+                    self.pos += i + 1;
+                    break;
+                }
+                '\\' => match chars.next() {
+                    Some((_, 'n')) => value.push('\n'),
+                    Some((_, 'r')) => value.push('\r'),
+                    Some((_, 't')) => value.push('\t'),
+                    Some((_, '0')) => value.push('\0'),
+                    Some((_, c)) => value.push(c),
+                    None => {}
+                },
+                c => value.push(c),
+            }
+        }
+        Ok(Str {
+            value,
+            span: self.span(start),
+        })
+    }
+
+    /// Parses the children of a node, group or alternation up to `close`.
+    fn children(&mut self, close: char) -> Result<Vec<Child>, SyntaxError> {
+        let mut children = Vec::new();
+        while !self.eat(close) {
+            if self.rest().is_empty() {
+                return Err(self.error(if close == ')' {
+                    "unclosed `(`"
+                } else {
+                    "unclosed `[`"
+                }));
+            }
+            children.push(self.child()?);
+        }
+        Ok(children)
+    }
+
+    fn child(&mut self) -> Result<Child, SyntaxError> {
+        self.skip_trivia();
+        let start = self.pos;
+        match self.peek() {
+            Some('.') => {
+                self.pos += 1;
+                return Ok(Child::Anchor(self.span(start)));
+            }
+            Some('!') => return Ok(Child::NegatedField(self.ident(1)?)),
+            Some('(') if self.rest()[1..].trim_start().starts_with('#') => {
+                return self.predicate().map(Child::Predicate)
+            }
+            _ => (),
+        }
+        // `field: pattern`
+        let mut field = None;
+        if self.peek().is_some_and(is_ident_start) {
+            let ident = self.ident(0)?;
+            if self.rest().trim_start().starts_with(':') {
+                self.pos = self.src.len() - self.rest().trim_start().len() + 1;
+                self.skip_trivia();
+                field = Some(ident);
+            } else if ident.name == "_" {
+                return self
+                    .suffixes(start, None, ExprKind::Wildcard)
+                    .map(Child::Expr);
+            } else {
+                self.pos = start;
+                return Err(self.error("expected a pattern"));
+            }
+        }
+        let kind = match self.peek() {
+            Some('(') => {
+                self.pos += 1;
+                self.skip_trivia();
+                match self.peek() {
+                    Some('(' | '[' | '"' | '.') => ExprKind::Group(self.children(')')?),
+                    Some(c) if is_ident_start(c) => self.node()?,
+                    _ => return Err(self.error("expected a node kind or pattern")),
+                }
+            }
+            Some('[') => {
+                self.pos += 1;
+                ExprKind::Alternation(self.children(']')?)
+            }
+            Some('"') => ExprKind::Anonymous(self.string()?),
+            Some('_') if !self.rest()[1..].starts_with(is_ident_char) => {
+                self.pos += 1;
+                ExprKind::Wildcard
+            }
+            _ => return Err(self.error("expected a pattern")),
+        };
+        self.suffixes(start, field, kind).map(Child::Expr)
+    }
+
+    /// Parses the contents of a named node after the `(`.
+    fn node(&mut self) -> Result<ExprKind, SyntaxError> {
+        let mut kind = self.ident(0)?;
+        if kind.name == "MISSING" {
+            self.skip_trivia();
+            let name = match self.peek() {
+                Some('"') => Some(NodeName::Anonymous(self.string()?)),
+                Some(c) if is_ident_start(c) => Some(NodeName::Named(self.ident(0)?)),
+                _ => None,
+            };
+            if !self.eat(')') {
+                return Err(self.error("expected `)`"));
+            }
+            return Ok(ExprKind::Missing(name));
+        }
+        let mut supertype = None;
+        if self.rest().starts_with('/') {
+            self.pos += 1;
+            supertype = Some(kind);
+            kind = self.ident(0)?;
+        }
+        Ok(ExprKind::Named(NamedNode {
+            kind,
+            supertype,
+            children: self.children(')')?,
+        }))
+    }
+
+    /// Parses the quantifier and captures following a pattern.
+    fn suffixes(
+        &mut self,
+        start: usize,
+        field: Option<Ident>,
+        kind: ExprKind,
+    ) -> Result<Expr, SyntaxError> {
+        let mut end = self.pos;
+        self.skip_trivia();
+        let quantifier = match self.peek() {
+            Some('?') => Quantifier::ZeroOrOne,
+            Some('*') => Quantifier::ZeroOrMore,
+            Some('+') => Quantifier::OneOrMore,
+            _ => Quantifier::One,
+        };
+        if quantifier != Quantifier::One {
+            self.pos += 1;
+            end = self.pos;
+            self.skip_trivia();
+        }
+        let mut captures = Vec::new();
+        while self.peek() == Some('@') {
+            captures.push(self.ident(1)?);
+            end = self.pos;
+            self.skip_trivia();
+        }
+        // Leave the trivia after the expression to the caller.
+        self.comments
+            .retain(|comment| comment.span.start < end as u32);
+        self.pos = end;
+        Ok(Expr {
+            field,
+            kind,
+            quantifier,
+            captures,
+            span: start as u32..end as u32,
+        })
+    }
+
+    fn predicate(&mut self) -> Result<Predicate, SyntaxError> {
+        let start = self.pos;
+        self.pos += 1;
+        self.skip_trivia();
+        let name = self.ident(1)?;
+        let mut args = Vec::new();
+        loop {
+            self.skip_trivia();
+            match self.peek() {
+                Some(')') => {
+                    self.pos += 1;
+                    break;
+                }
+                Some('@') => args.push(PredicateArg::Capture(self.ident(1)?)),
+                Some('"') => args.push(PredicateArg::String(self.string()?)),
+                Some(c) if is_ident_start(c) => args.push(PredicateArg::Ident(self.ident(0)?)),
+                None => return Err(self.error("unclosed `(`")),
+                _ => return Err(self.error("expected a predicate argument")),
+            }
+        }
+        Ok(Predicate {
+            name,
+            args,
+            span: self.span(start),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, Child, ExprKind, NodeName, PredicateArg};
+    use crate::query::Quantifier;
+
+    #[test]
+    fn parse_query() {
+        let source = r#"; highlights
+(function_definition
+  name: (identifier) @function ; the name
+  !type_parameters
+  body: (block . (expression_statement)* @body.first))
+
+[
+  "def"
+  (MISSING ":")
+  (expression/identifier)
+] @keyword
+
+((identifier) @constant
+ (#match? @constant "^[A-Z]\\w*$")
+ (#set! priority 100))
+_ @any
+"#;
+        let file = parse(source).unwrap();
+        let comments: Vec<_> = file.comments.iter().map(|c| &*c.text).collect();
+        assert_eq!(comments, ["; highlights", "; the name"]);
+        assert_eq!(file.patterns.len(), 4);
+
+        let function = &file.patterns[0];
+        let ExprKind::Named(node) = &function.kind else {
+            panic!("expected a named node");
+        };
+        assert_eq!(node.kind.name, "function_definition");
+        assert_eq!(
+            &source[function.span.start as usize..][..20],
+            "(function_definition"
+        );
+        assert!(source[..function.span.end as usize].ends_with("@body.first))"));
+        let [Child::Expr(name), Child::NegatedField(negated), Child::Expr(body)] = &*node.children
+        else {
+            panic!("unexpected children {:?}", node.children);
+        };
+        assert_eq!(name.field.as_ref().unwrap().name, "name");
+        assert_eq!(name.captures[0].name, "function");
+        assert_eq!(
+            &source[name.captures[0].span.start as usize..][..9],
+            "@function"
+        );
+        assert_eq!(negated.name, "type_parameters");
+        let [Child::Anchor(_), Child::Expr(statement)] = body.children() else {
+            panic!("unexpected children {:?}", body.children());
+        };
+        assert_eq!(statement.quantifier, Quantifier::ZeroOrMore);
+        assert_eq!(statement.captures[0].name, "body.first");
+
+        let keyword = &file.patterns[1];
+        let ExprKind::Alternation(alternatives) = &keyword.kind else {
+            panic!("expected an alternation");
+        };
+        assert_eq!(alternatives.len(), 3);
+        assert!(matches!(
+            &alternatives[1],
+            Child::Expr(expr)
+                if matches!(&expr.kind, ExprKind::Missing(Some(NodeName::Anonymous(str))) if str.value == ":")
+        ));
+        let Child::Expr(subtype) = &alternatives[2] else {
+            panic!("expected a pattern");
+        };
+        let ExprKind::Named(subtype) = &subtype.kind else {
+            panic!("expected a named node");
+        };
+        assert_eq!(subtype.supertype.as_ref().unwrap().name, "expression");
+        assert_eq!(subtype.kind.name, "identifier");
+
+        let constant = &file.patterns[2];
+        assert!(matches!(constant.kind, ExprKind::Group(_)));
+        let predicates: Vec<_> = constant.predicates().collect();
+        assert_eq!(predicates[0].name.name, "match?");
+        assert!(matches!(&predicates[0].args[0], PredicateArg::Capture(c) if c.name == "constant"));
+        assert_eq!(predicates[0].args[1].as_str(), Some(r"^[A-Z]\w*$"));
+        assert_eq!(predicates[1].name.name, "set!");
+        assert_eq!(predicates[1].args[1].as_str(), Some("100"));
+
+        assert!(matches!(file.patterns[3].kind, ExprKind::Wildcard));
+    }
+
+    #[test]
+    fn syntax_errors() {
+        let error = |source| parse(source).unwrap_err();
+        let err = error("(identifier");
+        assert_eq!(err.message, "unclosed `(`");
+        let err = error("(call\n  (identifier) @\n)");
+        assert_eq!(err.message, "expected a name");
+        assert_eq!((err.location.line, err.location.column), (1, 16));
+        assert_eq!(
+            error("(#eq? @a @b)").message,
+            "predicates must be within a pattern"
+        );
+        assert_eq!(error("identifier").message, "expected a pattern");
+        assert_eq!(error("(a \"b)").message, "unclosed string");
+    }
+}
@@ -0,0 +1,153 @@
+//! Translation of Lua patterns, used by the `#lua-match?` predicate of queries
+//! written for Neovim, into regular expressions.
+
+/// Characters with a special meaning in regular expressions, see
+/// `regex_syntax::is_meta_character`.
+fn is_meta_character(c: char) -> bool {
+    r"\.+*?()|[]{}^$#&-~".contains(c)
+}
+
+fn push_literal(regex: &mut String, c: char) {
+    if is_meta_character(c) {
+        regex.push('\\');
+    }
+    regex.push(c);
+}
+
+/// Escapes `str` so that it is matched literally by a regex.
+pub(super) fn escape(str: &str) -> String {
+    let mut regex = String::with_capacity(str.len());
+    for c in str.chars() {
+        push_literal(&mut regex, c);
+    }
+    regex
+}
+
+/// Pushes the item for `%c` where `c` is the character after the `%`.
+fn push_escape(regex: &mut String, c: char, in_set: bool) -> Result<(), String> {
+    // Lua's character classes are defined by the C locale so they only
+    // contain ASCII characters.
+    let class = match c.to_ascii_lowercase() {
+        'a' => "A-Za-z",
+        'c' => r"\x00-\x1F\x7F",
+        'd' => "0-9",
+        'g' => r"\x21-\x7E",
+        'l' => "a-z",
+        'p' => r"\x21-\x2F\x3A-\x40\x5B-\x60\x7B-\x7E",
+        's' => r"\x09-\x0D\x20",
+        'u' => "A-Z",
+        'w' => "A-Za-z0-9",
+        'x' => "0-9A-Fa-f",
+        // `%b`, `%f` and back references (`%1`) have no regex equivalent
+        _ if c.is_ascii_alphanumeric() => return Err(format!("unsupported pattern item '%{c}'")),
+        _ => {
+            push_literal(regex, c);
+            return Ok(());
+        }
+    };
+    let negated = c.is_ascii_uppercase();
+    if in_set && !negated {
+        regex.push_str(class);
+    } else {
+        regex.push('[');
+        if negated {
+            regex.push('^');
+        }
+        regex.push_str(class);
+        regex.push(']');
+    }
+    Ok(())
+}
+
+/// Translates a Lua pattern into an equivalent regex.
+///
+/// Like Lua's `string.find`, the regex searches the whole text unless the
+/// pattern is anchored with `^`. Balanced matches (`%b`), frontier patterns
+/// (`%f`) and back references are not supported.
+pub(super) fn to_regex(pattern: &str) -> Result<String, String> {
+    let mut regex = String::with_capacity(pattern.len() * 2);
+    let mut chars = pattern.chars().peekable();
+    if chars.next_if_eq(&'^').is_some() {
+        regex.push('^');
+    }
+    // quantifiers only apply to single character classes, everywhere else
+    // they are literal characters
+    let mut quantifiable = false;
+    while let Some(c) = chars.next() {
+        match c {
+            '*' | '+' | '?' if quantifiable => {
+                regex.push(c);
+                quantifiable = false;
+                continue;
+            }
+            '-' if quantifiable => {
+                regex.push_str("*?");
+                quantifiable = false;
+                continue;
+            }
+            '(' | ')' => {
+                regex.push(c);
+                quantifiable = false;
+                continue;
+            }
+            '$' if chars.peek().is_none() => regex.push('$'),
+            '.' => regex.push_str("(?s:.)"),
+            '%' => {
+                let c = chars.next().ok_or("malformed pattern (ends with '%')")?;
+                push_escape(&mut regex, c, false)?;
+            }
+            '[' => {
+                regex.push('[');
+                if chars.next_if_eq(&'^').is_some() {
+                    regex.push('^');
+                }
+                // a `]` directly after the opening bracket is a literal
+                let mut first = true;
+                loop {
+                    let c = chars.next().ok_or("malformed pattern (missing ']')")?;
+                    match c {
+                        ']' if !first => break,
+                        '%' => {
+                            let c = chars.next().ok_or("malformed pattern (missing ']')")?;
+                            push_escape(&mut regex, c, true)?;
+                        }
+                        _ => {
+                            push_literal(&mut regex, c);
+                            let mut ahead = chars.clone();
+                            if ahead.next() == Some('-') && ahead.peek().is_some_and(|&c| c != ']')
+                            {
+                                regex.push('-');
+                                push_literal(&mut regex, ahead.next().unwrap());
+                                chars = ahead;
+                            }
+                        }
+                    }
+                    first = false;
+                }
+                regex.push(']');
+            }
+            _ => push_literal(&mut regex, c),
+        }
+        quantifiable = true;
+    }
+    Ok(regex)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::to_regex;
+
+    #[test]
+    fn translate() {
+        assert_eq!(to_regex("^%u[%w_]*$").unwrap(), "^[A-Z][A-Za-z0-9_]*$");
+        assert_eq!(to_regex("%.%-?%d+").unwrap(), r"\.\-?[0-9]+");
+        assert_eq!(to_regex("a.-b").unwrap(), "a(?s:.)*?b");
+        assert_eq!(to_regex("[^%S\n]").unwrap(), "[^[^\\x09-\\x0D\\x20]\n]");
+        assert_eq!(to_regex("[]a-z-]").unwrap(), r"[\]a-z\-]");
+        assert_eq!(to_regex("*a^$b$").unwrap(), r"\*a\^\$b$");
+        assert_eq!(to_regex("(%a+)|{").unwrap(), r"([A-Za-z]+)\|\{");
+        assert!(to_regex("%b()").is_err());
+        assert!(to_regex("[abc").is_err());
+        assert!(to_regex("abc%").is_err());
+    }
+}
@@ -0,0 +1,151 @@
+use std::fmt;
+
+use crate::query::{Capture, Pattern, Query};
+
+/// A fixed size set of bits, all set initially.
+#[derive(Clone, PartialEq, Eq, Hash)]
+struct BitSet {
+    words: Box<[u64]>,
+    len: u32,
+}
+
+impl BitSet {
+    fn new(len: u32) -> BitSet {
+        let mut words = vec![u64::MAX; (len as usize).div_ceil(64)].into_boxed_slice();
+        if len % 64 != 0 {
+            if let Some(last) = words.last_mut() {
+                *last = (1 << (len % 64)) - 1;
+            }
+        }
+        BitSet { words, len }
+    }
+
+    fn get(&self, i: u32) -> bool {
+        assert!(i < self.len, "index is {i} but the length is {}", self.len);
+        self.words[i as usize / 64] & (1 << (i % 64)) != 0
+    }
+
+    fn set(&mut self, i: u32, value: bool) {
+        assert!(i < self.len, "index is {i} but the length is {}", self.len);
+        let word = &mut self.words[i as usize / 64];
+        if value {
+            *word |= 1 << (i % 64);
+        } else {
+            *word &= !(1 << (i % 64));
+        }
+    }
+
+    fn is_full(&self) -> bool {
+        self.words.iter().map(|word| word.count_ones()).sum::<u32>() == self.len
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u32> + '_ {
+        (0..self.len).filter(|&i| self.get(i))
+    }
+}
+
+/// The patterns of a [`Query`] which are enabled for a single execution of a
+/// query cursor, see [`QueryCursor::set_pattern_mask`].
+///
+/// Unlike [`Query::disable_pattern`] a mask does not modify the query so the
+/// same query can be executed with different patterns enabled.
+///
+/// [`QueryCursor::set_pattern_mask`]: crate::QueryCursor::set_pattern_mask
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct PatternMask(BitSet);
+
+impl PatternMask {
+    /// Creates a mask with all patterns of `query` enabled.
+    pub fn new(query: &Query) -> PatternMask {
+        PatternMask(BitSet::new(query.pattern_count() as u32))
+    }
+
+    pub fn is_enabled(&self, pattern: Pattern) -> bool {
+        self.0.get(pattern.0)
+    }
+
+    pub fn set_enabled(&mut self, pattern: Pattern, enabled: bool) {
+        self.0.set(pattern.0, enabled)
+    }
+
+    pub fn enable(&mut self, pattern: Pattern) {
+        self.set_enabled(pattern, true)
+    }
+
+    pub fn disable(&mut self, pattern: Pattern) {
+        self.set_enabled(pattern, false)
+    }
+
+    /// Iterates over the enabled patterns.
+    pub fn enabled(&self) -> impl Iterator<Item = Pattern> + '_ {
+        self.0.iter().map(Pattern)
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.0.len
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.0.is_full()
+    }
+}
+
+impl fmt::Debug for PatternMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.enabled()).finish()
+    }
+}
+
+/// The captures of a [`Query`] which are returned by a single execution of a
+/// query cursor, see [`QueryCursor::set_capture_mask`].
+///
+/// Unlike [`Query::disable_capture`] a mask does not modify the query so the
+/// same query can be executed with different captures enabled. Disabled
+/// captures are still recorded and can be used by predicates, they are only
+/// removed from the returned matches.
+///
+/// [`QueryCursor::set_capture_mask`]: crate::QueryCursor::set_capture_mask
+#[derive(Clone, PartialEq, Eq, Hash)]
+pub struct CaptureMask(BitSet);
+
+impl CaptureMask {
+    /// Creates a mask with all captures of `query` enabled.
+    pub fn new(query: &Query) -> CaptureMask {
+        CaptureMask(BitSet::new(query.num_captures))
+    }
+
+    pub fn is_enabled(&self, capture: Capture) -> bool {
+        self.0.get(capture.0)
+    }
+
+    pub fn set_enabled(&mut self, capture: Capture, enabled: bool) {
+        self.0.set(capture.0, enabled)
+    }
+
+    pub fn enable(&mut self, capture: Capture) {
+        self.set_enabled(capture, true)
+    }
+
+    pub fn disable(&mut self, capture: Capture) {
+        self.set_enabled(capture, false)
+    }
+
+    /// Iterates over the enabled captures.
+    pub fn enabled(&self) -> impl Iterator<Item = Capture> + '_ {
+        self.0.iter().map(Capture)
+    }
+
+    pub(crate) fn len(&self) -> u32 {
+        self.0.len
+    }
+
+    pub(crate) fn is_full(&self) -> bool {
+        self.0.is_full()
+    }
+}
+
+impl fmt::Debug for CaptureMask {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_set().entries(self.enabled()).finish()
+    }
+}
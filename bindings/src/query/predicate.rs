@@ -1,13 +1,17 @@
+use std::borrow::Cow;
 use std::error::Error;
 use std::iter::zip;
 use std::ops::Range;
 use std::ptr::NonNull;
-use std::{fmt, slice};
+use std::sync::Arc;
+use std::{fmt, slice, str};
 
+use crate::encoding::{decode_cursor_range, decode_range};
+use crate::query::lua_pattern;
 use crate::query::property::QueryProperty;
 use crate::query::{Capture, Pattern, PatternData, Query, QueryData, QueryStr, UserPredicate};
 use crate::query_cursor::MatchedNode;
-use crate::Input;
+use crate::{Encoding, Input, Node};
 
 use regex_cursor::engines::meta::Regex;
 use regex_cursor::Cursor;
@@ -32,6 +36,29 @@ pub(super) enum TextPredicateKind {
     EqCapture(Capture),
     MatchString(Regex),
     AnyString(Box<[QueryStr]>),
+    /// The kind of the node is one of the given kinds.
+    KindEq(Box<[QueryStr]>),
+    /// The kind of the node's parent is one of the given kinds.
+    HasParent(Box<[QueryStr]>),
+    /// The kind of any of the node's ancestors is one of the given kinds.
+    HasAncestor(Box<[QueryStr]>),
+}
+
+/// Translates a Lua pattern to a regex.
+fn lua_regex(pattern: &str) -> Result<Regex, InvalidPredicateError> {
+    match lua_pattern::to_regex(pattern).and_then(|regex| {
+        Regex::builder()
+            .build(&regex)
+            .map_err(|err| err.to_string())
+    }) {
+        Ok(regex) => Ok(regex),
+        Err(err) => bail!("invalid lua pattern '{pattern}', {err}"),
+    }
+}
+
+fn is_any_kind(node: &Node, kinds: &[QueryStr], query: &Query) -> bool {
+    let kind = node.kind();
+    kinds.iter().any(|&str| query.get_string(str) == kind)
 }
 
 #[derive(Debug)]
@@ -90,10 +117,17 @@ impl TextPredicate {
         let mut capture_nodes = matched_nodes
             .iter()
             .filter(|matched_node| matched_node.capture == self.capture);
+        // Query strings and regexes are UTF-8. Text in other encodings is
+        // decoded before comparing it with them.
+        let utf8 = matches!(input.encoding(), Encoding::Utf8);
         match self.kind {
             TextPredicateKind::EqString(str) => self.satisfied_helper(capture_nodes.map(|node| {
                 let range = node.node.byte_range();
-                input_matches_str(query.get_string(str), range.clone(), input)
+                if utf8 {
+                    input_matches_str(query.get_string(str), range.clone(), input)
+                } else {
+                    decode_range(input, range) == query.get_string(str)
+                }
             })),
             TextPredicateKind::EqCapture(other_capture) => {
                 let mut other_nodes = matched_nodes
@@ -113,6 +147,10 @@ impl TextPredicate {
             TextPredicateKind::MatchString(ref regex) => {
                 self.satisfied_helper(capture_nodes.map(|node| {
                     let range = node.node.byte_range();
+                    if !utf8 {
+                        let text = decode_range(input, range);
+                        return regex.is_match(regex_cursor::Input::new(text.as_str()));
+                    }
                     let mut input = regex_cursor::Input::new(input.cursor_at(range.start));
                     input.slice(range.start as usize..range.end as usize);
                     regex.is_match(input)
@@ -122,126 +160,554 @@ impl TextPredicate {
                 let strings = strings.iter().map(|&str| query.get_string(str));
                 self.satisfied_helper(capture_nodes.map(|node| {
                     let range = node.node.byte_range();
+                    if !utf8 {
+                        let text = decode_range(input, range);
+                        return strings.clone().any(|str| str == text);
+                    }
                     strings
                         .clone()
                         .filter(|str| str.len() == range.len())
                         .any(|str| input_matches_str(str, range.clone(), input))
                 }))
             }
+            TextPredicateKind::KindEq(ref kinds) => self.satisfied_helper(
+                capture_nodes.map(|matched_node| is_any_kind(&matched_node.node, kinds, query)),
+            ),
+            TextPredicateKind::HasParent(ref kinds) => {
+                self.satisfied_helper(capture_nodes.map(|matched_node| {
+                    matched_node
+                        .node
+                        .parent()
+                        .is_some_and(|parent| is_any_kind(&parent, kinds, query))
+                }))
+            }
+            TextPredicateKind::HasAncestor(ref kinds) => {
+                self.satisfied_helper(capture_nodes.map(|matched_node| {
+                    matched_node
+                        .node
+                        .ancestors()
+                        .skip(1)
+                        .any(|ancestor| is_any_kind(&ancestor, kinds, query))
+                }))
+            }
+        }
+    }
+}
+
+type EvaluatePredicateFn = dyn Fn(&mut PredicateEvaluation) -> bool + Send + Sync;
+
+/// A predicate of a pattern which is evaluated by a function registered with
+/// [`Query::register_predicate`].
+pub(crate) struct CustomPredicate {
+    pattern: Pattern,
+    /// The name of the predicate without the leading `#`.
+    name: Box<str>,
+    args: Box<[PredicateArg]>,
+    evaluate: Arc<EvaluatePredicateFn>,
+}
+
+impl fmt::Debug for CustomPredicate {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("CustomPredicate")
+            .field("pattern", &self.pattern)
+            .field("name", &self.name)
+            .field("args", &self.args)
+            .finish_non_exhaustive()
+    }
+}
+
+/// An object safe version of [`Input`] used by [`PredicateEvaluation`].
+trait DynInput {
+    fn cursor_at(&mut self, offset: u32) -> &mut dyn Cursor;
+    fn encoding(&self) -> Encoding;
+}
+
+impl<I: Input> DynInput for I {
+    fn cursor_at(&mut self, offset: u32) -> &mut dyn Cursor {
+        Input::cursor_at(self, offset)
+    }
+
+    fn encoding(&self) -> Encoding {
+        Input::encoding(self)
+    }
+}
+
+/// A cursor over the input of a [`PredicateEvaluation`].
+///
+/// The cursor yields the raw bytes of the input, which are only UTF-8 if the
+/// input's [`Encoding`] is UTF-8.
+pub struct InputCursor<'a>(&'a mut dyn Cursor);
+
+impl Cursor for InputCursor<'_> {
+    fn chunk(&self) -> &[u8] {
+        self.0.chunk()
+    }
+
+    fn utf8_aware(&self) -> bool {
+        self.0.utf8_aware()
+    }
+
+    fn advance(&mut self) -> bool {
+        self.0.advance()
+    }
+
+    fn backtrack(&mut self) -> bool {
+        self.0.backtrack()
+    }
+
+    fn total_bytes(&self) -> Option<usize> {
+        self.0.total_bytes()
+    }
+
+    fn offset(&self) -> usize {
+        self.0.offset()
+    }
+}
+
+/// A match for which a predicate registered with [`Query::register_predicate`]
+/// is evaluated.
+pub struct PredicateEvaluation<'a, 'tree> {
+    query: &'a Query,
+    pattern: Pattern,
+    args: &'a [PredicateArg],
+    matched_nodes: &'a [MatchedNode<'tree>],
+    input: &'a mut dyn DynInput,
+}
+
+impl<'a, 'tree> PredicateEvaluation<'a, 'tree> {
+    pub fn query(&self) -> &'a Query {
+        self.query
+    }
+
+    /// The pattern which matched.
+    pub fn pattern(&self) -> Pattern {
+        self.pattern
+    }
+
+    /// The arguments of the predicate.
+    pub fn args(&self) -> &'a [PredicateArg] {
+        self.args
+    }
+
+    /// The `i`th argument of the predicate if it is a string.
+    pub fn str_arg(&self, i: usize) -> Option<&'a str> {
+        match self.args.get(i)? {
+            PredicateArg::String(str) => Some(str.get(self.query)),
+            PredicateArg::Capture(_) => None,
+        }
+    }
+
+    /// All nodes captured by the match.
+    pub fn matched_nodes(&self) -> &'a [MatchedNode<'tree>] {
+        self.matched_nodes
+    }
+
+    /// The nodes captured by the given capture.
+    pub fn nodes_for_capture(&self, capture: Capture) -> impl Iterator<Item = &'a Node<'tree>> {
+        self.matched_nodes
+            .iter()
+            .filter(move |matched_node| matched_node.capture == capture)
+            .map(|matched_node| &matched_node.node)
+    }
+
+    /// The encoding of the input.
+    pub fn encoding(&self) -> Encoding {
+        self.input.encoding()
+    }
+
+    /// A cursor over the input, positioned at the chunk containing `offset`.
+    ///
+    /// Unlike [`PredicateEvaluation::text`] this never copies the text. The
+    /// cursor can be used with `regex_cursor` to match a regex against the
+    /// text of a node without decoding it, if the input is UTF-8.
+    pub fn cursor_at(&mut self, offset: u32) -> InputCursor<'_> {
+        InputCursor(self.input.cursor_at(offset))
+    }
+
+    /// The text of a node, decoded to UTF-8 if the input uses another encoding.
+    ///
+    /// The text is only copied if it is split across multiple chunks of the
+    /// input or needs to be decoded.
+    pub fn text(&mut self, node: &Node) -> Cow<'_, str> {
+        let range = node.byte_range();
+        let encoding = self.input.encoding();
+        let cursor = self.input.cursor_at(range.start);
+        fn chunk_text(cursor: &dyn Cursor, range: Range<u32>) -> Option<&str> {
+            let start = range.start as usize - cursor.offset();
+            let end = range.end as usize - cursor.offset();
+            str::from_utf8(cursor.chunk().get(start..end)?).ok()
         }
+        if matches!(encoding, Encoding::Utf8) && chunk_text(cursor, range.clone()).is_some() {
+            let cursor: &dyn Cursor = cursor;
+            return Cow::Borrowed(chunk_text(cursor, range).unwrap());
+        }
+        Cow::Owned(decode_cursor_range(cursor, encoding, range))
     }
 }
 
 impl Query {
+    /// Registers a function which evaluates the predicate `#name` at runtime.
+    ///
+    /// The function is called by [`QueryCursor::next_match`] and
+    /// [`QueryCursor::next_matched_node`] for every match of a pattern with the
+    /// predicate, after the built-in predicates like `#eq?` were satisfied.
+    /// Matches for which it returns `false` are skipped. The `name` excludes
+    /// the leading `#`, for example `"in-allowlist?"`.
+    ///
+    /// Registering a function for a name which already has a registered
+    /// function replaces the previous function.
+    ///
+    /// Note that the predicate is only known once the query was created, so
+    /// the `custom_predicate` callback of [`Query::new`] must still accept it
+    /// (it is passed as [`UserPredicate::Other`]). Otherwise the query fails to
+    /// parse.
+    ///
+    /// [`QueryCursor::next_match`]: crate::QueryCursor::next_match
+    /// [`QueryCursor::next_matched_node`]: crate::QueryCursor::next_matched_node
+    pub fn register_predicate(
+        &mut self,
+        name: &str,
+        evaluate: impl Fn(&mut PredicateEvaluation) -> bool + Send + Sync + 'static,
+    ) {
+        let evaluate: Arc<EvaluatePredicateFn> = Arc::new(evaluate);
+        let mut custom_predicates = std::mem::take(&mut self.custom_predicates);
+        custom_predicates.retain(|predicate| &*predicate.name != name);
+        for pattern in 0..self.patterns.len() as u32 {
+            let pattern = Pattern(pattern);
+            custom_predicates.extend(
+                self.pattern_predicates(pattern)
+                    .filter(|predicate| predicate.name() == name)
+                    .map(|predicate| CustomPredicate {
+                        pattern,
+                        name: name.into(),
+                        args: predicate.args().collect(),
+                        evaluate: evaluate.clone(),
+                    }),
+            );
+        }
+        custom_predicates.sort_by_key(|predicate| predicate.pattern);
+        self.custom_predicates = custom_predicates;
+    }
+
+    /// Evaluates the predicates registered with [`Query::register_predicate`]
+    /// for a match of `pattern`.
+    pub(crate) fn custom_predicates_satisfied<I: Input>(
+        &self,
+        pattern: Pattern,
+        matched_nodes: &[MatchedNode],
+        input: &mut I,
+    ) -> bool {
+        let start = self
+            .custom_predicates
+            .partition_point(|predicate| predicate.pattern < pattern);
+        self.custom_predicates[start..]
+            .iter()
+            .take_while(|predicate| predicate.pattern == pattern)
+            .all(|predicate| {
+                (predicate.evaluate)(&mut PredicateEvaluation {
+                    query: self,
+                    pattern,
+                    args: &predicate.args,
+                    matched_nodes,
+                    input,
+                })
+            })
+    }
+
+    /// Iterate over all predicates (and directives like `#set!`) of the given
+    /// pattern in the order they appear in the query source.
+    ///
+    /// This includes the predicates that are evaluated by the query cursor
+    /// itself like `#eq?` and `#match?`.
+    pub fn pattern_predicates(&self, pattern: Pattern) -> impl Iterator<Item = Predicate<'_>> {
+        assert!(
+            pattern.0 < self.patterns.len() as u32,
+            "Pattern index is {pattern:?} but the pattern count is {}",
+            self.patterns.len(),
+        );
+        unsafe { raw_predicates(self.raw, pattern) }
+            // predicates were validated when the query was created
+            .map(|predicate| unsafe { Predicate::new(self, predicate) }.unwrap())
+    }
+
+    /// Parses the predicates of a pattern. Invalid predicates are passed to
+    /// `report` which decides whether parsing continues with the next
+    /// predicate.
     pub(super) fn parse_pattern_predicates(
         &mut self,
         pattern: Pattern,
         mut custom_predicate: impl FnMut(Pattern, UserPredicate) -> Result<(), InvalidPredicateError>,
+        mut report: impl FnMut(InvalidPredicateError) -> Result<(), InvalidPredicateError>,
     ) -> Result<PatternData, InvalidPredicateError> {
         let text_predicate_start = self.text_predicates.len() as u32;
 
-        let predicate_steps = unsafe {
-            let mut len = 0u32;
-            let raw_predicates = ts_query_predicates_for_pattern(self.raw, pattern.0, &mut len);
-            (len != 0)
-                .then(|| slice::from_raw_parts(raw_predicates, len as usize))
-                .unwrap_or_default()
-        };
-        let predicates = predicate_steps
-            .split(|step| step.kind == PredicateStepKind::Done)
-            .filter(|predicate| !predicate.is_empty());
-
+        let predicates = unsafe { raw_predicates(self.raw, pattern) };
         for predicate in predicates {
-            let predicate = unsafe { Predicate::new(self, predicate)? };
-
-            match predicate.name() {
-                "eq?" | "not-eq?" | "any-eq?" | "any-not-eq?" => {
-                    predicate.check_arg_count(2)?;
-                    let capture_idx = predicate.capture_arg(0)?;
-                    let arg2 = predicate.arg(1);
-
-                    let negated = matches!(predicate.name(), "not-eq?" | "any-not-eq?");
-                    let match_all = matches!(predicate.name(), "eq?" | "not-eq?");
-                    let kind = match arg2 {
-                        PredicateArg::Capture(capture) => TextPredicateKind::EqCapture(capture),
-                        PredicateArg::String(str) => TextPredicateKind::EqString(str),
-                    };
-                    self.text_predicates.push(TextPredicate {
-                        capture: capture_idx,
-                        kind,
-                        negated,
-                        match_all,
-                    });
-                }
+            if let Err(err) = self.parse_predicate(pattern, predicate, &mut custom_predicate) {
+                report(err)?;
+            }
+        }
+        Ok(PatternData {
+            text_predicates: text_predicate_start..self.text_predicates.len() as u32,
+        })
+    }
 
-                "match?" | "not-match?" | "any-match?" | "any-not-match?" => {
-                    predicate.check_arg_count(2)?;
-                    let capture_idx = predicate.capture_arg(0)?;
-                    let regex = predicate.query_str_arg(1)?.get(self);
-
-                    let negated = matches!(predicate.name(), "not-match?" | "any-not-match?");
-                    let match_all = matches!(predicate.name(), "match?" | "not-match?");
-                    let regex = match Regex::builder().build(regex) {
-                        Ok(regex) => regex,
-                        Err(err) => bail!("invalid regex '{regex}', {err}"),
-                    };
-                    self.text_predicates.push(TextPredicate {
-                        capture: capture_idx,
-                        kind: TextPredicateKind::MatchString(regex),
-                        negated,
-                        match_all,
-                    });
-                }
+    fn parse_predicate(
+        &mut self,
+        pattern: Pattern,
+        predicate: &[PredicateStep],
+        custom_predicate: &mut impl FnMut(Pattern, UserPredicate) -> Result<(), InvalidPredicateError>,
+    ) -> Result<(), InvalidPredicateError> {
+        let predicate = unsafe { Predicate::new(self, predicate)? };
 
-                "set!" => {
-                    let property = QueryProperty::parse(&predicate)?;
-                    custom_predicate(
-                        pattern,
-                        UserPredicate::SetProperty {
-                            key: property.key.get(self),
-                            val: property.val.map(|val| val.get(self)),
-                        },
-                    )?
-                }
-                "is-not?" | "is?" => {
-                    let property = QueryProperty::parse(&predicate)?;
-                    custom_predicate(
-                        pattern,
-                        UserPredicate::IsPropertySet {
-                            negate: predicate.name() == "is-not?",
-                            key: property.key.get(self),
-                            val: property.val.map(|val| val.get(self)),
-                        },
-                    )?
-                }
+        match predicate.name() {
+            "eq?" | "not-eq?" | "any-eq?" | "any-not-eq?" => {
+                predicate.check_arg_count(2)?;
+                let capture_idx = predicate.capture_arg(0)?;
+                let arg2 = predicate.arg(1);
+
+                let negated = matches!(predicate.name(), "not-eq?" | "any-not-eq?");
+                let match_all = matches!(predicate.name(), "eq?" | "not-eq?");
+                let kind = match arg2 {
+                    PredicateArg::Capture(capture) => TextPredicateKind::EqCapture(capture),
+                    PredicateArg::String(str) => TextPredicateKind::EqString(str),
+                };
+                self.text_predicates.push(TextPredicate {
+                    capture: capture_idx,
+                    kind,
+                    negated,
+                    match_all,
+                });
+            }
+
+            "match?" | "not-match?" | "any-match?" | "any-not-match?" => {
+                predicate.check_arg_count(2)?;
+                let capture_idx = predicate.capture_arg(0)?;
+                let regex = predicate.query_str_arg(1)?.get(self);
+
+                let negated = matches!(predicate.name(), "not-match?" | "any-not-match?");
+                let match_all = matches!(predicate.name(), "match?" | "not-match?");
+                let regex = match Regex::builder().build(regex) {
+                    Ok(regex) => regex,
+                    Err(err) => bail!("invalid regex '{regex}', {err}"),
+                };
+                self.text_predicates.push(TextPredicate {
+                    capture: capture_idx,
+                    kind: TextPredicateKind::MatchString(regex),
+                    negated,
+                    match_all,
+                });
+            }
 
-                "any-of?" | "not-any-of?" => {
-                    predicate.check_min_arg_count(1)?;
-                    let capture = predicate.capture_arg(0)?;
-                    let negated = predicate.name() == "not-any-of?";
-                    let values: Result<_, InvalidPredicateError> = (1..predicate.num_args())
-                        .map(|i| predicate.query_str_arg(i))
-                        .collect();
-                    self.text_predicates.push(TextPredicate {
+            "lua-match?" | "not-lua-match?" | "any-lua-match?" | "any-not-lua-match?" => {
+                predicate.check_arg_count(2)?;
+                let capture_idx = predicate.capture_arg(0)?;
+                let pattern = predicate.query_str_arg(1)?.get(self);
+
+                let negated = matches!(predicate.name(), "not-lua-match?" | "any-not-lua-match?");
+                let match_all = matches!(predicate.name(), "lua-match?" | "not-lua-match?");
+                let regex = lua_regex(pattern)?;
+                self.text_predicates.push(TextPredicate {
+                    capture: capture_idx,
+                    kind: TextPredicateKind::MatchString(regex),
+                    negated,
+                    match_all,
+                });
+            }
+
+            "contains?" | "not-contains?" | "any-contains?" | "any-not-contains?" => {
+                predicate.check_min_arg_count(2)?;
+                let capture_idx = predicate.capture_arg(0)?;
+                let substrings: Result<Vec<_>, InvalidPredicateError> = (1..predicate.num_args())
+                    .map(|i| predicate.str_arg(i).map(lua_pattern::escape))
+                    .collect();
+
+                let negated = matches!(predicate.name(), "not-contains?" | "any-not-contains?");
+                let match_all = matches!(predicate.name(), "contains?" | "not-contains?");
+                // the text contains one of the substrings if it matches an
+                // alternation of them
+                let regex = match Regex::builder().build(&substrings?.join("|")) {
+                    Ok(regex) => regex,
+                    Err(err) => bail!("invalid #{}, {err}", predicate.name()),
+                };
+                self.text_predicates.push(TextPredicate {
+                    capture: capture_idx,
+                    kind: TextPredicateKind::MatchString(regex),
+                    negated,
+                    match_all,
+                });
+            }
+
+            "kind-eq?" | "not-kind-eq?" | "has-parent?" | "not-has-parent?" | "has-ancestor?"
+            | "not-has-ancestor?" => {
+                predicate.check_min_arg_count(2)?;
+                let capture = predicate.capture_arg(0)?;
+                let kinds: Result<_, InvalidPredicateError> = (1..predicate.num_args())
+                    .map(|i| predicate.query_str_arg(i))
+                    .collect();
+                let name = predicate.name();
+                let negated = name.starts_with("not-");
+                let kind = match name.trim_start_matches("not-") {
+                    "kind-eq?" => TextPredicateKind::KindEq(kinds?),
+                    "has-parent?" => TextPredicateKind::HasParent(kinds?),
+                    _ => TextPredicateKind::HasAncestor(kinds?),
+                };
+                self.text_predicates.push(TextPredicate {
+                    capture,
+                    kind,
+                    negated,
+                    match_all: true,
+                });
+            }
+
+            "set!" => {
+                let property = QueryProperty::parse(&predicate)?;
+                custom_predicate(
+                    pattern,
+                    UserPredicate::SetProperty {
+                        key: property.key.get(self),
+                        val: property.val.map(|val| val.get(self)),
+                    },
+                )?
+            }
+            "offset!" => {
+                predicate.check_arg_count(5)?;
+                let capture = predicate.capture_arg(0)?;
+                custom_predicate(
+                    pattern,
+                    UserPredicate::Offset {
                         capture,
-                        kind: TextPredicateKind::AnyString(values?),
-                        negated,
-                        match_all: false,
-                    });
+                        capture_name: self.capture_name(capture),
+                        start_row: predicate.int_arg(1)?,
+                        start_col: predicate.int_arg(2)?,
+                        end_row: predicate.int_arg(3)?,
+                        end_col: predicate.int_arg(4)?,
+                    },
+                )?
+            }
+            "gsub!" => {
+                predicate.check_arg_count(3)?;
+                let capture = predicate.capture_arg(0)?;
+                let lua_pattern = predicate.str_arg(1)?;
+                let replacement = predicate.str_arg(2)?;
+                let regex = lua_regex(lua_pattern)?;
+                let mut chars = replacement.chars();
+                while let Some(c) = chars.next() {
+                    ensure!(
+                        c != '%' || chars.next().is_some_and(|c| c == '%' || c.is_ascii_digit()),
+                        "invalid use of '%' in replacement string {replacement:?}"
+                    );
                 }
+                custom_predicate(
+                    pattern,
+                    UserPredicate::Gsub {
+                        capture,
+                        capture_name: self.capture_name(capture),
+                        pattern: lua_pattern,
+                        regex,
+                        replacement,
+                    },
+                )?
+            }
+            "strip!" => {
+                predicate.check_arg_count(2)?;
+                let capture = predicate.capture_arg(0)?;
+                let lua_pattern = predicate.str_arg(1)?;
+                let regex = lua_regex(lua_pattern)?;
+                custom_predicate(
+                    pattern,
+                    UserPredicate::Strip {
+                        capture,
+                        capture_name: self.capture_name(capture),
+                        pattern: lua_pattern,
+                        regex,
+                    },
+                )?
+            }
+            "trim!" => {
+                predicate.check_min_arg_count(1)?;
+                predicate.check_max_arg_count(5)?;
+                let capture = predicate.capture_arg(0)?;
+                let flags: Result<Vec<_>, InvalidPredicateError> = (1..predicate.num_args())
+                    .map(|i| match predicate.str_arg(i)? {
+                        "0" => Ok(false),
+                        "1" => Ok(true),
+                        arg => bail!("{i}. argument to #trim! must be 0 or 1, got {arg:?}"),
+                    })
+                    .collect();
+                let flags = flags?;
+                let flag = |i: usize| flags.get(i).copied().unwrap_or(false);
+                custom_predicate(
+                    pattern,
+                    UserPredicate::Trim {
+                        capture,
+                        capture_name: self.capture_name(capture),
+                        start_linewise: flag(0),
+                        start_charwise: flag(1),
+                        // for backwards compatibility only blank lines at the
+                        // end are trimmed by default
+                        end_linewise: flags.is_empty() || flag(2),
+                        end_charwise: flag(3),
+                    },
+                )?
+            }
+            "is-not?" | "is?" => {
+                let property = QueryProperty::parse(&predicate)?;
+                custom_predicate(
+                    pattern,
+                    UserPredicate::IsPropertySet {
+                        negate: predicate.name() == "is-not?",
+                        key: property.key.get(self),
+                        val: property.val.map(|val| val.get(self)),
+                    },
+                )?
+            }
 
-                // is and is-not are better handled as custom predicates since interpreting is context dependent
-                // "is?" => property_predicates.push((QueryProperty::parse(&predicate), false)),
-                // "is-not?" => property_predicates.push((QueryProperty::parse(&predicate), true)),
-                _ => custom_predicate(pattern, UserPredicate::Other(predicate))?,
+            "any-of?" | "not-any-of?" => {
+                predicate.check_min_arg_count(1)?;
+                let capture = predicate.capture_arg(0)?;
+                let negated = predicate.name() == "not-any-of?";
+                let values: Result<_, InvalidPredicateError> = (1..predicate.num_args())
+                    .map(|i| predicate.query_str_arg(i))
+                    .collect();
+                self.text_predicates.push(TextPredicate {
+                    capture,
+                    kind: TextPredicateKind::AnyString(values?),
+                    negated,
+                    match_all: false,
+                });
             }
+
+            // is and is-not are better handled as custom predicates since interpreting is context dependent
+            // "is?" => property_predicates.push((QueryProperty::parse(&predicate), false)),
+            // "is-not?" => property_predicates.push((QueryProperty::parse(&predicate), true)),
+            _ => custom_predicate(pattern, UserPredicate::Other(predicate))?,
         }
-        Ok(PatternData {
-            text_predicates: text_predicate_start..self.text_predicates.len() as u32,
-        })
+        Ok(())
     }
 }
 
+/// Split the predicate steps of a pattern into the individual predicates.
+///
+/// # Safety
+///
+/// The returned slices are owned by the query and must not outlive it.
+unsafe fn raw_predicates<'a>(
+    query: NonNull<QueryData>,
+    pattern: Pattern,
+) -> impl Iterator<Item = &'a [PredicateStep]> {
+    let mut len = 0u32;
+    let raw_predicates = ts_query_predicates_for_pattern(query, pattern.0, &mut len);
+    #[allow(clippy::obfuscated_if_else)]
+    let predicate_steps = (len != 0)
+        .then(|| slice::from_raw_parts(raw_predicates, len as usize))
+        .unwrap_or_default();
+    predicate_steps
+        .split(|step| step.kind == PredicateStepKind::Done)
+        .filter(|predicate| !predicate.is_empty())
+}
+
+#[derive(Debug, Clone, Copy)]
 pub enum PredicateArg {
     Capture(Capture),
     String(QueryStr),
@@ -321,6 +787,17 @@ impl<'a> Predicate<'a> {
         Ok(self.query_str_arg(i)?.get(self.query))
     }
 
+    pub fn int_arg(&self, i: usize) -> Result<i32, InvalidPredicateError> {
+        let arg = self.str_arg(i)?;
+        match arg.parse() {
+            Ok(int) => Ok(int),
+            Err(_) => bail!(
+                "{i}. argument to #{} must be an integer, got {arg:?}",
+                self.name()
+            ),
+        }
+    }
+
     pub fn num_args(&self) -> usize {
         self.args.len()
     }
@@ -369,7 +846,7 @@ impl InvalidPredicateError {
             UserPredicate::SetProperty { key, .. } => Self::UnknownProperty {
                 property: key.into(),
             },
-            UserPredicate::Other(predicate) => Self::UnknownPredicate {
+            predicate => Self::UnknownPredicate {
                 name: predicate.name().into(),
             },
         }
@@ -1,13 +1,16 @@
 use core::slice;
+use std::any::Any;
 use std::cell::UnsafeCell;
+use std::ffi::c_void;
 use std::marker::PhantomData;
 use std::mem;
-use std::ops::Range;
-use std::ptr::NonNull;
+use std::ops::{ControlFlow, Range};
+use std::panic::{catch_unwind, resume_unwind, AssertUnwindSafe};
+use std::ptr::{self, NonNull};
 
 use crate::node::NodeRaw;
-use crate::query::{Capture, Pattern, Query, QueryData};
-use crate::{Input, IntoInput, Node, Tree};
+use crate::query::{Capture, CaptureMask, Pattern, PatternMask, Query, QueryData};
+use crate::{Input, IntoInput, Node, Point, Tree};
 
 enum QueryCursorData {}
 
@@ -25,9 +28,87 @@ pub struct QueryCursor<'a, 'tree, I: Input> {
     ptr: NonNull<QueryCursorData>,
     tree: PhantomData<&'tree Tree>,
     input: I,
+    /// The options passed to `ts_query_cursor_exec_with_options`. The C
+    /// library holds on to a pointer to them while the query is running.
+    progress: Option<Box<QueryProgress<'a>>>,
+    pattern_mask: Option<&'a PatternMask>,
+    capture_mask: Option<&'a CaptureMask>,
+    /// The enabled matched nodes of the current match if the capture mask
+    /// disables some of them.
+    masked_nodes: Vec<MatchedNode<'tree>>,
 }
 
-impl<'tree, I: Input> QueryCursor<'_, 'tree, I> {
+impl<'a, 'tree, I: Input> QueryCursor<'a, 'tree, I> {
+    fn satisfies_predicates(&mut self, pattern_idx: u16, matched_nodes: &[MatchedNode]) -> bool {
+        self.query
+            .pattern_text_predicates(pattern_idx)
+            .iter()
+            .all(|predicate| predicate.satisfied(&mut self.input, matched_nodes, self.query))
+            && self.query.custom_predicates_satisfied(
+                Pattern(pattern_idx as u32),
+                matched_nodes,
+                &mut self.input,
+            )
+    }
+
+    fn pattern_enabled(&self, pattern_idx: u16) -> bool {
+        self.pattern_mask
+            .map_or(true, |mask| mask.is_enabled(Pattern(pattern_idx as u32)))
+    }
+
+    /// Only keep the matched nodes of captures enabled by the capture mask.
+    fn mask_captures<'m>(
+        capture_mask: Option<&CaptureMask>,
+        masked_nodes: &'m mut Vec<MatchedNode<'tree>>,
+        matched_nodes: &'m [MatchedNode<'tree>],
+    ) -> &'m [MatchedNode<'tree>] {
+        let Some(mask) = capture_mask else {
+            return matched_nodes;
+        };
+        masked_nodes.clear();
+        masked_nodes.extend(
+            matched_nodes
+                .iter()
+                .filter(|node| mask.is_enabled(node.capture))
+                .cloned(),
+        );
+        masked_nodes
+    }
+
+    /// Only return the patterns enabled by `mask`. Disabled patterns are still
+    /// matched but their matches are skipped.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mask was not created for the query of this cursor.
+    pub fn set_pattern_mask(&mut self, mask: Option<&'a PatternMask>) {
+        if let Some(mask) = mask {
+            assert_eq!(
+                mask.len() as usize,
+                self.query.pattern_count(),
+                "the pattern mask does not belong to this query"
+            );
+        }
+        self.pattern_mask = mask.filter(|mask| !mask.is_full());
+    }
+
+    /// Only return the nodes of the captures enabled by `mask`. Predicates
+    /// still see the nodes of disabled captures.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the mask was not created for the query of this cursor.
+    pub fn set_capture_mask(&mut self, mask: Option<&'a CaptureMask>) {
+        if let Some(mask) = mask {
+            assert_eq!(
+                mask.len(),
+                self.query.num_captures(),
+                "the capture mask does not belong to this query"
+            );
+        }
+        self.capture_mask = mask.filter(|mask| !mask.is_full());
+    }
+
     pub fn next_match(&mut self) -> Option<QueryMatch<'_, 'tree>> {
         let mut query_match = TSQueryMatch {
             id: 0,
@@ -38,25 +119,30 @@ impl<'tree, I: Input> QueryCursor<'_, 'tree, I> {
         loop {
             let success =
                 unsafe { ts_query_cursor_next_match(self.ptr.as_ptr(), &mut query_match) };
+            self.resume_progress_panic();
             if !success {
                 return None;
             }
-            let matched_nodes: &[_] = match query_match.captures {
+            let matched_nodes: &[MatchedNode<'tree>] = match query_match.captures {
                 None => &[],
                 Some(ptr) => unsafe {
                     slice::from_raw_parts(ptr.cast().as_ptr(), query_match.capture_count as usize)
                 },
             };
-            let satisfies_predicates = self
-                .query
-                .pattern_text_predicates(query_match.pattern_index)
-                .iter()
-                .all(|predicate| predicate.satisfied(&mut self.input, matched_nodes, self.query));
+            if !self.pattern_enabled(query_match.pattern_index) {
+                continue;
+            }
+            let satisfies_predicates =
+                self.satisfies_predicates(query_match.pattern_index, matched_nodes);
             if satisfies_predicates {
                 let res = QueryMatch {
                     id: query_match.id,
                     pattern: Pattern(query_match.pattern_index as u32),
-                    matched_nodes,
+                    matched_nodes: Self::mask_captures(
+                        self.capture_mask,
+                        &mut self.masked_nodes,
+                        matched_nodes,
+                    ),
                     query_cursor: unsafe { self.ptr.as_mut() },
                     _tree: PhantomData,
                 };
@@ -77,25 +163,46 @@ impl<'tree, I: Input> QueryCursor<'_, 'tree, I> {
             let success = unsafe {
                 ts_query_cursor_next_capture(self.ptr.as_ptr(), &mut query_match, &mut capture_idx)
             };
+            self.resume_progress_panic();
             if !success {
                 return None;
             }
-            let matched_nodes: &[_] = match query_match.captures {
+            let matched_nodes: &[MatchedNode<'tree>] = match query_match.captures {
                 None => &[],
                 Some(ptr) => unsafe {
                     slice::from_raw_parts(ptr.cast().as_ptr(), query_match.capture_count as usize)
                 },
             };
-            let satisfies_predicates = self
-                .query
-                .pattern_text_predicates(query_match.pattern_index)
-                .iter()
-                .all(|predicate| predicate.satisfied(&mut self.input, matched_nodes, self.query));
+            if !self.pattern_enabled(query_match.pattern_index) {
+                unsafe {
+                    ts_query_cursor_remove_match(self.ptr.as_ptr(), query_match.id);
+                }
+                continue;
+            }
+            // the other captures of the match may still be enabled
+            if self
+                .capture_mask
+                .is_some_and(|mask| !mask.is_enabled(matched_nodes[capture_idx as usize].capture))
+            {
+                continue;
+            }
+            let satisfies_predicates =
+                self.satisfies_predicates(query_match.pattern_index, matched_nodes);
             if satisfies_predicates {
+                if let Some(mask) = self.capture_mask {
+                    capture_idx = matched_nodes[..capture_idx as usize]
+                        .iter()
+                        .filter(|node| mask.is_enabled(node.capture))
+                        .count() as u32;
+                }
                 let res = QueryMatch {
                     id: query_match.id,
                     pattern: Pattern(query_match.pattern_index as u32),
-                    matched_nodes,
+                    matched_nodes: Self::mask_captures(
+                        self.capture_mask,
+                        &mut self.masked_nodes,
+                        matched_nodes,
+                    ),
                     query_cursor: unsafe { self.ptr.as_mut() },
                     _tree: PhantomData,
                 };
@@ -114,7 +221,71 @@ impl<'tree, I: Input> QueryCursor<'_, 'tree, I> {
         }
     }
 
-    pub fn reuse(self) -> InactiveQueryCursor {
+    /// Set the range of (row, column) positions in which the query will be
+    /// executed.
+    pub fn set_point_range(&mut self, range: Range<Point>) {
+        unsafe {
+            ts_query_cursor_set_point_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the byte range within which all matches must be fully contained.
+    ///
+    /// In contrast to [`set_byte_range`](Self::set_byte_range), this will
+    /// restrict the query cursor to only return matches where _all_ nodes are
+    /// _fully_ contained within the given range. Both functions can be used
+    /// together, e.g. to search for any matches that intersect line 5000, as
+    /// long as they are fully contained within lines 4500-5500.
+    pub fn set_containing_byte_range(&mut self, range: Range<u32>) {
+        unsafe {
+            ts_query_cursor_set_containing_byte_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the (row, column) range within which all matches must be fully
+    /// contained.
+    ///
+    /// See [`set_containing_byte_range`](Self::set_containing_byte_range).
+    pub fn set_containing_point_range(&mut self, range: Range<Point>) {
+        unsafe {
+            ts_query_cursor_set_containing_point_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the maximum start depth for a query cursor.
+    ///
+    /// This prevents cursors from exploring children nodes at a certain depth.
+    /// Note if a pattern includes many children, then they will still be
+    /// checked. The zero max start depth value can be used as a special
+    /// behavior and it helps to destructure a subtree by staying on a node and
+    /// using captures for interested parts. Note that the zero max start depth
+    /// only limit a search depth for a pattern's root node but other nodes that
+    /// are parts of the pattern may be searched at any depth what defined by
+    /// the pattern structure.
+    ///
+    /// Set to `None` to remove the maximum start depth.
+    pub fn set_max_start_depth(&mut self, max_start_depth: Option<u32>) {
+        unsafe {
+            ts_query_cursor_set_max_start_depth(
+                self.ptr.as_ptr(),
+                max_start_depth.unwrap_or(u32::MAX),
+            );
+        }
+    }
+
+    /// Continue a panic of the progress callback once control is back in Rust.
+    fn resume_progress_panic(&mut self) {
+        if let Some(payload) = self
+            .progress
+            .as_mut()
+            .and_then(|progress| progress.panic.take())
+        {
+            resume_unwind(payload)
+        }
+    }
+
+    pub fn reuse(mut self) -> InactiveQueryCursor {
+        drop(self.progress.take());
         let res = InactiveQueryCursor { ptr: self.ptr };
         mem::forget(self);
         res
@@ -142,7 +313,12 @@ impl InactiveQueryCursor {
                 })
             })
         };
+        // cached cursors keep the ranges and depth of their previous user
         this.set_byte_range(range);
+        this.set_point_range(Point::ZERO..Point::MAX);
+        this.set_containing_byte_range(0..u32::MAX);
+        this.set_containing_point_range(Point::ZERO..Point::MAX);
+        this.set_max_start_depth(None);
         this.set_match_limit(limit);
         this
     }
@@ -177,6 +353,58 @@ impl InactiveQueryCursor {
         }
     }
 
+    /// Set the range of (row, column) positions in which the query will be
+    /// executed.
+    pub fn set_point_range(&mut self, range: Range<Point>) {
+        unsafe {
+            ts_query_cursor_set_point_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the byte range within which all matches must be fully contained.
+    ///
+    /// In contrast to [`set_byte_range`](Self::set_byte_range), this will
+    /// restrict the query cursor to only return matches where _all_ nodes are
+    /// _fully_ contained within the given range. Both functions can be used
+    /// together, e.g. to search for any matches that intersect line 5000, as
+    /// long as they are fully contained within lines 4500-5500.
+    pub fn set_containing_byte_range(&mut self, range: Range<u32>) {
+        unsafe {
+            ts_query_cursor_set_containing_byte_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the (row, column) range within which all matches must be fully
+    /// contained.
+    ///
+    /// See [`set_containing_byte_range`](Self::set_containing_byte_range).
+    pub fn set_containing_point_range(&mut self, range: Range<Point>) {
+        unsafe {
+            ts_query_cursor_set_containing_point_range(self.ptr.as_ptr(), range.start, range.end);
+        }
+    }
+
+    /// Set the maximum start depth for a query cursor.
+    ///
+    /// This prevents cursors from exploring children nodes at a certain depth.
+    /// Note if a pattern includes many children, then they will still be
+    /// checked. The zero max start depth value can be used as a special
+    /// behavior and it helps to destructure a subtree by staying on a node and
+    /// using captures for interested parts. Note that the zero max start depth
+    /// only limit a search depth for a pattern's root node but other nodes that
+    /// are parts of the pattern may be searched at any depth what defined by
+    /// the pattern structure.
+    ///
+    /// Set to `None` to remove the maximum start depth.
+    pub fn set_max_start_depth(&mut self, max_start_depth: Option<u32>) {
+        unsafe {
+            ts_query_cursor_set_max_start_depth(
+                self.ptr.as_ptr(),
+                max_start_depth.unwrap_or(u32::MAX),
+            );
+        }
+    }
+
     pub fn execute_query<'a, 'tree, I: IntoInput>(
         self,
         query: &'a Query,
@@ -191,6 +419,74 @@ impl InactiveQueryCursor {
             ptr,
             tree: PhantomData,
             input: input.into_input(),
+            progress: None,
+            pattern_mask: None,
+            capture_mask: None,
+            masked_nodes: Vec::new(),
+        }
+    }
+
+    /// Start running the query with a progress/cancellation callback.
+    ///
+    /// The callback is invoked periodically while the cursor searches for
+    /// matches and receives the current [`QueryCursorState`]. Returning
+    /// [`ControlFlow::Break`] stops the search: `next_match` and
+    /// `next_matched_node` return `None`.
+    pub fn execute_query_with_options<'a, 'tree, I: IntoInput>(
+        self,
+        query: &'a Query,
+        node: &Node<'tree>,
+        input: I,
+        options: QueryCursorOptions<'a>,
+    ) -> QueryCursor<'a, 'tree, I::Input> {
+        // The payload points to the boxed `QueryProgress`. A panic of the callback must not
+        // unwind into C so it is stashed in the box, the query is cancelled and the panic is
+        // resumed once `ts_query_cursor_next_*` returns.
+        unsafe extern "C" fn progress_cb(raw_state: NonNull<RawQueryCursorState>) -> bool {
+            let raw_ref = raw_state.as_ref();
+            let progress: *mut QueryProgress = raw_ref.payload.as_ptr().cast();
+            let callback = &mut *(*progress).callback;
+            let public_state = QueryCursorState {
+                current_byte_offset: raw_ref.current_byte_offset,
+            };
+            match catch_unwind(AssertUnwindSafe(|| callback(&public_state))) {
+                Ok(flow) => flow.is_break(),
+                Err(payload) => {
+                    (*progress).panic = Some(payload);
+                    true
+                }
+            }
+        }
+
+        let mut progress = Box::new(QueryProgress {
+            raw: RawQueryCursorOptions {
+                payload: None,
+                progress_callback: Some(progress_cb),
+            },
+            callback: options.callback,
+            panic: None,
+        });
+        progress.raw.payload = NonNull::new(ptr::addr_of_mut!(*progress).cast());
+
+        let ptr = self.ptr;
+        unsafe {
+            ts_query_cursor_exec_with_options(
+                ptr.as_ptr(),
+                query.raw.as_ref(),
+                node.as_raw(),
+                &progress.raw,
+            )
+        };
+        mem::forget(self);
+        QueryCursor {
+            query,
+            ptr,
+            tree: PhantomData,
+            input: input.into_input(),
+            progress: Some(progress),
+            pattern_mask: None,
+            capture_mask: None,
+            masked_nodes: Vec::new(),
         }
     }
 }
@@ -207,6 +503,54 @@ impl Drop for InactiveQueryCursor {
     }
 }
 
+/// State passed to the progress callback during query execution.
+#[derive(Debug, Clone, Copy)]
+pub struct QueryCursorState {
+    pub current_byte_offset: u32,
+}
+
+/// Options for [`InactiveQueryCursor::execute_query_with_options`].
+///
+/// The callback receives the current [`QueryCursorState`] and returns [`ControlFlow::Break`] to
+/// cancel the query.
+pub struct QueryCursorOptions<'a> {
+    callback: &'a mut dyn FnMut(&QueryCursorState) -> ControlFlow<()>,
+}
+
+impl<'a> QueryCursorOptions<'a> {
+    pub fn new(
+        callback: &'a mut impl FnMut(&QueryCursorState) -> ControlFlow<()>,
+    ) -> QueryCursorOptions<'a> {
+        QueryCursorOptions { callback }
+    }
+}
+
+struct QueryProgress<'a> {
+    raw: RawQueryCursorOptions,
+    callback: &'a mut dyn FnMut(&QueryCursorState) -> ControlFlow<()>,
+    /// The payload of a panic of `callback`, resumed by the query cursor.
+    panic: Option<Box<dyn Any + Send>>,
+}
+
+#[repr(C)]
+#[derive(Debug)]
+struct RawQueryCursorState {
+    /// The payload passed via `RawQueryCursorOptions`' `payload` field.
+    payload: NonNull<c_void>,
+    current_byte_offset: u32,
+}
+
+/// A function that accepts the current query cursor state and returns `true` when the query
+/// should be cancelled.
+type QueryProgressCallback = unsafe extern "C" fn(state: NonNull<RawQueryCursorState>) -> bool;
+
+#[repr(C)]
+#[derive(Debug)]
+struct RawQueryCursorOptions {
+    payload: Option<NonNull<c_void>>,
+    progress_callback: Option<QueryProgressCallback>,
+}
+
 pub type MatchedNodeIdx = u32;
 
 #[repr(C)]
@@ -329,6 +673,13 @@ extern "C" {
 
     /// Start running a given query on a given node.
     fn ts_query_cursor_exec(self_: *mut QueryCursorData, query: &QueryData, node: NodeRaw);
+    /// Start running a given query on a given node, with some options.
+    fn ts_query_cursor_exec_with_options(
+        self_: *mut QueryCursorData,
+        query: &QueryData,
+        node: NodeRaw,
+        options: &RawQueryCursorOptions,
+    );
     /// Manage the maximum number of in-progress matches allowed by this query
     /// cursor.
     ///
@@ -344,46 +695,37 @@ extern "C" {
     /// Set the range of bytes or (row, column) positions in which the query
     /// will be executed.
     fn ts_query_cursor_set_byte_range(self_: *mut QueryCursorData, start_byte: u32, end_byte: u32);
-
+    fn ts_query_cursor_set_point_range(
+        self_: *mut QueryCursorData,
+        start_point: Point,
+        end_point: Point,
+    );
+    /// Set the byte or (row, column) range within which all matches must be
+    /// fully contained.
+    fn ts_query_cursor_set_containing_byte_range(
+        self_: *mut QueryCursorData,
+        start_byte: u32,
+        end_byte: u32,
+    ) -> bool;
+    fn ts_query_cursor_set_containing_point_range(
+        self_: *mut QueryCursorData,
+        start_point: Point,
+        end_point: Point,
+    ) -> bool;
+    /// Set the maximum start depth for a query cursor.
+    fn ts_query_cursor_set_max_start_depth(self_: *mut QueryCursorData, max_start_depth: u32);
 }
 
 #[cfg(test)]
 mod tests {
-    use std::path::Path;
+    use std::ops::ControlFlow;
+    use std::panic::{catch_unwind, AssertUnwindSafe};
 
-    use crate::{Grammar, InactiveQueryCursor, Input, Parser, Query};
+    use regex_cursor::engines::meta::Regex;
 
-    struct StrInput<'a> {
-        src: &'a str,
-        cursor: &'a str,
-    }
-
-    impl<'a> StrInput<'a> {
-        fn new(src: &'a str) -> Self {
-            Self { src, cursor: src }
-        }
-    }
-
-    impl<'a> Input for StrInput<'a> {
-        type Cursor = &'a str;
-
-        fn cursor_at(&mut self, _offset: u32) -> &mut &'a str {
-            // &str Cursor has offset()=0 and chunk()=self, so the parser reads
-            // src.as_ptr() + byte_index, which is correct for a contiguous string.
-            self.cursor = self.src;
-            &mut self.cursor
-        }
-
-        fn eq(&mut self, r1: std::ops::Range<u32>, r2: std::ops::Range<u32>) -> bool {
-            let b = self.src.as_bytes();
-            b[r1.start as usize..r1.end as usize] == b[r2.start as usize..r2.end as usize]
-        }
-    }
-
-    fn python_grammar() -> Grammar {
-        let so = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-grammars/python/python.so");
-        unsafe { Grammar::new("python", &so) }.expect("python grammar")
-    }
+    use crate::query::PredicateArg;
+    use crate::test_grammars::python_grammar;
+    use crate::{CaptureMask, InactiveQueryCursor, Parser, PatternMask, Query, QueryCursorOptions};
 
     /// Regression test: when all captures in a pattern are disabled via
     /// `Query::disable_capture`, tree-sitter returns `capture_count=0` with a
@@ -400,11 +742,11 @@ mod tests {
         let src = "x = 1";
         let mut parser = Parser::new();
         parser.set_grammar(grammar).unwrap();
-        let tree = parser.parse(StrInput::new(src), None).unwrap();
+        let tree = parser.parse(src, None).unwrap();
 
         let root = tree.root_node();
         let cursor = InactiveQueryCursor::new(0..src.len() as u32, u32::MAX);
-        let mut cursor = cursor.execute_query(&query, &root, StrInput::new(src));
+        let mut cursor = cursor.execute_query(&query, &root, src);
 
         // Consuming matches must not panic even though captures is NULL.
         let mut count = 0;
@@ -414,4 +756,274 @@ mod tests {
         }
         assert!(count > 0, "expected at least one match");
     }
+
+    #[test]
+    fn containing_range_and_max_start_depth() {
+        let grammar = python_grammar();
+        let query = Query::new(grammar, "(expression_statement) @stmt", |_, _| Ok(())).unwrap();
+        let src = "a = 1\nb = 2\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+
+        let mut cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+        cursor.set_containing_byte_range(0..5);
+        let mut cursor = cursor.execute_query(&query, &root, src);
+        let mut starts = Vec::new();
+        while let Some(mat) = cursor.next_match() {
+            starts.push(mat.matched_nodes().next().unwrap().node.start_byte());
+        }
+        assert_eq!(starts, [0]);
+
+        let mut cursor = cursor.reuse();
+        cursor.set_max_start_depth(Some(0));
+        let mut cursor = cursor.execute_query(&query, &root, src);
+        assert!(cursor.next_match().is_none());
+        drop(cursor);
+
+        // Cached cursors must not leak the restrictions above into new users.
+        let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+        let mut cursor = cursor.execute_query(&query, &root, src);
+        let mut count = 0;
+        while cursor.next_match().is_some() {
+            count += 1;
+        }
+        assert_eq!(count, 2);
+    }
+
+    #[test]
+    fn structural_and_lua_predicates() {
+        let grammar = python_grammar();
+        let src = "FOO = 1\nfoo_bar = 2\ndef f(x):\n    return g(x)\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+        let captured = |query: &str| {
+            let query = Query::new(grammar, query, |_, _| Ok(())).unwrap();
+            let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+            let mut cursor = cursor.execute_query(&query, &root, src);
+            let mut captured = Vec::new();
+            while let Some(mat) = cursor.next_match() {
+                for matched_node in mat.matched_nodes() {
+                    captured.push(
+                        &src[matched_node.node.start_byte() as usize
+                            ..matched_node.node.end_byte() as usize],
+                    );
+                }
+            }
+            captured
+        };
+
+        assert_eq!(
+            captured(r#"((identifier) @id (#lua-match? @id "^[%u_]+$"))"#),
+            ["FOO"]
+        );
+        assert_eq!(
+            captured(r#"((identifier) @id (#not-lua-match? @id "^%l"))"#),
+            ["FOO"]
+        );
+        assert_eq!(
+            captured(r#"((identifier) @id (#contains? @id "_b" "OO"))"#),
+            ["FOO", "foo_bar"]
+        );
+        assert_eq!(
+            captured(r#"((identifier) @id (#has-ancestor? @id function_definition))"#),
+            ["f", "x", "g", "x"]
+        );
+        assert_eq!(
+            captured(r#"((identifier) @id (#has-parent? @id call argument_list))"#),
+            ["g", "x"]
+        );
+        assert_eq!(
+            captured(r#"((_) @node (#kind-eq? @node "integer" "parameters"))"#),
+            ["1", "2", "(x)"]
+        );
+        assert_eq!(
+            captured(
+                r#"((expression_statement (assignment left: (_) @left)) (#not-has-ancestor? @left block))"#
+            ),
+            ["FOO", "foo_bar"]
+        );
+
+        let err = Query::new(
+            grammar,
+            r#"((identifier) @id (#lua-match? @id "%b()"))"#,
+            |_, _| Ok(()),
+        )
+        .unwrap_err();
+        assert!(err.to_string().contains("invalid lua pattern"), "{err}");
+    }
+
+    #[test]
+    fn registered_predicates() {
+        let grammar = python_grammar();
+        let source = r#"((identifier) @id (#in-allowlist? @id "allowed"))"#;
+        let mut query = Query::new(grammar, source, |_, predicate| {
+            assert_eq!(predicate.to_string(), "#in-allowlist?");
+            Ok(())
+        })
+        .unwrap();
+        query.register_predicate("in-allowlist?", |evaluation| {
+            let capture = evaluation.query().get_capture("id").unwrap();
+            assert!(matches!(evaluation.args()[0], PredicateArg::Capture(id) if id == capture));
+            let allowlist = evaluation.str_arg(1).unwrap();
+            evaluation
+                .nodes_for_capture(capture)
+                .all(|node| evaluation.text(node).starts_with(allowlist))
+        });
+
+        let src = "allowed_a = denied\nallowed_b = 1\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+        let matched = |query: &Query| {
+            let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+            let mut cursor = cursor.execute_query(query, &root, src);
+            let mut matched = Vec::new();
+            while let Some((mat, idx)) = cursor.next_matched_node() {
+                let node = &mat.matched_node(idx).node;
+                matched.push(&src[node.start_byte() as usize..node.end_byte() as usize]);
+            }
+            matched
+        };
+        assert_eq!(matched(&query), ["allowed_a", "allowed_b"]);
+
+        // registering the predicate again replaces the previous function
+        let regex = Regex::new("^(allowed|denied)").unwrap();
+        query.register_predicate("in-allowlist?", move |evaluation| {
+            let capture = evaluation.query().get_capture("id").unwrap();
+            let node = evaluation.nodes_for_capture(capture).next().unwrap();
+            let range = node.byte_range();
+            let mut input = regex_cursor::Input::new(evaluation.cursor_at(range.start));
+            input.slice(range.start as usize..range.end as usize);
+            regex.is_match(input)
+        });
+        assert_eq!(matched(&query), ["allowed_a", "denied", "allowed_b"]);
+    }
+
+    #[test]
+    fn pattern_and_capture_masks() {
+        let grammar = python_grammar();
+        let source = r#"
+            ((assignment left: (identifier) @left right: (identifier) @right)
+              (#eq? @left @right))
+            (integer) @int
+        "#;
+        let query = Query::new(grammar, source, |_, _| Ok(())).unwrap();
+        let src = "x = x\ny = 1\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+        let captured = |patterns: Option<&PatternMask>, captures: Option<&CaptureMask>| {
+            let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+            let mut cursor = cursor.execute_query(&query, &root, src);
+            cursor.set_pattern_mask(patterns);
+            cursor.set_capture_mask(captures);
+            let mut matches = Vec::new();
+            while let Some(mat) = cursor.next_match() {
+                let captured: Vec<_> = mat
+                    .matched_nodes()
+                    .map(|matched_node| query.capture_name(matched_node.capture))
+                    .collect();
+                matches.push(captured);
+            }
+            let mut cursor = cursor.reuse().execute_query(&query, &root, src);
+            cursor.set_pattern_mask(patterns);
+            cursor.set_capture_mask(captures);
+            let mut nodes = Vec::new();
+            while let Some((mat, idx)) = cursor.next_matched_node() {
+                let node = &mat.matched_node(idx).node;
+                nodes.push(&src[node.start_byte() as usize..node.end_byte() as usize]);
+            }
+            (matches, nodes)
+        };
+
+        let (matches, nodes) = captured(None, None);
+        assert_eq!(matches, [vec!["left", "right"], vec!["int"]]);
+        assert_eq!(nodes, ["x", "x", "1"]);
+
+        let mut patterns = PatternMask::new(&query);
+        patterns.disable(query.patterns().next().unwrap());
+        let (matches, nodes) = captured(Some(&patterns), None);
+        assert_eq!(matches, [vec!["int"]]);
+        assert_eq!(nodes, ["1"]);
+
+        // disabled captures still take part in predicates
+        let mut captures = CaptureMask::new(&query);
+        captures.disable(query.get_capture("left").unwrap());
+        let (matches, nodes) = captured(None, Some(&captures));
+        assert_eq!(matches, [vec!["right"], vec!["int"]]);
+        assert_eq!(nodes, ["x", "1"]);
+
+        let mut patterns = PatternMask::new(&query);
+        patterns.disable(query.patterns().nth(1).unwrap());
+        let (matches, nodes) = captured(Some(&patterns), Some(&captures));
+        assert_eq!(matches, [vec!["right"]]);
+        assert_eq!(nodes, ["x"]);
+        assert_eq!(format!("{captures:?}"), "{Capture(1), Capture(2)}");
+    }
+
+    #[test]
+    fn progress_callback_cancels_query() {
+        let grammar = python_grammar();
+        let query = Query::new(grammar, "(identifier) @id", |_, _| Ok(())).unwrap();
+        let src = "x = y\n".repeat(1000);
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(&src, None).unwrap();
+        let root = tree.root_node();
+
+        let mut calls = 0;
+        let mut callback = |_: &_| {
+            calls += 1;
+            ControlFlow::Break(())
+        };
+        let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+        let mut cursor = cursor.execute_query_with_options(
+            &query,
+            &root,
+            &src,
+            QueryCursorOptions::new(&mut callback),
+        );
+        let mut count = 0;
+        while cursor.next_match().is_some() {
+            count += 1;
+        }
+        drop(cursor);
+        assert!(calls > 0);
+        assert!(count < 2000, "query was not cancelled");
+    }
+
+    #[test]
+    fn progress_callback_panic_is_resumed() {
+        let grammar = python_grammar();
+        let query = Query::new(grammar, "(identifier) @id", |_, _| Ok(())).unwrap();
+        let src = "x = y\n".repeat(1000);
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(&src, None).unwrap();
+        let root = tree.root_node();
+
+        let mut callback = |_: &_| -> ControlFlow<()> { panic!("progress callback") };
+        let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+        let mut cursor = cursor.execute_query_with_options(
+            &query,
+            &root,
+            &src,
+            QueryCursorOptions::new(&mut callback),
+        );
+        let panic =
+            catch_unwind(AssertUnwindSafe(|| while cursor.next_match().is_some() {})).unwrap_err();
+        assert_eq!(panic.downcast_ref::<&str>(), Some(&"progress callback"));
+        drop(cursor);
+
+        // the cursor returned to the cache must not call the old callback
+        let cursor = InactiveQueryCursor::new(0..u32::MAX, u32::MAX);
+        let mut cursor = cursor.execute_query(&query, &root, &src);
+        assert!(cursor.next_match().is_some());
+    }
 }
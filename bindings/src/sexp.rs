@@ -0,0 +1,508 @@
+use std::ffi::{c_char, CStr};
+use std::{fmt, ops};
+
+use crate::node::NodeRaw;
+use crate::tree::ts_current_free;
+use crate::{Node, Tree, TreeCursor};
+
+impl Node<'_> {
+    /// Render the syntax tree of this node as an S-expression.
+    ///
+    /// The format matches the one used by tree-sitter's corpus tests: only
+    /// named nodes are included, children are prefixed with their field name
+    /// and missing nodes and unexpected characters (inside `ERROR` nodes) are
+    /// marked as `(MISSING kind)` and `(UNEXPECTED 'c')`. For example:
+    ///
+    /// ```text
+    /// (module (expression_statement (assignment left: (identifier) right: (integer))))
+    /// ```
+    pub fn to_sexp(&self) -> String {
+        Sexp::from_node(self, false).to_string()
+    }
+
+    /// Render the syntax tree of this node as an S-expression like
+    /// [`Node::to_sexp`], including the byte range of each node:
+    ///
+    /// ```text
+    /// (module [0..6] (expression_statement [0..5] (identifier [0..5])))
+    /// ```
+    pub fn to_sexp_with_byte_ranges(&self) -> String {
+        Sexp::from_node(self, true).to_string()
+    }
+}
+
+/// A parsed S-expression describing a syntax tree, see [`Node::to_sexp`].
+///
+/// S-expressions can be parsed with [`Sexp::parse`] and compared against a
+/// syntax tree with [`Sexp::matches`] which allows writing tests in the style
+/// of tree-sitter's corpus tests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sexp {
+    /// The field name of the node within its parent.
+    pub field: Option<String>,
+    /// The kind of the node. Missing nodes and unexpected characters are
+    /// written as `MISSING kind` and `UNEXPECTED 'c'`.
+    pub kind: String,
+    /// The byte range of the node. When matching against a tree the range is
+    /// only checked if it is specified.
+    pub byte_range: Option<ops::Range<u32>>,
+    pub children: Vec<Sexp>,
+}
+
+impl Sexp {
+    /// Build the S-expression of a node's syntax tree.
+    pub fn from_node(node: &Node, byte_ranges: bool) -> Sexp {
+        let kind = if node.is_named() || node.is_missing() {
+            node_head(node)
+        } else {
+            format!("\"{}\"", node.kind())
+        };
+        let mut sexp = Sexp {
+            field: None,
+            kind,
+            byte_range: byte_ranges.then(|| node.byte_range()),
+            children: Vec::new(),
+        };
+        push_children(&mut node.walk(), byte_ranges, None, &mut sexp.children);
+        sexp
+    }
+
+    /// Parse an S-expression in the format produced by [`Node::to_sexp`] and
+    /// [`Node::to_sexp_with_byte_ranges`].
+    ///
+    /// Whitespace (including newlines) between the elements is ignored.
+    pub fn parse(src: &str) -> Result<Sexp, SexpParseError> {
+        let mut parser = SexpParser { src, pos: 0 };
+        let sexp = parser.node(None)?;
+        parser.skip_whitespace();
+        if parser.pos != src.len() {
+            return Err(parser.error("unexpected trailing input"));
+        }
+        Ok(sexp)
+    }
+
+    /// Check whether the syntax tree of `node` matches this S-expression.
+    pub fn matches(&self, node: &Node) -> Result<(), SexpMismatch> {
+        let actual = Sexp::from_node(node, true);
+        let mut path = Vec::new();
+        match_sexp(self, &actual, &mut path)
+    }
+}
+
+impl fmt::Display for Sexp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if let Some(field) = &self.field {
+            write!(f, "{field}: ")?;
+        }
+        write!(f, "({}", self.kind)?;
+        if let Some(range) = &self.byte_range {
+            write!(f, " [{}..{}]", range.start, range.end)?;
+        }
+        for child in &self.children {
+            write!(f, " {child}")?;
+        }
+        f.write_str(")")
+    }
+}
+
+/// Assert that the syntax tree of `tree` matches the S-expression `expected`.
+///
+/// # Panics
+///
+/// Panics if `expected` is not a valid S-expression or if the tree does not
+/// match it. The panic message describes the first mismatch.
+#[track_caller]
+pub fn assert_tree_matches(tree: &Tree, expected: &str) {
+    let expected = match Sexp::parse(expected) {
+        Ok(expected) => expected,
+        Err(err) => panic!("{err}"),
+    };
+    let root = tree.root_node();
+    if let Err(err) = expected.matches(&root) {
+        panic!(
+            "{err}\n  expected: {expected}\n  actual:   {}",
+            Sexp::from_node(&root, expected.byte_range.is_some())
+        );
+    }
+}
+
+/// The head of a visible node: its kind or the `MISSING`/`UNEXPECTED` marker.
+fn node_head(node: &Node) -> String {
+    if node.is_missing() {
+        if node.is_named() {
+            format!("MISSING {}", node.kind())
+        } else {
+            format!("MISSING \"{}\"", node.kind())
+        }
+    } else if node.is_error() && node.child_count() == 0 && !node.byte_range().is_empty() {
+        // The unexpected character is only known to the C library.
+        let raw = unsafe { ts_node_string(node.as_raw()) };
+        let sexp = unsafe { CStr::from_ptr(raw) }.to_string_lossy();
+        let head = sexp
+            .trim_start_matches('(')
+            .trim_end_matches(')')
+            .to_owned();
+        unsafe { ts_current_free(raw.cast_mut().cast()) };
+        head
+    } else {
+        node.kind().to_owned()
+    }
+}
+
+/// Push the visible children of the cursor's current node to `out`. The
+/// children of hidden and anonymous nodes are flattened into their parent.
+fn push_children(
+    cursor: &mut TreeCursor,
+    byte_ranges: bool,
+    inherited_field: Option<&str>,
+    out: &mut Vec<Sexp>,
+) {
+    if !cursor.goto_first_child() {
+        return;
+    }
+    loop {
+        let node = cursor.node();
+        let field = cursor
+            .field_name()
+            .or(inherited_field.filter(|_| !node.is_extra()));
+        if node.is_named() || node.is_missing() {
+            let mut sexp = Sexp {
+                field: field.map(str::to_owned),
+                kind: node_head(&node),
+                byte_range: byte_ranges.then(|| node.byte_range()),
+                children: Vec::new(),
+            };
+            push_children(cursor, byte_ranges, None, &mut sexp.children);
+            out.push(sexp);
+        } else {
+            push_children(cursor, byte_ranges, field, out);
+        }
+        if !cursor.goto_next_sibling() {
+            break;
+        }
+    }
+    cursor.goto_parent();
+}
+
+fn match_sexp<'a>(
+    expected: &'a Sexp,
+    actual: &'a Sexp,
+    path: &mut Vec<&'a Sexp>,
+) -> Result<(), SexpMismatch> {
+    let mismatch = |path: &[&Sexp], expected: Option<&Sexp>, actual: Option<&Sexp>| {
+        Err(SexpMismatch {
+            path: path.iter().map(|sexp| describe(sexp)).collect(),
+            expected: expected.map(describe),
+            actual: actual.map(describe),
+        })
+    };
+    let range_matches = expected
+        .byte_range
+        .as_ref()
+        .map_or(true, |range| Some(range) == actual.byte_range.as_ref());
+    if expected.field != actual.field || expected.kind != actual.kind || !range_matches {
+        return mismatch(path, Some(expected), Some(actual));
+    }
+    path.push(actual);
+    for i in 0..expected.children.len().max(actual.children.len()) {
+        match (expected.children.get(i), actual.children.get(i)) {
+            (Some(expected), Some(actual)) => match_sexp(expected, actual, path)?,
+            (expected, actual) => return mismatch(path, expected, actual),
+        }
+    }
+    path.pop();
+    Ok(())
+}
+
+/// Describe a node of an S-expression without its children.
+fn describe(sexp: &Sexp) -> String {
+    Sexp {
+        children: Vec::new(),
+        ..sexp.clone()
+    }
+    .to_string()
+}
+
+/// A syntax tree which does not match the expected S-expression, see
+/// [`Sexp::matches`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SexpMismatch {
+    /// The (actual) ancestors of the mismatched node, starting at the root.
+    pub path: Vec<String>,
+    /// The expected node or `None` if the tree has an unexpected extra node.
+    pub expected: Option<String>,
+    /// The node in the tree or `None` if an expected node is missing.
+    pub actual: Option<String>,
+}
+
+impl fmt::Display for SexpMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("syntax tree mismatch")?;
+        if !self.path.is_empty() {
+            write!(f, " in {}", self.path.join(" > "))?;
+        }
+        let expected = self.expected.as_deref().unwrap_or("no more children");
+        let actual = self.actual.as_deref().unwrap_or("no more children");
+        write!(f, ": expected {expected}, found {actual}")
+    }
+}
+
+impl std::error::Error for SexpMismatch {}
+
+/// An error encountered while parsing an S-expression with [`Sexp::parse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SexpParseError {
+    /// The byte offset of the error.
+    pub offset: usize,
+    pub message: &'static str,
+}
+
+impl fmt::Display for SexpParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "invalid S-expression at byte {}: {}",
+            self.offset, self.message
+        )
+    }
+}
+
+impl std::error::Error for SexpParseError {}
+
+struct SexpParser<'a> {
+    src: &'a str,
+    pos: usize,
+}
+
+impl<'a> SexpParser<'a> {
+    fn error(&self, message: &'static str) -> SexpParseError {
+        SexpParseError {
+            offset: self.pos,
+            message,
+        }
+    }
+
+    fn rest(&self) -> &str {
+        &self.src[self.pos..]
+    }
+
+    fn skip_whitespace(&mut self) {
+        self.pos = self.src.len() - self.rest().trim_start().len();
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        self.skip_whitespace();
+        if self.rest().starts_with(c) {
+            self.pos += c.len_utf8();
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Parse `(kind [start..end] children...)`, the field name has already
+    /// been consumed.
+    fn node(&mut self, field: Option<String>) -> Result<Sexp, SexpParseError> {
+        if !self.eat('(') {
+            return Err(self.error("expected `(`"));
+        }
+        let mut kind = self.atom()?;
+        if kind == "MISSING" || kind == "UNEXPECTED" {
+            kind.push(' ');
+            kind.push_str(&self.atom()?);
+        }
+        let byte_range = if self.eat('[') {
+            let start = self.number()?;
+            self.skip_whitespace();
+            if !self.rest().starts_with("..") {
+                return Err(self.error("expected `..`"));
+            }
+            self.pos += 2;
+            let end = self.number()?;
+            if !self.eat(']') {
+                return Err(self.error("expected `]`"));
+            }
+            Some(start..end)
+        } else {
+            None
+        };
+        let mut children = Vec::new();
+        while !self.eat(')') {
+            if self.rest().is_empty() {
+                return Err(self.error("unclosed `(`"));
+            }
+            let field = if self.rest().starts_with('(') {
+                None
+            } else {
+                let field = self.atom()?;
+                if !self.eat(':') {
+                    return Err(self.error("expected `:` after field name"));
+                }
+                Some(field)
+            };
+            children.push(self.node(field)?);
+        }
+        Ok(Sexp {
+            field,
+            kind,
+            byte_range,
+            children,
+        })
+    }
+
+    /// Parse a symbol, a `"quoted"` string or a `'c'` character literal.
+    fn atom(&mut self) -> Result<String, SexpParseError> {
+        self.skip_whitespace();
+        let rest = &self.src[self.pos..];
+        let len = if rest.starts_with('"') {
+            // Quoted node kinds are not escaped, the closing quote is the
+            // first quote which ends the atom.
+            rest.char_indices()
+                .skip(1)
+                .find(|&(i, c)| {
+                    c == '"'
+                        && rest[i + 1..]
+                            .chars()
+                            .next()
+                            .map_or(true, |next| next == ')' || next.is_whitespace())
+                })
+                .map(|(i, _)| i + 1)
+                .ok_or_else(|| self.error("unclosed string"))?
+        } else if rest.starts_with('\'') {
+            let mut chars = rest.char_indices().skip(1);
+            if let Some((_, '\\')) = chars.next() {
+                chars.next();
+            }
+            match chars.next() {
+                Some((i, '\'')) => i + 1,
+                _ => return Err(self.error("invalid character literal")),
+            }
+        } else {
+            rest.find(|c: char| c.is_whitespace() || "()[]:".contains(c))
+                .unwrap_or(rest.len())
+        };
+        if len == 0 {
+            return Err(self.error("expected a node kind"));
+        }
+        self.pos += len;
+        Ok(rest[..len].to_owned())
+    }
+
+    fn number(&mut self) -> Result<u32, SexpParseError> {
+        self.skip_whitespace();
+        let rest = &self.src[self.pos..];
+        let len = rest
+            .find(|c: char| !c.is_ascii_digit())
+            .unwrap_or(rest.len());
+        let number = rest[..len]
+            .parse()
+            .map_err(|_| self.error("expected a byte offset"))?;
+        self.pos += len;
+        Ok(number)
+    }
+}
+
+extern "C" {
+    /// Get an S-expression representing the node as a string.
+    ///
+    /// This string is allocated with `malloc` and the caller is responsible for
+    /// freeing it using `free`.
+    fn ts_node_string(node: NodeRaw) -> *const c_char;
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CStr;
+
+    use super::{assert_tree_matches, ts_node_string, Sexp};
+    use crate::test_grammars::python_grammar;
+    use crate::tree::ts_current_free;
+    use crate::{Node, Parser, Tree};
+
+    fn parse(src: &str) -> Tree {
+        let mut parser = Parser::new();
+        parser.set_grammar(python_grammar()).unwrap();
+        parser.parse(src, None).unwrap()
+    }
+
+    fn c_sexp(node: &Node) -> String {
+        unsafe {
+            let raw = ts_node_string(node.as_raw());
+            let sexp = CStr::from_ptr(raw).to_str().unwrap().to_owned();
+            ts_current_free(raw.cast_mut().cast());
+            sexp
+        }
+    }
+
+    #[test]
+    fn to_sexp_matches_c_library() {
+        for src in [
+            "x = 1\n",
+            "def f(a, *b, c=1):\n    # comment\n    return a[b] if c else {}\n",
+            "class A(B):\n    @property\n    def x(self): pass\n",
+            "print(1 +)\n",
+            "x = [1, 2\n",
+            "def f(:\n  x = $\n",
+            "for x in y:\n",
+        ] {
+            let tree = parse(src);
+            let root = tree.root_node();
+            let sexp = root.to_sexp();
+            assert_eq!(sexp, c_sexp(&root), "{src:?}");
+            assert_eq!(Sexp::parse(&sexp).unwrap().to_string(), sexp);
+            let with_ranges = root.to_sexp_with_byte_ranges();
+            assert_eq!(Sexp::parse(&with_ranges).unwrap().to_string(), with_ranges);
+            assert_eq!(Sexp::parse(&with_ranges).unwrap().matches(&root), Ok(()));
+        }
+        assert_eq!(
+            parse("x = 1").root_node().to_sexp_with_byte_ranges(),
+            "(module [0..5] (expression_statement [0..5] (assignment [0..5] \
+             left: (identifier [0..1]) right: (integer [4..5]))))"
+        );
+    }
+
+    #[test]
+    fn match_tree() {
+        let tree = parse("def f(a):\n    return a\n");
+        assert_tree_matches(
+            &tree,
+            "
+            (module
+              (function_definition
+                name: (identifier)
+                parameters: (parameters (identifier))
+                body: (block
+                  (return_statement (identifier [21..22])))))",
+        );
+
+        let expected = Sexp::parse(
+            "(module (function_definition name: (identifier) parameters: (parameters) body: (block)))",
+        )
+        .unwrap();
+        let mismatch = expected.matches(&tree.root_node()).unwrap_err();
+        assert_eq!(
+            mismatch.to_string(),
+            "syntax tree mismatch in (module [0..23]) > (function_definition [0..22]) > \
+             parameters: (parameters [5..8]): expected no more children, found (identifier [6..7])"
+        );
+
+        let tree = parse("x = (1\n");
+        assert_tree_matches(&tree, "(module (ERROR (identifier [0..1]) (integer)))");
+
+        let err = Sexp::parse("(module (x) name:").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "invalid S-expression at byte 17: expected `(`"
+        );
+    }
+
+    #[test]
+    #[should_panic = "expected right: (integer), found right: (identifier [4..5])"]
+    fn assert_tree_matches_panics_on_mismatch() {
+        let tree = parse("x = y\n");
+        assert_tree_matches(
+            &tree,
+            "(module (expression_statement (assignment left: (identifier) right: (integer))))",
+        );
+    }
+}
@@ -0,0 +1,31 @@
+//! Grammars shared by the unit tests.
+
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use skidder::Repo;
+
+use crate::Grammar;
+
+/// Builds the python grammar in `test-grammars` (if it isn't up to date
+/// already) and returns the path to its shared library.
+pub fn python_library() -> &'static Path {
+    static LIBRARY: OnceLock<PathBuf> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let config = skidder::Config {
+            repos: vec![Repo::Local {
+                path: Path::new(env!("CARGO_MANIFEST_DIR"))
+                    .join("../test-grammars")
+                    .canonicalize()
+                    .unwrap(),
+            }],
+            index: PathBuf::new(),
+            verbose: false,
+        };
+        skidder::build_grammar(&config, "python", false).expect("failed to build python grammar")
+    })
+}
+
+pub fn python_grammar() -> Grammar {
+    unsafe { Grammar::new("python", python_library()) }.expect("python grammar")
+}
@@ -1,19 +1,37 @@
-use std::fmt;
+use std::ffi::c_void;
+#[cfg(unix)]
+use std::os::fd::AsRawFd;
+use std::os::raw::c_int;
 use std::ptr::NonNull;
+use std::{fmt, slice};
 
+use crate::grammar_registry::GrammarRef;
 use crate::node::{Node, NodeRaw};
-use crate::{Point, TreeCursor};
+use crate::{Grammar, Point, Range, TreeCursor};
 
 // opaque pointers
 pub(super) enum SyntaxTreeData {}
 
 pub struct Tree {
     ptr: NonNull<SyntaxTreeData>,
+    grammar: GrammarRef,
 }
 
 impl Tree {
     pub(super) unsafe fn from_raw(raw: NonNull<SyntaxTreeData>) -> Tree {
-        Tree { ptr: raw }
+        Tree {
+            ptr: raw,
+            grammar: GrammarRef::new(ts_tree_language(raw)),
+        }
+    }
+
+    /// Create a (shallow) copy of the tree behind a raw pointer, for example
+    /// the tree a [`Node`] belongs to.
+    pub(super) unsafe fn copy_raw(raw: NonNull<SyntaxTreeData>) -> Tree {
+        Tree {
+            ptr: ts_tree_copy(raw),
+            grammar: GrammarRef::new(ts_tree_language(raw)),
+        }
     }
 
     pub(super) fn as_raw(&self) -> NonNull<SyntaxTreeData> {
@@ -24,6 +42,36 @@ impl Tree {
         unsafe { Node::from_raw(ts_tree_root_node(self.ptr)).unwrap() }
     }
 
+    /// Get the root node of the syntax tree, with its position shifted
+    /// forward by the given offset.
+    pub fn root_node_with_offset(&self, offset_bytes: u32, offset_extent: Point) -> Node<'_> {
+        unsafe {
+            Node::from_raw(ts_tree_root_node_with_offset(
+                self.ptr,
+                offset_bytes,
+                offset_extent,
+            ))
+            .unwrap()
+        }
+    }
+
+    /// Get the included ranges that were used to parse the syntax tree.
+    pub fn included_ranges(&self) -> Vec<Range> {
+        let mut len = 0u32;
+        unsafe {
+            let ptr = ts_tree_included_ranges(self.ptr, &mut len);
+            let ranges = if len == 0 {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(ptr, len as usize).to_vec()
+            };
+            if !ptr.is_null() {
+                ts_current_free(ptr.cast());
+            }
+            ranges
+        }
+    }
+
     pub fn edit(&mut self, edit: &InputEdit) {
         unsafe { ts_tree_edit(self.ptr, edit) }
     }
@@ -31,6 +79,49 @@ impl Tree {
     pub fn walk(&self) -> TreeCursor<'_> {
         self.root_node().walk()
     }
+
+    /// Compare an old edited syntax tree to a new syntax tree representing the
+    /// same document, returning the ranges whose syntactic structure has
+    /// changed.
+    ///
+    /// For this to work correctly, `old_tree` must have been edited such that
+    /// its ranges match up to `new_tree`. Generally you'll want to call this
+    /// right after parsing, passing the old tree that was passed to the parser
+    /// and the new tree that the parser returned.
+    ///
+    /// The returned ranges indicate areas where the hierarchical structure of
+    /// syntax nodes (from root to leaf) has changed between the old and new
+    /// trees. Characters outside these ranges have identical ancestor nodes in
+    /// both trees. The ranges may be slightly larger than the exact changed
+    /// areas.
+    pub fn changed_ranges(
+        old_tree: &Tree,
+        new_tree: &Tree,
+    ) -> impl ExactSizeIterator<Item = Range> {
+        let mut len = 0u32;
+        unsafe {
+            let ptr = ts_tree_get_changed_ranges(old_tree.ptr, new_tree.ptr, &mut len);
+            let ranges = if len == 0 {
+                Vec::new()
+            } else {
+                slice::from_raw_parts(ptr, len as usize).to_vec()
+            };
+            if !ptr.is_null() {
+                ts_current_free(ptr.cast());
+            }
+            ranges.into_iter()
+        }
+    }
+
+    /// Write a DOT graph describing the syntax tree to the given file.
+    ///
+    /// The graph can be rendered with `dot(1)`. The file descriptor is
+    /// duplicated so the caller keeps ownership of `file`. This is only
+    /// available on unix.
+    #[cfg(unix)]
+    pub fn print_dot_graph(&self, file: &impl AsRawFd) {
+        unsafe { ts_tree_print_dot_graph(self.ptr, file.as_raw_fd()) }
+    }
 }
 
 impl fmt::Debug for Tree {
@@ -47,10 +138,9 @@ impl Drop for Tree {
 
 impl Clone for Tree {
     fn clone(&self) -> Self {
-        unsafe {
-            Tree {
-                ptr: ts_tree_copy(self.ptr),
-            }
+        Tree {
+            ptr: unsafe { ts_tree_copy(self.ptr) },
+            grammar: self.grammar.clone(),
         }
     }
 }
@@ -60,6 +150,7 @@ unsafe impl Sync for Tree {}
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct InputEdit {
     pub start_byte: u32,
     pub old_end_byte: u32,
@@ -87,12 +178,42 @@ extern "C" {
     fn ts_tree_copy(self_: NonNull<SyntaxTreeData>) -> NonNull<SyntaxTreeData>;
     /// Delete the syntax tree, freeing all of the memory that it used.
     fn ts_tree_delete(self_: NonNull<SyntaxTreeData>);
+    /// Get the language that was used to parse the syntax tree.
+    fn ts_tree_language(self_: NonNull<SyntaxTreeData>) -> Grammar;
     /// Get the root node of the syntax tree.
     fn ts_tree_root_node<'tree>(self_: NonNull<SyntaxTreeData>) -> NodeRaw;
+    /// Get the root node of the syntax tree, but with its position shifted
+    /// forward by the given offset.
+    fn ts_tree_root_node_with_offset(
+        self_: NonNull<SyntaxTreeData>,
+        offset_bytes: u32,
+        offset_extent: Point,
+    ) -> NodeRaw;
+    /// Get the array of included ranges that was used to parse the syntax
+    /// tree.
+    ///
+    /// The returned pointer must be freed by the caller.
+    fn ts_tree_included_ranges(self_: NonNull<SyntaxTreeData>, length: &mut u32) -> *mut Range;
     /// Edit the syntax tree to keep it in sync with source code that has been
     /// edited.
     ///
     /// You must describe the edit both in terms of byte offsets and in terms of
     /// row/column coordinates.
     fn ts_tree_edit(self_: NonNull<SyntaxTreeData>, edit: &InputEdit);
+    /// Compare an old edited syntax tree to a new syntax tree representing the
+    /// same document, returning an array of ranges whose syntactic structure has
+    /// changed.
+    ///
+    /// The returned array is allocated using the tree-sitter allocator and the
+    /// caller is responsible for freeing it. The length of the array will be
+    /// written to the given `length` pointer.
+    fn ts_tree_get_changed_ranges(
+        old_tree: NonNull<SyntaxTreeData>,
+        new_tree: NonNull<SyntaxTreeData>,
+        length: &mut u32,
+    ) -> *mut Range;
+    /// Write a DOT graph describing the syntax tree to the given file.
+    fn ts_tree_print_dot_graph(self_: NonNull<SyntaxTreeData>, file_descriptor: c_int);
+    /// The deallocation function used by the tree-sitter library.
+    pub(crate) static ts_current_free: unsafe extern "C" fn(ptr: *mut c_void);
 }
@@ -6,7 +6,7 @@ use std::marker::PhantomData;
 use std::{fmt, mem};
 
 use crate::node::NodeRaw;
-use crate::{Node, Tree};
+use crate::{FieldId, Node, Point, Tree};
 
 thread_local! {
     static CACHE: Cell<Option<TreeCursorGuard>> = const { Cell::new(None) };
@@ -77,6 +77,51 @@ impl<'tree> TreeCursor<'tree> {
         }
     }
 
+    /// Move the cursor to the first child of its current node that extends
+    /// beyond the given point.
+    ///
+    /// Returns the index of the child node if one was found.
+    pub fn goto_first_child_for_point(&mut self, point: Point) -> Option<u32> {
+        match unsafe { ts_tree_cursor_goto_first_child_for_point(&mut self.inner, point) } {
+            -1 => None,
+            n => Some(n as u32),
+        }
+    }
+
+    /// Move the cursor to the node that is the nth descendant of the node the
+    /// cursor was created with (or last reset to), where zero represents that
+    /// node itself.
+    ///
+    /// Descendants are numbered in pre-order, see [`Node::descendants`].
+    pub fn goto_descendant(&mut self, descendant_index: u32) {
+        unsafe { ts_tree_cursor_goto_descendant(&mut self.inner, descendant_index) }
+    }
+
+    /// Get the index of the cursor's current node out of all of the
+    /// descendants of the node the cursor was created with (or last reset to).
+    pub fn descendant_index(&self) -> u32 {
+        unsafe { ts_tree_cursor_current_descendant_index(&self.inner) }
+    }
+
+    /// Get the depth of the cursor's current node relative to the node the
+    /// cursor was created with (or last reset to).
+    pub fn depth(&self) -> u32 {
+        unsafe { ts_tree_cursor_current_depth(&self.inner) }
+    }
+
+    /// Traverse the subtree of the current node depth-first.
+    ///
+    /// Yields a [`TraversalEvent::Enter`] event when a node is first visited
+    /// and a [`TraversalEvent::Leave`] event once all of its children have
+    /// been visited. Depths are relative to the current node. When the
+    /// traversal is finished the cursor is back on the node it started from.
+    pub fn traverse(&mut self) -> Traverse<'_, 'tree> {
+        Traverse {
+            cursor: self,
+            state: TraversalState::default(),
+        }
+    }
+
     pub fn reset(&mut self, node: &Node<'tree>) {
         unsafe { ts_tree_cursor_reset(&mut self.inner, node.as_raw()) }
     }
@@ -91,6 +136,14 @@ impl<'tree> TreeCursor<'tree> {
             (!ptr.is_null()).then(|| CStr::from_ptr(ptr).to_str().unwrap())
         }
     }
+
+    /// Get the numerical field id of the cursor's current node.
+    ///
+    /// Returns `None` if the current node doesn't have a field. See also
+    /// [`Node::child_by_field_id`] and [`crate::Grammar::field_id_for_name`].
+    pub fn field_id(&self) -> Option<FieldId> {
+        unsafe { ts_tree_cursor_current_field_id(&self.inner) }
+    }
 }
 
 impl fmt::Debug for TreeCursorRaw {
@@ -169,6 +222,98 @@ impl<'tree> Iterator for TreeRecursiveWalker<'_, 'tree> {
     }
 }
 
+/// An event of a depth-first traversal, see [`TreeCursor::traverse`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TraversalEvent<'tree> {
+    /// The node is visited before its children.
+    Enter { node: Node<'tree>, depth: u32 },
+    /// The node is visited after its children.
+    Leave { node: Node<'tree>, depth: u32 },
+}
+
+impl<'tree> TraversalEvent<'tree> {
+    pub fn node(&self) -> &Node<'tree> {
+        match self {
+            Self::Enter { node, .. } | Self::Leave { node, .. } => node,
+        }
+    }
+
+    pub fn depth(&self) -> u32 {
+        match self {
+            Self::Enter { depth, .. } | Self::Leave { depth, .. } => *depth,
+        }
+    }
+}
+
+/// The state of a depth-first traversal of a cursor's current node.
+#[derive(Debug, Default)]
+pub(crate) struct TraversalState {
+    started: bool,
+    /// Whether the last event entered the cursor's current node.
+    entered: bool,
+    depth: u32,
+    done: bool,
+}
+
+impl TraversalState {
+    pub(crate) fn next<'tree>(
+        &mut self,
+        cursor: &mut TreeCursor<'tree>,
+    ) -> Option<TraversalEvent<'tree>> {
+        if self.done {
+            return None;
+        }
+        if !self.started {
+            self.started = true;
+            self.entered = true;
+            return Some(TraversalEvent::Enter {
+                node: cursor.node(),
+                depth: 0,
+            });
+        }
+        if self.entered {
+            if cursor.goto_first_child() {
+                self.depth += 1;
+            } else {
+                self.entered = false;
+                return Some(TraversalEvent::Leave {
+                    node: cursor.node(),
+                    depth: self.depth,
+                });
+            }
+        } else if self.depth == 0 {
+            self.done = true;
+            return None;
+        } else if !cursor.goto_next_sibling() {
+            cursor.goto_parent();
+            self.depth -= 1;
+            return Some(TraversalEvent::Leave {
+                node: cursor.node(),
+                depth: self.depth,
+            });
+        }
+        self.entered = true;
+        Some(TraversalEvent::Enter {
+            node: cursor.node(),
+            depth: self.depth,
+        })
+    }
+}
+
+/// A depth-first traversal created by [`TreeCursor::traverse`].
+pub struct Traverse<'cursor, 'tree> {
+    cursor: &'cursor mut TreeCursor<'tree>,
+    state: TraversalState,
+}
+
+impl<'tree> Iterator for Traverse<'_, 'tree> {
+    type Item = TraversalEvent<'tree>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.state.next(self.cursor)
+    }
+}
+
 extern "C" {
     /// Create a new tree cursor starting from the given node.
     ///
@@ -189,14 +334,10 @@ extern "C" {
     // fn ts_tree_cursor_reset_to(dst: *mut TreeCursorRaw, src: *const TreeCursorRaw);
     /// Get the tree cursor's current node.
     fn ts_tree_cursor_current_node(self_: *const TreeCursorRaw) -> NodeRaw;
-    // /// Get the field name of the tree cursor's current node.
-    // /// This returns `NULL` if the current node doesn't have a field.
-    // /// See also [`ts_node_child_by_field_name`].
-    // fn ts_tree_cursor_current_field_name(self_: *const TreeCursorRaw) -> *const raw::c_char;
-    // /// Get the field id of the tree cursor's current node.
-    // /// This returns zero if the current node doesn't have a field.
-    // /// See also [`ts_node_child_by_field_id`], [`ts_language_field_id_for_name`].
-    // fn ts_tree_cursor_current_field_id(self_: *const TreeCursorRaw) -> TSFieldId;
+    /// Get the field id of the tree cursor's current node.
+    /// This returns zero if the current node doesn't have a field.
+    /// See also `ts_node_child_by_field_id`, `ts_language_field_id_for_name`.
+    fn ts_tree_cursor_current_field_id(self_: *const TreeCursorRaw) -> Option<FieldId>;
     /// Move the cursor to the parent of its current node.
     /// This returns `true` if the cursor successfully moved, and returns `false`
     /// if there was no parent node (the cursor was already on the root node).
@@ -224,7 +365,6 @@ extern "C" {
     /// because it needs to iterate through all the children to compute the child's
     /// position.
     fn ts_tree_cursor_goto_last_child(self_: *mut TreeCursorRaw) -> bool;
-    /*
     /// Move the cursor to the node that is the nth descendant of
     /// the original node that the cursor was constructed with, where
     /// zero represents the original node itself.
@@ -235,12 +375,15 @@ extern "C" {
     /// Get the depth of the cursor's current node relative to the original
     /// node that the cursor was constructed with.
     fn ts_tree_cursor_current_depth(self_: *const TreeCursorRaw) -> u32;
-    */
     /// Move the cursor to the first child of its current node that extends beyond
     /// the given byte offset or point.
     /// This returns the index of the child node if one was found, and returns -1
     /// if no such child was found.
     fn ts_tree_cursor_goto_first_child_for_byte(self_: *mut TreeCursorRaw, goal_byte: u32) -> i64;
+    fn ts_tree_cursor_goto_first_child_for_point(
+        self_: *mut TreeCursorRaw,
+        goal_point: Point,
+    ) -> i64;
     fn ts_tree_cursor_copy(cursor: *const TreeCursorRaw) -> TreeCursorRaw;
     /// Get the field name of the tree cursor's curren tnode.
     ///
@@ -252,40 +395,10 @@ extern "C" {
 #[cfg(test)]
 mod tests {
     use std::collections::HashSet;
-    use std::path::Path;
-
-    use super::TreeCursor;
-    use crate::{Grammar, Input, Node, Parser};
-
-    struct StrInput<'a> {
-        src: &'a str,
-        cursor: &'a str,
-    }
-
-    impl<'a> StrInput<'a> {
-        fn new(src: &'a str) -> Self {
-            Self { src, cursor: src }
-        }
-    }
-
-    impl<'a> Input for StrInput<'a> {
-        type Cursor = &'a str;
 
-        fn cursor_at(&mut self, _offset: u32) -> &mut &'a str {
-            self.cursor = self.src;
-            &mut self.cursor
-        }
-
-        fn eq(&mut self, r1: std::ops::Range<u32>, r2: std::ops::Range<u32>) -> bool {
-            let b = self.src.as_bytes();
-            b[r1.start as usize..r1.end as usize] == b[r2.start as usize..r2.end as usize]
-        }
-    }
-
-    fn python_grammar() -> Grammar {
-        let so = Path::new(env!("CARGO_MANIFEST_DIR")).join("../test-grammars/python/python.so");
-        unsafe { Grammar::new("python", &so) }.expect("python grammar")
-    }
+    use super::{TraversalEvent, TreeCursor};
+    use crate::test_grammars::python_grammar;
+    use crate::{Node, Parser};
 
     fn collect_descendants(node: &Node, out: &mut HashSet<usize>) {
         for i in 0..node.child_count() {
@@ -301,7 +414,7 @@ mod tests {
         let src = "x = 1\ny = [1, 2, 3]\n";
         let mut parser = Parser::new();
         parser.set_grammar(grammar).unwrap();
-        let tree = parser.parse(StrInput::new(src), None).unwrap();
+        let tree = parser.parse(src, None).unwrap();
         let root = tree.root_node();
 
         let mut expected = HashSet::new();
@@ -321,4 +434,83 @@ mod tests {
             "walker must visit every descendant exactly once"
         );
     }
+
+    #[test]
+    fn depth_first_traversal() {
+        let grammar = python_grammar();
+        let src = "x = 1\ny = [1, 2]\n";
+        let mut parser = Parser::new();
+        parser.set_grammar(grammar).unwrap();
+        let tree = parser.parse(src, None).unwrap();
+        let root = tree.root_node();
+
+        let mut expected = HashSet::new();
+        collect_descendants(&root, &mut expected);
+        let descendants: Vec<_> = root.descendants().collect();
+        assert_eq!(descendants[0], root);
+        assert_eq!(descendants.len(), expected.len() + 1);
+        assert!(descendants[1..]
+            .iter()
+            .all(|node| expected.contains(&node.id())));
+
+        // enter and leave events are balanced and report the cursor depth
+        let mut cursor = root.walk();
+        let mut stack = Vec::new();
+        let mut entered = Vec::new();
+        for event in cursor.traverse() {
+            match event {
+                TraversalEvent::Enter { node, depth } => {
+                    assert_eq!(depth as usize, stack.len());
+                    if let Some(parent) = stack.last() {
+                        assert_eq!(node.parent().as_ref(), Some(parent));
+                    }
+                    entered.push(node.clone());
+                    stack.push(node);
+                }
+                TraversalEvent::Leave { node, depth } => {
+                    assert_eq!(stack.pop(), Some(node));
+                    assert_eq!(depth as usize, stack.len());
+                }
+            }
+        }
+        assert!(stack.is_empty());
+        assert_eq!(entered, descendants);
+        assert_eq!(cursor.node(), root);
+
+        // descendants are numbered in pre-order
+        for (i, node) in descendants.iter().enumerate() {
+            cursor.goto_descendant(i as u32);
+            assert_eq!(&cursor.node(), node);
+            assert_eq!(cursor.descendant_index(), i as u32);
+            assert_eq!(cursor.depth() as usize, node.ancestors().count() - 1);
+        }
+
+        // traversing a subtree doesn't leave it
+        let list = root
+            .descendants()
+            .find(|node| node.kind() == "list")
+            .unwrap();
+        let kinds: Vec<_> = list
+            .walk()
+            .traverse()
+            .filter(|event| matches!(event, TraversalEvent::Leave { .. }))
+            .map(|event| (event.node().kind(), event.depth()))
+            .collect();
+        assert_eq!(
+            kinds,
+            [
+                ("[", 1),
+                ("integer", 1),
+                (",", 1),
+                ("integer", 1),
+                ("]", 1),
+                ("list", 0)
+            ]
+        );
+        let ancestors: Vec<_> = list.ancestors().map(|node| node.kind()).collect();
+        assert_eq!(
+            ancestors,
+            ["list", "assignment", "expression_statement", "module"]
+        );
+    }
 }
@@ -0,0 +1,137 @@
+use std::collections::HashSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use tree_house::highlighter::Highlight;
+use tree_house::query_lint::{format_query, lint_query, QueryKind};
+use tree_house::read_query_with_source_map;
+use tree_house::tree_sitter::Grammar;
+
+use crate::flags::CheckQueries;
+
+impl CheckQueries {
+    pub fn run(self) -> Result<()> {
+        let repo = self
+            .repo
+            .canonicalize()
+            .with_context(|| format!("failed to access {}", self.repo.display()))?;
+        let highlights: Option<HashSet<String>> = self
+            .highlights
+            .as_ref()
+            .map(|path| {
+                let names = fs::read_to_string(path)
+                    .with_context(|| format!("failed to read {}", path.display()))?;
+                Ok::<_, anyhow::Error>(names.lines().map(|name| name.trim().to_owned()).collect())
+            })
+            .transpose()?;
+        let config = skidder::Config {
+            repos: vec![skidder::Repo::Local { path: repo.clone() }],
+            index: PathBuf::new(),
+            verbose: false,
+        };
+        let grammars = match self.grammar {
+            Some(grammar) => vec![grammar],
+            None => grammar_names(&repo)?,
+        };
+
+        let mut problems = 0;
+        for name in grammars {
+            let grammar = match load_grammar(&config, &repo, &name) {
+                Ok(grammar) => grammar,
+                Err(err) => {
+                    println!("error: {err:#}");
+                    problems += 1;
+                    continue;
+                }
+            };
+            for kind in QueryKind::ALL {
+                let path = repo.join(&name).join(kind.file_name());
+                let Ok(text) = fs::read_to_string(&path) else {
+                    continue;
+                };
+                // syntax errors are reported by the linter
+                if let Ok(formatted) = format_query(&text) {
+                    if formatted != text {
+                        if self.fix {
+                            fs::write(&path, formatted)
+                                .with_context(|| format!("failed to write {}", path.display()))?;
+                        } else {
+                            println!("{}: not formatted", path.display());
+                            problems += 1;
+                        }
+                    }
+                }
+
+                let (query, source_map) = read_query_with_source_map(&name, |language| {
+                    fs::read_to_string(repo.join(language).join(kind.file_name()))
+                        .unwrap_or_default()
+                });
+                let highlight = |mut name: &str| {
+                    let Some(highlights) = &highlights else {
+                        return Some(Highlight::new(0));
+                    };
+                    loop {
+                        if highlights.contains(name) {
+                            return Some(Highlight::new(0));
+                        }
+                        name = name.rsplit_once('.')?.0;
+                    }
+                };
+                for lint in lint_query(grammar, kind, &query, highlight) {
+                    let severity = if lint.is_error() { "error" } else { "warning" };
+                    let (language, location) = source_map
+                        .resolve_location(&lint.location)
+                        .unwrap_or((&name, lint.location));
+                    let path = repo.join(language).join(kind.file_name());
+                    println!(
+                        "{severity}: {}\n  in {}\n{location}",
+                        lint.message,
+                        path.display()
+                    );
+                    problems += 1;
+                }
+            }
+        }
+        if problems != 0 {
+            bail!("found {problems} problems");
+        }
+        Ok(())
+    }
+}
+
+/// Builds and loads the parser of a grammar. Grammars which reuse the parser
+/// of another grammar load that grammar's parser.
+fn load_grammar(config: &skidder::Config, repo: &Path, name: &str) -> Result<Grammar> {
+    let metadata = skidder::Metadata::read(&repo.join(name).join("metadata.json"))?;
+    let parser_name = match metadata {
+        skidder::Metadata::ReuseParser { name } => name,
+        skidder::Metadata::ParserDefinition(_) => name.to_owned(),
+    };
+    skidder::build_grammar(config, &parser_name, false)
+        .with_context(|| format!("failed to build the parser of {name}"))?;
+    let (parser_name, parser) = config
+        .compiled_parser_path(name)
+        .with_context(|| format!("failed to find the parser of {name}"))?;
+    unsafe { Grammar::new(&parser_name, &parser) }
+        .with_context(|| format!("failed to load {}", parser.display()))
+}
+
+/// The names of all grammars in a repository, including grammars which reuse
+/// the parser of another grammar.
+fn grammar_names(repo: &Path) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for dent in
+        fs::read_dir(repo).with_context(|| format!("failed to access {}", repo.display()))?
+    {
+        let dent = dent.with_context(|| format!("failed to access {}", repo.display()))?;
+        if !dent.path().join("metadata.json").exists() {
+            continue;
+        }
+        if let Some(name) = dent.file_name().to_str() {
+            names.push(name.to_owned());
+        }
+    }
+    names.sort();
+    Ok(names)
+}
@@ -35,6 +35,18 @@ xflags::xflags! {
             optional -r, --recursive
             required path: PathBuf
         }
+        /// Check that the queries of the grammars in a repository are
+        /// formatted and report problems like unknown node kinds, captures
+        /// and properties
+        cmd check-queries {
+            /// Format the queries instead of reporting unformatted queries
+            optional --fix
+            /// A file with the recognized highlight names, one per line. Unknown
+            /// highlight captures are only reported if this is set
+            optional --highlights highlights: PathBuf
+            required repo: PathBuf
+            optional grammar: String
+        }
         default cmd version {
             optional --version
         }
@@ -56,6 +68,7 @@ pub enum SkidderCmd {
     InitRepo(InitRepo),
     LoadGrammar(LoadGrammar),
     RegenerateParser(RegenerateParser),
+    CheckQueries(CheckQueries),
 }
 
 #[derive(Debug)]
@@ -101,6 +114,15 @@ pub struct RegenerateParser {
     pub recursive: bool,
 }
 
+#[derive(Debug)]
+pub struct CheckQueries {
+    pub repo: PathBuf,
+    pub grammar: Option<String>,
+
+    pub fix: bool,
+    pub highlights: Option<PathBuf>,
+}
+
 impl Skidder {
     #[allow(dead_code)]
     pub fn from_env_or_exit() -> Self {
@@ -5,6 +5,7 @@ use ::skidder::list_grammars;
 use anyhow::Result;
 
 mod build;
+mod check_queries;
 mod flags;
 mod generate_parser;
 mod import;
@@ -28,6 +29,7 @@ fn wrapped_main() -> Result<()> {
     match flags.subcommand {
         flags::SkidderCmd::Import(import_cmd) => import_cmd.run(),
         flags::SkidderCmd::Build(build_cmd) => build_cmd.run(),
+        flags::SkidderCmd::CheckQueries(check_cmd) => check_cmd.run(),
         flags::SkidderCmd::InitRepo(init_cmd) => init_cmd.run(),
         flags::SkidderCmd::LoadGrammar(load_cmd) => load_cmd.run(),
         flags::SkidderCmd::RegenerateParser(generate_cmd) => generate_cmd.run(),
@@ -6,8 +6,6 @@ use crate::highlighter::{Highlight, HighlightQuery};
 use crate::injections_query::{InjectionLanguageMarker, InjectionsQuery};
 use crate::Language;
 
-use std::fmt::Write;
-
 #[derive(Debug)]
 pub struct LanguageConfig {
     pub grammar: Grammar,
@@ -46,41 +44,151 @@ static INHERITS_REGEX: Lazy<Regex> =
     Lazy::new(|| Regex::new(r";+\s*inherits\s*:?\s*([a-z_,()-]+)\s*").unwrap());
 
 /// reads a query by invoking `read_query_text`, handles any `inherits` directives
-pub fn read_query(language: &str, mut read_query_text: impl FnMut(&str) -> String) -> String {
+pub fn read_query(language: &str, read_query_text: impl FnMut(&str) -> String) -> String {
+    read_query_with_source_map(language, read_query_text).0
+}
+
+/// Like [`read_query`] but also returns a [`QuerySourceMap`] which maps offsets
+/// in the returned query back to the query of the language they were read from.
+pub fn read_query_with_source_map(
+    language: &str,
+    mut read_query_text: impl FnMut(&str) -> String,
+) -> (String, QuerySourceMap) {
     fn read_query_impl(
         language: &str,
         read_query_text: &mut impl FnMut(&str) -> String,
         // The chain of languages currently being expanded, used to break cyclic
         // `; inherits:` directives (e.g. `a` inherits `b` and `b` inherits `a`).
         chain: &mut Vec<String>,
-    ) -> String {
+        output: &mut String,
+        source_map: &mut QuerySourceMap,
+    ) {
         if chain.iter().any(|ancestor| ancestor == language) {
-            return String::new();
+            return;
         }
         chain.push(language.to_string());
         let query = read_query_text(language);
+        let file = source_map.files.len();
+        source_map.files.push(QueryFile {
+            language: language.to_string(),
+            text: String::new(),
+        });
 
         // replaces all "; inherits <language>(,<language>)*" with the queries of the given language(s)
-        let result = INHERITS_REGEX
-            .replace_all(&query, |captures: &regex::Captures| {
-                captures[1]
-                    .split(',')
-                    .fold(String::new(), |mut output, language| {
-                        // `write!` to a String cannot fail.
-                        write!(
-                            output,
-                            "\n{}\n",
-                            read_query_impl(language, &mut *read_query_text, chain)
-                        )
-                        .unwrap();
-                        output
-                    })
-            })
-            .into_owned();
+        let mut copied = 0;
+        for captures in INHERITS_REGEX.captures_iter(&query) {
+            let directive = captures.get(0).unwrap();
+            source_map.push_segment(output.len(), file, copied);
+            output.push_str(&query[copied..directive.start()]);
+            for language in captures[1].split(',') {
+                source_map.push_segment(output.len(), file, directive.start());
+                output.push('\n');
+                read_query_impl(language, &mut *read_query_text, chain, output, source_map);
+                source_map.push_segment(output.len(), file, directive.start());
+                output.push('\n');
+            }
+            copied = directive.end();
+        }
+        source_map.push_segment(output.len(), file, copied);
+        output.push_str(&query[copied..]);
+        source_map.files[file].text = query;
         chain.pop();
-        result
     }
-    read_query_impl(language, &mut read_query_text, &mut Vec::new())
+    let mut output = String::new();
+    let mut source_map = QuerySourceMap::default();
+    read_query_impl(
+        language,
+        &mut read_query_text,
+        &mut Vec::new(),
+        &mut output,
+        &mut source_map,
+    );
+    (output, source_map)
+}
+
+#[derive(Debug, Default, Clone)]
+struct QueryFile {
+    language: String,
+    text: String,
+}
+
+/// A contiguous part of a query read with [`read_query_with_source_map`] that
+/// was copied from a single file.
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    start: usize,
+    file: usize,
+    file_start: usize,
+}
+
+/// Maps byte offsets in a query returned by [`read_query_with_source_map`] to
+/// the query of the language they were read from, through any number of
+/// `; inherits` directives.
+#[derive(Debug, Default, Clone)]
+pub struct QuerySourceMap {
+    files: Vec<QueryFile>,
+    segments: Vec<Segment>,
+}
+
+impl QuerySourceMap {
+    fn push_segment(&mut self, start: usize, file: usize, file_start: usize) {
+        let segment = Segment {
+            start,
+            file,
+            file_start,
+        };
+        match self.segments.last_mut() {
+            Some(last) if last.start == start => *last = segment,
+            _ => self.segments.push(segment),
+        }
+    }
+
+    /// Returns the language whose query contains the byte at `offset` of the
+    /// combined query, the text of that language's query and the byte offset
+    /// within it.
+    ///
+    /// The newlines inserted around inherited queries are attributed to the
+    /// `; inherits` directive that caused them.
+    pub fn lookup(&self, offset: usize) -> Option<(&str, &str, usize)> {
+        let idx = self
+            .segments
+            .partition_point(|segment| segment.start <= offset)
+            .checked_sub(1)?;
+        let segment = self.segments[idx];
+        let file = &self.files[segment.file];
+        let file_offset = (segment.file_start + offset - segment.start).min(file.text.len());
+        Some((&file.language, &file.text, file_offset))
+    }
+
+    /// Maps the location of a problem in the combined query to the query file
+    /// of the language that contains it. Returns that language together with
+    /// the location within its query.
+    pub fn resolve_location(
+        &self,
+        location: &query::ParserErrorLocation,
+    ) -> Option<(&str, query::ParserErrorLocation)> {
+        let (language, text, offset) = self.lookup(location.offset as usize)?;
+        let location = query::ParserErrorLocation::new(text, offset, location.len as usize);
+        Some((language, location))
+    }
+
+    /// Rewrites the location of an error reported for the combined query so
+    /// that it refers to the query file of the language that contains it.
+    /// Returns that language together with the updated error.
+    ///
+    /// Errors without a location are attributed to the language the query was
+    /// read for.
+    pub fn resolve_error(&self, mut error: query::ParseError) -> (&str, query::ParseError) {
+        let root = self.files.first().map_or("", |file| &file.language);
+        let Some(location) = error.location_mut() else {
+            return (root, error);
+        };
+        let Some((language, resolved)) = self.resolve_location(location) else {
+            return (root, error);
+        };
+        *location = resolved;
+        (language, error)
+    }
 }
 
 pub trait LanguageLoader {
@@ -103,7 +211,9 @@ where
 
 #[cfg(test)]
 mod tests {
-    use super::read_query;
+    use tree_sitter::query::{ParseError, ParserErrorLocation};
+
+    use super::{read_query, read_query_with_source_map};
 
     #[test]
     fn read_query_breaks_inherits_cycle() {
@@ -135,4 +245,35 @@ mod tests {
             "missing a's own patterns: {result:?}"
         );
     }
+
+    #[test]
+    fn source_map_resolves_inherited_errors() {
+        let read = |lang: &str| match lang {
+            "a" => "(a_pattern) @a\n; inherits: b,c\n(a_pattern) @a2\n".to_string(),
+            "b" => "(b_pattern) @b\n(b_error) @b\n".to_string(),
+            "c" => "(c_pattern)".to_string(),
+            _ => String::new(),
+        };
+        let (query, source_map) = read_query_with_source_map("a", read);
+        assert_eq!(query, read_query("a", read));
+
+        let offset = query.find("b_error").unwrap();
+        assert_eq!(source_map.lookup(offset), Some(("b", &*read("b"), 16)));
+        let offset = query.find("c_pattern").unwrap();
+        assert_eq!(source_map.lookup(offset), Some(("c", &*read("c"), 1)));
+        let offset = query.find("@a2").unwrap();
+        assert_eq!(source_map.lookup(offset), Some(("a", &*read("a"), 43)));
+
+        let error = ParseError::InvalidNodeType {
+            node: "b_error".to_string(),
+            location: ParserErrorLocation::new(&query, query.find("b_error").unwrap(), 7),
+        };
+        let (language, error) = source_map.resolve_error(error);
+        assert_eq!(language, "b");
+        let location = error.location().unwrap();
+        assert_eq!(
+            (location.line, location.column, location.offset),
+            (1, 1, 16)
+        );
+    }
 }
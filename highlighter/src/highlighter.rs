@@ -20,6 +20,28 @@ use tree_sitter::{
 };
 use tree_sitter::{Pattern, QueryMatch};
 
+/// Checks a predicate of the highlights or locals query and returns whether it
+/// excludes the pattern from matching locals.
+pub(crate) fn is_non_local(predicate: UserPredicate) -> Result<bool, InvalidPredicateError> {
+    match predicate {
+        // Allow the `(#set! local.scope-inherits <bool>)` property to be parsed.
+        // This information is not used by this query though, it's used in the
+        // injection query instead.
+        UserPredicate::SetProperty {
+            key: "local.scope-inherits",
+            ..
+        } => Ok(false),
+        // TODO: `(#is(-not)? local)` applies to the entire pattern. Ideally you
+        // should be able to supply capture(s?) which are each checked.
+        UserPredicate::IsPropertySet {
+            negate: true,
+            key: "local",
+            val: None,
+        } => Ok(true),
+        _ => Err(InvalidPredicateError::unknown(predicate)),
+    }
+}
+
 /// Contains the data needed to highlight code written in a particular language.
 ///
 /// This struct is immutable and can be shared between threads.
@@ -50,24 +72,8 @@ impl HighlightQuery {
 
         let mut non_local_patterns = HashSet::new();
         let mut query = Query::new(grammar, &query_source, |pattern, predicate| {
-            match predicate {
-                // Allow the `(#set! local.scope-inherits <bool>)` property to be parsed.
-                // This information is not used by this query though, it's used in the
-                // injection query instead.
-                UserPredicate::SetProperty {
-                    key: "local.scope-inherits",
-                    ..
-                } => (),
-                // TODO: `(#is(-not)? local)` applies to the entire pattern. Ideally you
-                // should be able to supply capture(s?) which are each checked.
-                UserPredicate::IsPropertySet {
-                    negate: true,
-                    key: "local",
-                    val: None,
-                } => {
-                    non_local_patterns.insert(pattern);
-                }
-                _ => return Err(InvalidPredicateError::unknown(predicate)),
+            if is_non_local(predicate)? {
+                non_local_patterns.insert(pattern);
             }
             Ok(())
         })?;
@@ -158,6 +164,29 @@ impl fmt::Debug for Highlight {
     }
 }
 
+/// Highlights are serialized as their index rather than their internal
+/// representation.
+#[cfg(feature = "serde")]
+impl serde::Serialize for Highlight {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u32(self.get())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for Highlight {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let inner = u32::deserialize(deserializer)?;
+        if inner > Highlight::MAX {
+            return Err(serde::de::Error::invalid_value(
+                serde::de::Unexpected::Unsigned(inner.into()),
+                &"a highlight index less than u32::MAX",
+            ));
+        }
+        Ok(Highlight::new(inner))
+    }
+}
+
 #[derive(Debug)]
 struct HighlightedNode {
     end: u32,
@@ -1,3 +1,4 @@
+use std::borrow::Cow;
 use std::cmp::Reverse;
 use std::iter::{self, from_fn, Peekable};
 use std::mem::take;
@@ -16,7 +17,8 @@ use crate::parse::LayerUpdateFlags;
 use crate::{Injection, Language, Layer, LayerData, Range, Syntax, TREE_SITTER_MATCH_LIMIT};
 use tree_sitter::{
     query::{self, InvalidPredicateError, UserPredicate},
-    Capture, Grammar, InactiveQueryCursor, MatchedNodeIdx, Node, Pattern, Query, QueryMatch,
+    Capture, Grammar, InactiveQueryCursor, MatchedNode, MatchedNodeIdx, Node, Pattern, Query,
+    QueryMatch,
 };
 
 const SHEBANG: &str = r"#!\s*(?:\S*[/\\](?:env\s+(?:\-\S+\s+)*)?)?([^\s\.\d]+)";
@@ -27,6 +29,251 @@ pub struct InjectionProperties {
     include_children: IncludedChildren,
     language: Option<Box<str>>,
     combined: bool,
+    /// The `#offset!`, `#trim!`, `#gsub!` and `#strip!` directives of the
+    /// pattern in the order they appear in the query.
+    directives: Vec<(Capture, Directive)>,
+}
+
+/// A directive which transforms the range or the text of a capture.
+#[derive(Clone, Debug)]
+enum Directive {
+    Offset {
+        start_row: i32,
+        start_col: i32,
+        end_row: i32,
+        end_col: i32,
+    },
+    Trim {
+        start_linewise: bool,
+        start_charwise: bool,
+        end_linewise: bool,
+        end_charwise: bool,
+    },
+    /// Replaces all matches of the regex, used for both `#gsub!` and
+    /// `#strip!` (which replaces matches with nothing).
+    Replace { regex: Regex, replacement: Box<str> },
+}
+
+impl InjectionProperties {
+    /// Records a predicate of the injections query in the properties of its
+    /// pattern.
+    pub(crate) fn apply_predicate(
+        &mut self,
+        predicate: UserPredicate,
+    ) -> Result<(), InvalidPredicateError> {
+        match predicate {
+            UserPredicate::SetProperty {
+                key: "injection.include-unnamed-children",
+                val: None,
+            } => self.include_children = IncludedChildren::Unnamed,
+            UserPredicate::SetProperty {
+                key: "injection.include-children",
+                val: None,
+            } => self.include_children = IncludedChildren::All,
+            UserPredicate::SetProperty {
+                key: "injection.language",
+                val: Some(lang),
+            } => self.language = Some(lang.into()),
+            UserPredicate::SetProperty {
+                key: "injection.combined",
+                val: None,
+            } => self.combined = true,
+            UserPredicate::Offset {
+                capture,
+                start_row,
+                start_col,
+                end_row,
+                end_col,
+                ..
+            } => self.directives.push((
+                capture,
+                Directive::Offset {
+                    start_row,
+                    start_col,
+                    end_row,
+                    end_col,
+                },
+            )),
+            UserPredicate::Trim {
+                capture,
+                start_linewise,
+                start_charwise,
+                end_linewise,
+                end_charwise,
+                ..
+            } => self.directives.push((
+                capture,
+                Directive::Trim {
+                    start_linewise,
+                    start_charwise,
+                    end_linewise,
+                    end_charwise,
+                },
+            )),
+            UserPredicate::Gsub {
+                capture,
+                regex,
+                replacement,
+                ..
+            } => self.directives.push((
+                capture,
+                Directive::Replace {
+                    regex,
+                    replacement: replacement.into(),
+                },
+            )),
+            UserPredicate::Strip { capture, regex, .. } => self.directives.push((
+                capture,
+                Directive::Replace {
+                    regex,
+                    replacement: "".into(),
+                },
+            )),
+            predicate => {
+                return Err(InvalidPredicateError::unknown(predicate));
+            }
+        }
+        Ok(())
+    }
+
+    /// Applies the range directives (`#offset!` and `#trim!`) of the capture
+    /// to the range of the captured node.
+    ///
+    /// The resulting range never extends past the captured node. Its ends are
+    /// moved back to the start of the character they point into.
+    fn capture_range(&self, capture: Capture, node: &Node, source: RopeSlice) -> Range {
+        let node_range = node.byte_range();
+        let mut range = node_range.clone();
+        let char_boundary =
+            |byte: u32| source.char_to_byte(source.byte_to_char(byte as usize)) as u32;
+        for (_, directive) in self
+            .directives
+            .iter()
+            .filter(|(directive_capture, _)| *directive_capture == capture)
+        {
+            match *directive {
+                Directive::Offset {
+                    start_row,
+                    start_col,
+                    end_row,
+                    end_col,
+                } => {
+                    range = offset_byte(source, range.start, start_row, start_col)
+                        ..offset_byte(source, range.end, end_row, end_col);
+                }
+                Directive::Trim {
+                    start_linewise,
+                    start_charwise,
+                    end_linewise,
+                    end_charwise,
+                } => {
+                    let text: Cow<str> = source
+                        .byte_slice(range.start as usize..range.end as usize)
+                        .into();
+                    let trimmed_end = text.trim_end().len();
+                    let end = if end_charwise {
+                        trimmed_end
+                    } else if end_linewise {
+                        // the end of the last line which is not blank
+                        text[trimmed_end..]
+                            .find('\n')
+                            .map_or(text.len(), |i| trimmed_end + i)
+                    } else {
+                        text.len()
+                    };
+                    let trimmed_start = text.len() - text.trim_start().len();
+                    let start = if start_charwise {
+                        trimmed_start
+                    } else if start_linewise {
+                        // the start of the first line which is not blank
+                        text[..trimmed_start].rfind('\n').map_or(0, |i| i + 1)
+                    } else {
+                        0
+                    };
+                    range = range.start + start as u32..range.start + end.max(start) as u32;
+                }
+                Directive::Replace { .. } => (),
+            }
+            // the next directive slices the source with the range
+            let start = char_boundary(range.start);
+            range = start..char_boundary(range.end).max(start);
+        }
+        let start = range.start.clamp(node_range.start, node_range.end);
+        start..range.end.clamp(start, node_range.end)
+    }
+
+    /// Applies the text directives (`#gsub!` and `#strip!`) of the capture to
+    /// the captured text. Returns `None` if the capture has no text directives.
+    fn capture_text(&self, capture: Capture, text: RopeSlice) -> Option<String> {
+        let mut replaced = None;
+        for (_, directive) in self
+            .directives
+            .iter()
+            .filter(|(directive_capture, _)| *directive_capture == capture)
+        {
+            let Directive::Replace {
+                ref regex,
+                ref replacement,
+            } = *directive
+            else {
+                continue;
+            };
+            let text = replaced.take().unwrap_or_else(|| String::from(text));
+            replaced = Some(replace_all(regex, &text, replacement));
+        }
+        replaced
+    }
+}
+
+/// Moves `byte` by the given number of rows and columns (in bytes).
+fn offset_byte(source: RopeSlice, byte: u32, rows: i32, cols: i32) -> u32 {
+    let mut byte = byte as i64;
+    if rows != 0 {
+        let line = source.byte_to_line(byte as usize);
+        let col = byte - source.line_to_byte(line) as i64;
+        let line = (line as i64 + rows as i64).clamp(0, source.len_lines() as i64 - 1);
+        byte = source.line_to_byte(line as usize) as i64 + col;
+    }
+    (byte + cols as i64).clamp(0, source.len_bytes() as i64) as u32
+}
+
+/// Replaces all matches of `regex` in `text` like Lua's `string.gsub`.
+fn replace_all(regex: &Regex, text: &str, replacement: &str) -> String {
+    let mut res = String::with_capacity(text.len());
+    let mut last_end = 0;
+    for captures in regex.captures_iter(regex_cursor::Input::new(text)) {
+        let Some(whole_match) = captures.get_match() else {
+            continue;
+        };
+        res.push_str(&text[last_end..whole_match.start()]);
+        let mut chars = replacement.chars();
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                res.push(c);
+                continue;
+            }
+            match chars.next() {
+                Some(group @ '0'..='9') => {
+                    let group = group as usize - '0' as usize;
+                    // like in Lua `%1` refers to the whole match if the
+                    // pattern has no groups
+                    let span = if group == 1 && captures.group_len() == 1 {
+                        Some(whole_match.range())
+                    } else {
+                        captures.get_group(group).map(|span| span.range())
+                    };
+                    if let Some(span) = span {
+                        res.push_str(&text[span]);
+                    }
+                }
+                Some(c) => res.push(c),
+                None => (),
+            }
+        }
+        last_end = whole_match.end();
+    }
+    res.push_str(&text[last_end..]);
+    res
 }
 
 /// An indicator in the document or query source file which used by the loader to know which
@@ -58,6 +305,8 @@ pub struct InjectionQueryMatch<'tree> {
     language: Language,
     scope: Option<InjectionScope>,
     node: Node<'tree>,
+    /// The range of `node` after applying range directives like `#offset!`.
+    range: Range,
     last_match: bool,
     pattern: Pattern,
 }
@@ -81,6 +330,18 @@ enum IncludedChildren {
     Unnamed,
 }
 
+/// Checks a predicate of the locals query and returns whether the scope of the
+/// pattern inherits definitions from its parent scope.
+pub(crate) fn scope_inherits(predicate: UserPredicate) -> Result<bool, InvalidPredicateError> {
+    match predicate {
+        UserPredicate::SetProperty {
+            key: "local.scope-inherits",
+            val,
+        } => Ok(val.is_none_or(|val| val == "true")),
+        predicate => Err(InvalidPredicateError::unknown(predicate)),
+    }
+}
+
 #[derive(Debug)]
 pub struct InjectionsQuery {
     injection_query: Query,
@@ -106,53 +367,14 @@ impl InjectionsQuery {
         let mut injection_properties: HashMap<Pattern, InjectionProperties> = HashMap::new();
         let mut not_scope_inherits = HashSet::new();
         let injection_query = Query::new(grammar, injection_query_text, |pattern, predicate| {
-            match predicate {
-                // injections
-                UserPredicate::SetProperty {
-                    key: "injection.include-unnamed-children",
-                    val: None,
-                } => {
-                    injection_properties
-                        .entry(pattern)
-                        .or_default()
-                        .include_children = IncludedChildren::Unnamed
-                }
-                UserPredicate::SetProperty {
-                    key: "injection.include-children",
-                    val: None,
-                } => {
-                    injection_properties
-                        .entry(pattern)
-                        .or_default()
-                        .include_children = IncludedChildren::All
-                }
-                UserPredicate::SetProperty {
-                    key: "injection.language",
-                    val: Some(lang),
-                } => injection_properties.entry(pattern).or_default().language = Some(lang.into()),
-                UserPredicate::SetProperty {
-                    key: "injection.combined",
-                    val: None,
-                } => injection_properties.entry(pattern).or_default().combined = true,
-                predicate => {
-                    return Err(InvalidPredicateError::unknown(predicate));
-                }
-            }
-            Ok(())
+            injection_properties
+                .entry(pattern)
+                .or_default()
+                .apply_predicate(predicate)
         })?;
         let mut local_query = Query::new(grammar, local_query_text, |pattern, predicate| {
-            match predicate {
-                UserPredicate::SetProperty {
-                    key: "local.scope-inherits",
-                    val,
-                } => {
-                    if val.is_some_and(|val| val != "true") {
-                        not_scope_inherits.insert(pattern);
-                    }
-                }
-                predicate => {
-                    return Err(InvalidPredicateError::unknown(predicate));
-                }
+            if !scope_inherits(predicate)? {
+                not_scope_inherits.insert(pattern);
             }
             Ok(())
         })?;
@@ -196,24 +418,35 @@ impl InjectionsQuery {
         loader: impl LanguageLoader,
     ) -> Option<InjectionQueryMatch<'tree>> {
         let properties = self.injection_properties.get(&query_match.pattern());
+        let capture_range = |matched_node: &MatchedNode| match properties {
+            Some(properties) => {
+                properties.capture_range(matched_node.capture, &matched_node.node, source)
+            }
+            None => matched_node.node.byte_range(),
+        };
 
         let mut marker = None;
+        // the capture of a `Match` or `Filename` marker, which may have text directives
+        let mut marker_capture = None;
         let mut last_content_node = 0;
         let mut content_nodes = 0;
         for (i, matched_node) in query_match.matched_nodes().enumerate() {
             let capture = Some(matched_node.capture);
             if capture == self.injection_language_capture {
-                let range = matched_node.node.byte_range();
+                let range = capture_range(matched_node);
                 marker = Some(InjectionLanguageMarker::Match(
                     source.byte_slice(range.start as usize..range.end as usize),
                 ));
+                marker_capture = capture;
             } else if capture == self.injection_filename_capture {
-                let range = matched_node.node.byte_range();
+                let range = capture_range(matched_node);
                 marker = Some(InjectionLanguageMarker::Filename(
                     source.byte_slice(range.start as usize..range.end as usize),
                 ));
+                marker_capture = capture;
             } else if capture == self.injection_shebang_capture {
-                let range = matched_node.node.byte_range();
+                let range = capture_range(matched_node);
+                marker_capture = None;
                 let node_slice = source.byte_slice(range.start as usize..range.end as usize);
 
                 // some languages allow space and newlines before the actual string content
@@ -237,6 +470,22 @@ impl InjectionsQuery {
                 last_content_node = i as u32;
             }
         }
+        let replaced_text = properties
+            .zip(marker_capture)
+            .and_then(|(properties, capture)| match marker {
+                Some(
+                    InjectionLanguageMarker::Match(text) | InjectionLanguageMarker::Filename(text),
+                ) => properties.capture_text(capture, text),
+                _ => None,
+            });
+        if let Some(text) = &replaced_text {
+            marker = match marker {
+                Some(InjectionLanguageMarker::Filename(_)) => {
+                    Some(InjectionLanguageMarker::Filename(text.as_str().into()))
+                }
+                _ => Some(InjectionLanguageMarker::Match(text.as_str().into())),
+            };
+        }
         let marker = marker.or(properties
             .and_then(|p| p.language.as_deref())
             .map(InjectionLanguageMarker::Name))?;
@@ -260,6 +509,7 @@ impl InjectionsQuery {
             scope,
             include_children: properties.map(|p| p.include_children).unwrap_or_default(),
             node: query_match.matched_node(node_idx).node.clone(),
+            range: capture_range(query_match.matched_node(node_idx)),
             last_match: last_content_node == node_idx,
             pattern: query_match.pattern(),
         })
@@ -297,11 +547,10 @@ impl InjectionsQuery {
                 query_match.remove();
                 continue;
             };
-            let range = query_match.matched_node(node_idx).node.byte_range();
             if mat.last_match {
                 query_match.remove();
             }
-            if range.is_empty() {
+            if mat.range.is_empty() {
                 continue;
             }
             break Some(mat);
@@ -358,10 +607,16 @@ impl Syntax {
         edits: &[tree_sitter::InputEdit],
         source: RopeSlice<'_>,
         loader: &impl LanguageLoader,
+        changed_ranges: Option<&mut Vec<Range>>,
         mut parse_layer: impl FnMut(Layer),
     ) {
         self.map_injections(layer, None, edits);
         let layer_data = &mut self.layer_mut(layer);
+        // Remember the (mapped) injections before re-running the query so that injections which
+        // are removed can be reported as changed.
+        let previous_injections = changed_ranges
+            .is_some()
+            .then(|| layer_data.injections.clone());
         let Some(LanguageConfig {
             injection_query: ref injections_query,
             ..
@@ -445,7 +700,8 @@ impl Syntax {
             }
 
             let old_len = injections.len();
-            for range in intersect_ranges(mat.include_children, mat.node, &parent_ranges) {
+            for range in intersect_ranges(mat.include_children, mat.node, mat.range, &parent_ranges)
+            {
                 layer_data.ranges.push(tree_sitter::Range::new(
                     tree_sitter::Point::ZERO,
                     tree_sitter::Point::ZERO,
@@ -472,6 +728,23 @@ impl Syntax {
             self.layer_mut(old_injection.layer).flags.modified = true;
         }
 
+        if let (Some(changed_ranges), Some(previous_injections)) =
+            (changed_ranges, previous_injections)
+        {
+            let current: HashSet<_> = injections
+                .iter()
+                .map(|injection| (injection.layer, injection.range.clone()))
+                .collect();
+            changed_ranges.extend(
+                previous_injections
+                    .into_iter()
+                    .filter(|injection| {
+                        !current.contains(&(injection.layer, injection.range.clone()))
+                    })
+                    .map(|injection| injection.range),
+            );
+        }
+
         let layer_data = &mut self.layer_mut(layer);
         layer_data.ranges = parent_ranges;
         layer_data.parse_tree = Some(parse_tree);
@@ -635,9 +908,9 @@ impl Syntax {
 fn intersect_ranges<'tree, 'p>(
     include_children: IncludedChildren,
     node: Node<'tree>,
+    range: Range,
     parent_ranges: &'p [tree_sitter::Range],
 ) -> impl Iterator<Item = Range> + use<'tree, 'p> {
-    let range = node.byte_range();
     let i = parent_ranges.partition_point(|parent_range| parent_range.end_byte <= range.start);
     let parent_ranges = parent_ranges[i..]
         .iter()
@@ -795,6 +1068,18 @@ fn ranges_intersect(a: &Range, b: &Range) -> bool {
 mod tests {
     use super::*;
 
+    #[test]
+    fn replace_all_like_gsub() {
+        let regex = Regex::new("([a-z]+)-([a-z]+)").unwrap();
+        assert_eq!(replace_all(&regex, "a-b, cd-ef", "%2+%1"), "b+a, ef+cd");
+        assert_eq!(replace_all(&regex, "a-b", "[%0] 100%%"), "[a-b] 100%");
+        let regex = Regex::new(r"^\s*//").unwrap();
+        assert_eq!(replace_all(&regex, "  // x", ""), " x");
+        assert_eq!(replace_all(&regex, "x // y", "%1"), "x // y");
+        let regex = Regex::new("o").unwrap();
+        assert_eq!(replace_all(&regex, "foo", "<%1>"), "f<o><o>");
+    }
+
     #[test]
     fn exclude_ranges_no_exclusions() {
         let result: Vec<Range> =
@@ -8,32 +8,41 @@ use std::hash::{Hash, Hasher};
 use std::time::Duration;
 use tree_sitter::{IncompatibleGrammarError, Node, Tree};
 
-pub use crate::config::{read_query, LanguageConfig, LanguageLoader};
+pub use crate::config::{
+    read_query, read_query_with_source_map, LanguageConfig, LanguageLoader, QuerySourceMap,
+};
 pub use crate::injections_query::{InjectionLanguageMarker, InjectionsQuery};
-use crate::parse::LayerUpdateFlags;
+pub use crate::parse::UpdateStatus;
+use crate::parse::{LayerUpdateFlags, PendingUpdate};
+pub use crate::pretty_print::pretty_print_tree;
 pub use crate::query_iter::{CapturedMatch, QueryMatchIter, QueryMatchIterEvent};
+pub use crate::snapshot::{LayerSnapshot, SyntaxSnapshot};
+pub use crate::syntax_error::{SyntaxError, SyntaxErrorKind};
 pub use crate::tree_cursor::TreeCursor;
 pub use tree_sitter;
-// pub use pretty_print::pretty_print_tree;
 // pub use tree_cursor::TreeCursor;
 
 mod config;
+#[cfg(feature = "fixtures")]
+pub mod fixtures;
 pub mod highlighter;
 mod injections_query;
+pub mod locals;
 mod parse;
+mod pretty_print;
+pub mod query_iter;
+pub mod query_lint;
+mod snapshot;
+mod syntax_error;
 #[cfg(all(test, feature = "fixtures"))]
 mod tests;
-// mod pretty_print;
-#[cfg(feature = "fixtures")]
-pub mod fixtures;
-pub mod locals;
-pub mod query_iter;
 pub mod text_object;
 mod tree_cursor;
 
 /// A layer represents a single a single syntax tree that represents (part of)
 /// a file parsed with a tree-sitter grammar. See [`Syntax`].
 #[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Layer(u32);
 
 impl Layer {
@@ -43,6 +52,7 @@ impl Layer {
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Language(pub u32);
 
 impl Language {
@@ -86,6 +96,8 @@ impl Language {
 pub struct Syntax {
     layers: Slab<LayerData>,
     root: Layer,
+    /// An update suspended by [`Syntax::update_resumable`].
+    pending: Option<PendingUpdate>,
 }
 
 impl Syntax {
@@ -114,6 +126,7 @@ impl Syntax {
         let mut syntax = Self {
             root: Layer(root as u32),
             layers,
+            pending: None,
         };
 
         syntax.update(source, timeout, &[], loader).map(|_| syntax)
@@ -204,7 +217,8 @@ impl Syntax {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
 pub struct Injection {
     pub range: Range,
     pub layer: Layer,
@@ -1,11 +1,12 @@
 use std::mem::take;
-use std::time::Duration;
+use std::ops::ControlFlow;
+use std::time::{Duration, Instant};
 
 use ropey::RopeSlice;
-use tree_sitter::Parser;
+use tree_sitter::{ParseOptions, ParseOutcome, ParseState, Parser, ParserSuspension, Tree};
 
 use crate::config::LanguageLoader;
-use crate::{Error, LayerData, Syntax};
+use crate::{Error, Layer, LayerData, Range, Syntax};
 
 impl Syntax {
     pub fn update(
@@ -15,6 +16,79 @@ impl Syntax {
         edits: &[tree_sitter::InputEdit],
         loader: &impl LanguageLoader,
     ) -> Result<(), Error> {
+        self.update_impl(source, timeout, edits, loader, None, false)
+            .map(|_| ())
+    }
+
+    /// Updates the syntax tree like [`Syntax::update`] and returns the byte ranges whose syntax
+    /// changed.
+    ///
+    /// The ranges are merged across all injection layers: they include ranges where the structure
+    /// of a layer's syntax tree changed as well as the ranges of layers which were created or
+    /// destroyed by the update. The returned ranges are sorted and do not overlap.
+    ///
+    /// Note that an edit which does not change the structure of the tree (for example renaming
+    /// an identifier) does not necessarily produce a changed range. Callers should consider the
+    /// edited ranges themselves as changed as well.
+    ///
+    /// If this finishes an update suspended by [`Syntax::update_resumable`] then the whole
+    /// document is reported as changed.
+    pub fn update_with_changed_ranges(
+        &mut self,
+        source: RopeSlice,
+        timeout: Duration,
+        edits: &[tree_sitter::InputEdit],
+        loader: &impl LanguageLoader,
+    ) -> Result<Vec<Range>, Error> {
+        let mut changed_ranges = Vec::new();
+        self.update_impl(
+            source,
+            timeout,
+            edits,
+            loader,
+            Some(&mut changed_ranges),
+            false,
+        )?;
+        Ok(merge_ranges(changed_ranges))
+    }
+
+    /// Updates the syntax tree like [`Syntax::update`] but suspends the update instead of
+    /// failing with [`Error::Timeout`] when parsing a layer takes longer than `timeout`.
+    ///
+    /// This allows parsing huge documents in time slices, for example across multiple frames.
+    /// A suspended update is continued by calling any of the update functions again with the
+    /// same `source` and no edits. [`Syntax::update`] and [`Syntax::update_with_changed_ranges`]
+    /// fail with [`Error::Timeout`] if the continued update doesn't finish within `timeout`, in
+    /// which case the update stays pending. While an update is suspended the layers are only
+    /// partially updated: layers which were not reached yet still have the trees and injections
+    /// of the previous update.
+    ///
+    /// Updating with new edits while an update is suspended abandons the suspended update. In
+    /// that case all injection layers are discarded and re-created from scratch.
+    pub fn update_resumable(
+        &mut self,
+        source: RopeSlice,
+        timeout: Duration,
+        edits: &[tree_sitter::InputEdit],
+        loader: &impl LanguageLoader,
+    ) -> Result<UpdateStatus, Error> {
+        self.update_impl(source, timeout, edits, loader, None, true)
+    }
+
+    /// Whether an update was suspended by [`Syntax::update_resumable`] and has not finished yet.
+    pub fn has_pending_update(&self) -> bool {
+        self.pending.is_some()
+    }
+
+    fn update_impl(
+        &mut self,
+        source: RopeSlice,
+        timeout: Duration,
+        edits: &[tree_sitter::InputEdit],
+        loader: &impl LanguageLoader,
+        mut changed_ranges: Option<&mut Vec<Range>>,
+        resumable: bool,
+    ) -> Result<UpdateStatus, Error> {
         // size limit of 512MiB, TS just cannot handle files this big (too
         // slow). Furthermore, TS uses 32 (signed) bit indices so this limit
         // must never be raised above 2GiB
@@ -23,39 +97,99 @@ impl Syntax {
         }
 
         let mut queue = Vec::with_capacity(32);
-        let root_flags = &mut self.layer_mut(self.root).flags;
-        // The root layer is always considered.
-        root_flags.touched = true;
-        // If there was an edit then the root layer must've been modified.
-        root_flags.modified = true;
-        queue.push(self.root);
+        // The suspended parse of the first layer in the queue, if a pending update is continued.
+        let mut resume = None;
+        let pending_edits;
+        let mut edits = edits;
+        match self.pending.take() {
+            Some(pending) if edits.is_empty() => {
+                if let Some(changed_ranges) = changed_ranges.as_deref_mut() {
+                    // The changes made before the update was suspended are not tracked.
+                    changed_ranges.push(0..source.len_bytes() as u32);
+                }
+                // The remaining layers still need the edits of the suspended update.
+                pending_edits = pending.edits;
+                edits = &pending_edits;
+                queue = pending.queue;
+                queue.push(pending.layer);
+                resume = Some(pending.suspension);
+            }
+            pending => {
+                if pending.is_some() {
+                    self.discard_injection_layers();
+                }
+                let root_flags = &mut self.layer_mut(self.root).flags;
+                // The root layer is always considered.
+                root_flags.touched = true;
+                // If there was an edit then the root layer must've been modified.
+                root_flags.modified = true;
+                queue.push(self.root);
+            }
+        }
 
         let mut parser = Parser::new();
 
         while let Some(layer) = queue.pop() {
             let layer_data = self.layer_mut(layer);
-            if layer_data.ranges.is_empty() {
-                // Skip re-parsing and querying layers without any ranges.
-                continue;
-            }
-            if let Some(tree) = &mut layer_data.parse_tree {
-                if layer_data.flags.moved || layer_data.flags.modified {
-                    for edit in edits.iter().rev() {
-                        // Apply the edits in reverse.
-                        // If we applied them in order then edit 1 would disrupt the positioning
-                        // of edit 2.
-                        tree.edit(edit);
+            // `Some` if the layer must be (re-)parsed, with the suspended parse to continue.
+            let parse = match resume.take() {
+                // The edits were already applied before the layer's parse was suspended.
+                Some(suspension) => Some(suspension),
+                None => {
+                    if layer_data.ranges.is_empty() {
+                        // Skip re-parsing and querying layers without any ranges.
+                        continue;
+                    }
+                    if let Some(tree) = &mut layer_data.parse_tree {
+                        if layer_data.flags.moved || layer_data.flags.modified {
+                            for edit in edits.iter().rev() {
+                                // Apply the edits in reverse.
+                                // If we applied them in order then edit 1 would disrupt the
+                                // positioning of edit 2.
+                                tree.edit(edit);
+                            }
+                        }
+                        // Re-parse the tree if it was modified.
+                        layer_data.flags.modified.then_some(None)
+                    } else {
+                        // always parse if this layer has never been parsed before
+                        Some(None)
                     }
                 }
-                if layer_data.flags.modified {
-                    // Re-parse the tree.
-                    layer_data.parse(&mut parser, source, timeout, loader)?;
+            };
+            if let Some(suspension) = parse {
+                let suspension = layer_data.parse(
+                    &mut parser,
+                    source,
+                    timeout,
+                    loader,
+                    changed_ranges.as_deref_mut(),
+                    suspension,
+                    resumable,
+                )?;
+                if let Some(suspension) = suspension {
+                    self.pending = Some(PendingUpdate {
+                        edits: edits.to_vec(),
+                        layer,
+                        queue,
+                        suspension: Some(suspension),
+                    });
+                    // Only `update_resumable` reports a suspended update. The other update
+                    // functions continue a pending update and time out if it doesn't finish.
+                    if !resumable {
+                        return Err(Error::Timeout);
+                    }
+                    return Ok(UpdateStatus::Suspended);
                 }
-            } else {
-                // always parse if this layer has never been parsed before
-                layer_data.parse(&mut parser, source, timeout, loader)?;
             }
-            self.run_injection_query(layer, edits, source, loader, |layer| queue.push(layer));
+            self.run_injection_query(
+                layer,
+                edits,
+                source,
+                loader,
+                changed_ranges.as_deref_mut(),
+                |layer| queue.push(layer),
+            );
             self.run_local_query(layer, source, loader);
         }
 
@@ -64,7 +198,7 @@ impl Syntax {
         }
 
         self.prune_dead_layers();
-        Ok(())
+        Ok(UpdateStatus::Complete)
     }
 
     /// Reset all `LayerUpdateFlags` and remove all untouched layers
@@ -72,54 +206,165 @@ impl Syntax {
         self.layers
             .retain(|_, layer| take(&mut layer.flags).touched);
     }
+
+    /// Remove all injection layers after a suspended update was abandoned.
+    ///
+    /// The abandoned update may have left the injection layers partially updated. The root
+    /// layer's tree is always consistent with the edits of the abandoned update: it is either
+    /// re-parsed or edited (but not re-parsed yet) when the update is suspended.
+    fn discard_injection_layers(&mut self) {
+        let root = self.root.idx();
+        self.layers.retain(|idx, _| idx == root);
+        let root = self.layer_mut(self.root);
+        root.injections.clear();
+        root.flags = LayerUpdateFlags::default();
+    }
 }
 
 impl LayerData {
+    /// Parse the layer or continue its suspended parse.
+    ///
+    /// Returns the suspended parse if `resumable` is set and parsing did not finish within
+    /// `timeout`.
+    #[allow(clippy::too_many_arguments)]
     fn parse(
         &mut self,
         parser: &mut Parser,
         source: RopeSlice,
         timeout: Duration,
         loader: &impl LanguageLoader,
-    ) -> Result<(), Error> {
-        let Some(config) = loader.get_config(self.language) else {
-            return Ok(());
+        changed_ranges: Option<&mut Vec<Range>>,
+        suspension: Option<ParserSuspension>,
+        resumable: bool,
+    ) -> Result<Option<ParserSuspension>, Error> {
+        let deadline = Instant::now() + timeout;
+        let mut check = |_: &ParseState| {
+            if Instant::now() >= deadline {
+                ControlFlow::Break(())
+            } else {
+                ControlFlow::Continue(())
+            }
         };
-        if let Err(err) = parser.set_grammar(config.grammar) {
-            return Err(Error::IncompatibleGrammar(self.language, err));
-        }
-        parser
-            .set_included_ranges(&self.ranges)
-            .map_err(|_| Error::InvalidRanges)?;
-
-        // HACK:
-        // This is a workaround for a bug within the lexer (in the C library) or maybe within
-        // tree-sitter-markdown which needs more debugging. When adding a new range to a combined
-        // injection and passing the old tree, if the old tree doesn't already cover a wider range
-        // than the newly added range, some assumptions are violated in the lexer and it tries to
-        // access some invalid memory, resulting in a segfault. This workaround avoids that
-        // situation by avoiding passing the old tree when the old tree's range doesn't cover the
-        // total range of `self.ranges`.
-        //
-        // See <https://github.com/helix-editor/helix/pull/12972#issuecomment-2725410409>.
-        let tree = self.parse_tree.as_ref().filter(|tree| {
-            let included_ranges_range = self.ranges.first().map(|r| r.start_byte).unwrap_or(0)
-                ..self.ranges.last().map(|r| r.end_byte).unwrap_or(u32::MAX);
-            // Allow re-parsing the root layer even though the range is larger. The root always
-            // covers `0..u32::MAX`:
-            if included_ranges_range == (0..u32::MAX) {
-                return true;
+        let outcome = if let Some(suspension) = suspension {
+            suspension.resume(source, ParseOptions::new(&mut check))
+        } else {
+            let Some(config) = loader.get_config(self.language) else {
+                return Ok(None);
+            };
+            if let Err(err) = parser.set_grammar(config.grammar) {
+                return Err(Error::IncompatibleGrammar(self.language, err));
+            }
+            parser
+                .set_included_ranges(&self.ranges)
+                .map_err(|_| Error::InvalidRanges)?;
+
+            // HACK:
+            // This is a workaround for a bug within the lexer (in the C library) or maybe within
+            // tree-sitter-markdown which needs more debugging. When adding a new range to a
+            // combined injection and passing the old tree, if the old tree doesn't already cover
+            // a wider range than the newly added range, some assumptions are violated in the
+            // lexer and it tries to access some invalid memory, resulting in a segfault. This
+            // workaround avoids that situation by avoiding passing the old tree when the old
+            // tree's range doesn't cover the total range of `self.ranges`.
+            //
+            // See <https://github.com/helix-editor/helix/pull/12972#issuecomment-2725410409>.
+            let tree = self.parse_tree.as_ref().filter(|tree| {
+                let included_ranges_range = self.ranges.first().map(|r| r.start_byte).unwrap_or(0)
+                    ..self.ranges.last().map(|r| r.end_byte).unwrap_or(u32::MAX);
+                // Allow re-parsing the root layer even though the range is larger. The root
+                // always covers `0..u32::MAX`:
+                if included_ranges_range == (0..u32::MAX) {
+                    return true;
+                }
+                let tree_range = tree.root_node().byte_range();
+                tree_range.start <= included_ranges_range.start
+                    && tree_range.end >= included_ranges_range.end
+            });
+
+            if resumable {
+                parser.parse_resumable(source, tree, ParseOptions::new(&mut check))
+            } else {
+                parser
+                    .parse_with_options(source, tree, ParseOptions::new(&mut check))
+                    .map_or(ParseOutcome::Failed, ParseOutcome::Complete)
+            }
+        };
+        let tree = match outcome {
+            ParseOutcome::Complete(tree) => tree,
+            ParseOutcome::Suspended(suspension) => return Ok(Some(suspension)),
+            ParseOutcome::Failed => return Err(Error::Timeout),
+        };
+        if let Some(changed_ranges) = changed_ranges {
+            match &self.parse_tree {
+                Some(old_tree) => changed_ranges.extend(
+                    Tree::changed_ranges(old_tree, &tree)
+                        .map(|range| range.start_byte..range.end_byte),
+                ),
+                // A layer which is parsed for the first time changes the syntax of all of its
+                // included ranges.
+                None => {
+                    let len = source.len_bytes() as u32;
+                    changed_ranges.extend(
+                        self.ranges
+                            .iter()
+                            .map(|range| range.start_byte.min(len)..range.end_byte.min(len)),
+                    )
+                }
             }
-            let tree_range = tree.root_node().byte_range();
-            tree_range.start <= included_ranges_range.start
-                && tree_range.end >= included_ranges_range.end
-        });
-
-        let tree = parser
-            .parse_with_timeout(source, tree, timeout)
-            .ok_or(Error::Timeout)?;
+        }
         self.parse_tree = Some(tree);
-        Ok(())
+        Ok(None)
+    }
+}
+
+/// Sorts the given ranges and merges any overlapping or adjacent ranges.
+fn merge_ranges(mut ranges: Vec<Range>) -> Vec<Range> {
+    ranges.retain(|range| !range.is_empty());
+    ranges.sort_unstable_by_key(|range| range.start);
+    let mut merged: Vec<Range> = Vec::with_capacity(ranges.len());
+    for range in ranges {
+        match merged.last_mut() {
+            Some(last) if range.start <= last.end => last.end = last.end.max(range.end),
+            _ => merged.push(range),
+        }
+    }
+    merged
+}
+
+/// The result of [`Syntax::update_resumable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UpdateStatus {
+    /// All layers are up to date.
+    Complete,
+    /// Parsing a layer took longer than the timeout. The update is continued by the next update
+    /// without edits.
+    Suspended,
+}
+
+/// The state of an update suspended by [`Syntax::update_resumable`].
+#[derive(Debug)]
+pub(crate) struct PendingUpdate {
+    /// The edits of the suspended update.
+    edits: Vec<tree_sitter::InputEdit>,
+    /// The layer whose parse was suspended.
+    layer: Layer,
+    /// The remaining layers of the update.
+    queue: Vec<Layer>,
+    /// The suspended parse of `layer`. `None` if the parse must be restarted because the
+    /// `Syntax` was cloned.
+    suspension: Option<ParserSuspension>,
+}
+
+impl Clone for PendingUpdate {
+    fn clone(&self) -> Self {
+        // The state of a suspended parse can't be copied. The layer's tree was already edited so
+        // it's enough to restart the parse.
+        PendingUpdate {
+            edits: self.edits.clone(),
+            layer: self.layer,
+            queue: self.queue.clone(),
+            suspension: None,
+        }
     }
 }
 
@@ -130,3 +375,16 @@ pub(crate) struct LayerUpdateFlags {
     pub moved: bool,
     pub touched: bool,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::merge_ranges;
+
+    #[test]
+    fn merge_ranges_coalesces_overlapping_and_adjacent() {
+        assert_eq!(
+            merge_ranges(vec![10..20, 0..5, 15..30, 5..7, 40..40, 50..60]),
+            vec![0..7, 10..30, 50..60]
+        );
+    }
+}
@@ -1,8 +1,11 @@
 use std::fmt;
 
-use tree_sitter::{SyntaxTreeNode, TreeCursor};
+use tree_sitter::{Node, TreeCursor};
 
-pub fn pretty_print_tree<W: fmt::Write>(fmt: &mut W, node: SyntaxTreeNode) -> fmt::Result {
+/// Write the syntax tree of `node` as an indented S-expression with one named node per line.
+///
+/// See [`Node::to_sexp`] for a single line S-expression.
+pub fn pretty_print_tree<W: fmt::Write>(fmt: &mut W, node: Node) -> fmt::Result {
     if node.child_count() == 0 {
         if node_is_visible(&node) {
             write!(fmt, "({})", node.kind())
@@ -60,6 +63,6 @@ fn pretty_print_tree_impl<W: fmt::Write>(
     Ok(())
 }
 
-fn node_is_visible(node: &SyntaxTreeNode) -> bool {
-    node.is_missing() || (node.is_named() && node.language().node_kind_is_visible(node.kind_id()))
+fn node_is_visible(node: &Node) -> bool {
+    node.is_missing() || node.is_named()
 }
@@ -0,0 +1,609 @@
+//! Formatting and linting of the highlight, injection and locals queries.
+
+use std::collections::HashMap;
+use std::fmt::{self, Write};
+
+use tree_sitter::query::ast::{self, Child, Expr, ExprKind, NodeName, PredicateArg};
+use tree_sitter::query::{
+    InvalidPredicateError, ParseError, ParserErrorLocation, Quantifier, UserPredicate,
+};
+use tree_sitter::{Grammar, Query};
+
+use crate::highlighter::{self, Highlight};
+use crate::injections_query::{self, InjectionProperties};
+
+/// The maximum line width of [`format_query`]. Patterns which do not fit into
+/// a single line are broken into multiple lines.
+const MAX_WIDTH: usize = 100;
+
+/// The queries used by [`LanguageConfig`](crate::LanguageConfig).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum QueryKind {
+    Highlights,
+    Injections,
+    Locals,
+}
+
+impl QueryKind {
+    pub const ALL: [QueryKind; 3] = [
+        QueryKind::Highlights,
+        QueryKind::Injections,
+        QueryKind::Locals,
+    ];
+
+    /// The name of the file the query is read from, for example
+    /// `highlights.scm`.
+    pub fn file_name(self) -> &'static str {
+        match self {
+            QueryKind::Highlights => "highlights.scm",
+            QueryKind::Injections => "injections.scm",
+            QueryKind::Locals => "locals.scm",
+        }
+    }
+
+    /// Accepts the same predicates as [`HighlightQuery`] and
+    /// [`InjectionsQuery`].
+    ///
+    /// [`HighlightQuery`]: crate::highlighter::HighlightQuery
+    /// [`InjectionsQuery`]: crate::InjectionsQuery
+    fn check_predicate(self, predicate: UserPredicate) -> Result<(), InvalidPredicateError> {
+        match self {
+            QueryKind::Highlights => highlighter::is_non_local(predicate).map(drop),
+            QueryKind::Injections => InjectionProperties::default().apply_predicate(predicate),
+            QueryKind::Locals => injections_query::scope_inherits(predicate).map(drop),
+        }
+    }
+
+    fn is_known_capture(
+        self,
+        name: &str,
+        highlight: &mut impl FnMut(&str) -> Option<Highlight>,
+    ) -> bool {
+        match self {
+            QueryKind::Highlights => highlight(name).is_some(),
+            QueryKind::Injections => matches!(
+                name,
+                "injection.content"
+                    | "injection.language"
+                    | "injection.filename"
+                    | "injection.shebang"
+            ),
+            QueryKind::Locals => match name.strip_prefix("local.definition.") {
+                Some(suffix) => highlight(suffix).is_some(),
+                None => matches!(name, "local.scope" | "local.reference"),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LintKind {
+    /// The query does not compile, for example because of a syntax error or
+    /// an invalid field.
+    InvalidQuery,
+    /// A node kind which the grammar never produces.
+    UnknownNodeKind,
+    /// An unknown predicate, a `#set!` key which is not understood by the
+    /// query or a predicate with invalid arguments.
+    InvalidPredicate,
+    /// A capture name which is not recognized: highlights which are not
+    /// configured with [`LanguageConfig::configure`](crate::LanguageConfig::configure)
+    /// or captures which have no meaning in injection or locals queries.
+    UnknownCapture,
+    /// A highlights pattern which never takes effect because an identical
+    /// pattern follows it. When several patterns highlight the same node the
+    /// last one takes precedence.
+    ShadowedPattern,
+}
+
+/// A problem found by [`lint_query`].
+#[derive(Debug, PartialEq, Eq)]
+pub struct Lint {
+    pub kind: LintKind,
+    pub message: String,
+    pub location: ParserErrorLocation,
+}
+
+impl Lint {
+    /// Whether the query can not be compiled because of this problem.
+    pub fn is_error(&self) -> bool {
+        matches!(
+            self.kind,
+            LintKind::InvalidQuery | LintKind::UnknownNodeKind | LintKind::InvalidPredicate
+        )
+    }
+}
+
+impl From<ParseError> for Lint {
+    fn from(err: ParseError) -> Lint {
+        let message = match &err {
+            ParseError::InvalidNodeType { node, .. } => format!("invalid node type {node:?}"),
+            ParseError::InvalidPredicate { message, .. } => message.clone(),
+            // the first line of the message, without the location
+            err => err
+                .to_string()
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .to_owned(),
+        };
+        let kind = match err {
+            ParseError::InvalidNodeType { .. } => LintKind::UnknownNodeKind,
+            ParseError::InvalidPredicate { .. } => LintKind::InvalidPredicate,
+            _ => LintKind::InvalidQuery,
+        };
+        let location = match err {
+            ParseError::UnexpectedEof => ParserErrorLocation::new("", 0, 0),
+            ParseError::SyntaxError(location)
+            | ParseError::InvalidNodeType { location, .. }
+            | ParseError::InvalidFieldName { location, .. }
+            | ParseError::InvalidCaptureName { location, .. }
+            | ParseError::InvalidPredicate { location, .. }
+            | ParseError::ImpossiblePattern(location) => location,
+        };
+        Lint {
+            kind,
+            message,
+            location,
+        }
+    }
+}
+
+impl fmt::Display for Lint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}\n{}", self.message, self.location)
+    }
+}
+
+/// Checks a query for problems, see [`LintKind`].
+///
+/// `highlight` decides which highlights are recognized, like the closure
+/// passed to [`LanguageConfig::configure`](crate::LanguageConfig::configure).
+/// Captures starting with an `_` are private to the query and never reported.
+///
+/// Lints are ordered by their position in the query.
+pub fn lint_query(
+    grammar: Grammar,
+    kind: QueryKind,
+    source: &str,
+    mut highlight: impl FnMut(&str) -> Option<Highlight>,
+) -> Vec<Lint> {
+    let mut lints: Vec<Lint> = Query::diagnose(grammar, source, |_, predicate| {
+        kind.check_predicate(predicate)
+    })
+    .into_iter()
+    .map(Lint::from)
+    .collect();
+    let Ok(file) = ast::parse(source) else {
+        return lints;
+    };
+    let lint = |kind, message, span: &ast::Span| Lint {
+        kind,
+        message,
+        location: ParserErrorLocation::new(
+            source,
+            span.start as usize,
+            source[span.start as usize..span.end as usize]
+                .chars()
+                .count(),
+        ),
+    };
+
+    for pattern in &file.patterns {
+        pattern.walk(&mut |expr| {
+            for capture in &expr.captures {
+                if !capture.name.starts_with('_')
+                    && !kind.is_known_capture(&capture.name, &mut highlight)
+                {
+                    lints.push(lint(
+                        LintKind::UnknownCapture,
+                        format!("unknown capture @{}", capture.name),
+                        &capture.span,
+                    ));
+                }
+            }
+        });
+    }
+
+    if kind == QueryKind::Highlights {
+        let mut last_identical = HashMap::new();
+        let keys: Vec<_> = file
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(i, pattern)| {
+                let key = pattern_key(source, pattern);
+                last_identical.insert(key.clone(), i);
+                key
+            })
+            .collect();
+        for (i, (pattern, key)) in file.patterns.iter().zip(&keys).enumerate() {
+            let last = last_identical[key];
+            if last != i {
+                let line =
+                    ParserErrorLocation::new(source, file.patterns[last].span.start as usize, 0)
+                        .line;
+                let start = pattern.span.start;
+                lints.push(lint(
+                    LintKind::ShadowedPattern,
+                    format!(
+                        "pattern is shadowed by the identical pattern on line {}",
+                        line + 1
+                    ),
+                    &(start..start + 1),
+                ));
+            }
+        }
+    }
+    lints.sort_by_key(|lint| lint.location.offset);
+    lints
+}
+
+/// The pattern formatted on a single line with captures renamed in the order
+/// of their first occurrence so that patterns which only differ in their
+/// capture names are identical.
+fn pattern_key(source: &str, pattern: &Expr) -> String {
+    let mut captures = HashMap::new();
+    let mut key = String::new();
+    Printer::new(source, &[]).flat(pattern, &mut key);
+    let mut normalized = String::with_capacity(key.len());
+    let mut rest = key.as_str();
+    while let Some(start) = rest.find('@') {
+        normalized.push_str(&rest[..=start]);
+        rest = &rest[start + 1..];
+        let len = rest
+            .find(|c: char| c.is_whitespace() || c == ')')
+            .unwrap_or(rest.len());
+        let next = captures.len();
+        let idx = *captures.entry(&rest[..len]).or_insert(next);
+        write!(normalized, "{idx}").unwrap();
+        rest = &rest[len..];
+    }
+    normalized.push_str(rest);
+    normalized
+}
+
+/// Formats a query in the canonical style.
+///
+/// Patterns which fit into a single line are written on a single line.
+/// Otherwise each child is written on its own line, indented by two spaces.
+/// The alternatives of an alternation and the predicates of a pattern are
+/// always written on their own lines. Comments and single blank lines between
+/// patterns are preserved.
+pub fn format_query(source: &str) -> Result<String, ast::SyntaxError> {
+    let file = ast::parse(source)?;
+    let mut printer = Printer::new(source, &file.comments);
+    let mut out = String::with_capacity(source.len());
+    let mut prev_end = None;
+    for pattern in &file.patterns {
+        prev_end = printer.comments(pattern.span.start, prev_end, 0, &mut out);
+        if printer.blank_line_before(prev_end, pattern.span.start) {
+            out.push('\n');
+        }
+        printer.expr(pattern, 0, &mut out);
+        printer.trailing_comment(pattern.span.end, &mut out);
+        out.push('\n');
+        prev_end = Some(pattern.span.end);
+    }
+    printer.comments(source.len() as u32, prev_end, 0, &mut out);
+    Ok(out)
+}
+
+struct Printer<'a> {
+    source: &'a str,
+    comments: &'a [ast::Comment],
+    /// The index of the next comment which was not printed yet.
+    next_comment: usize,
+}
+
+impl<'a> Printer<'a> {
+    fn new(source: &'a str, comments: &'a [ast::Comment]) -> Self {
+        Printer {
+            source,
+            comments,
+            next_comment: 0,
+        }
+    }
+
+    fn text(&self, span: &ast::Span) -> &'a str {
+        &self.source[span.start as usize..span.end as usize]
+    }
+
+    fn blank_line_before(&self, prev_end: Option<u32>, start: u32) -> bool {
+        prev_end.is_some_and(|prev_end| {
+            self.source[prev_end as usize..start as usize]
+                .lines()
+                .skip(1)
+                .any(|line| line.trim().is_empty())
+        })
+    }
+
+    fn has_comments(&self, span: &ast::Span) -> bool {
+        self.comments[self.next_comment..]
+            .first()
+            .is_some_and(|comment| comment.span.start < span.end)
+    }
+
+    /// Prints the comments which start before `end`, each on its own line.
+    /// Returns the end of the last printed comment or `prev_end`.
+    fn comments(
+        &mut self,
+        end: u32,
+        mut prev_end: Option<u32>,
+        indent: usize,
+        out: &mut String,
+    ) -> Option<u32> {
+        while let Some(comment) = self.comments.get(self.next_comment) {
+            if comment.span.start >= end {
+                break;
+            }
+            if self.blank_line_before(prev_end, comment.span.start) {
+                out.push('\n');
+            }
+            push_indent(out, indent);
+            out.push_str(&comment.text);
+            out.push('\n');
+            prev_end = Some(comment.span.end);
+            self.next_comment += 1;
+        }
+        prev_end
+    }
+
+    /// Prints a comment which follows `end` on the same line.
+    fn trailing_comment(&mut self, end: u32, out: &mut String) -> bool {
+        let Some(comment) = self.comments.get(self.next_comment) else {
+            return false;
+        };
+        if comment.span.start < end
+            || self.source[end as usize..comment.span.start as usize].contains('\n')
+        {
+            return false;
+        }
+        out.push(' ');
+        out.push_str(&comment.text);
+        self.next_comment += 1;
+        true
+    }
+
+    fn expr(&mut self, expr: &Expr, indent: usize, out: &mut String) {
+        if !self.has_comments(&expr.span) && !must_break(expr) {
+            let mut flat = String::new();
+            self.flat(expr, &mut flat);
+            let column = out.len() - out.rfind('\n').map_or(0, |i| i + 1);
+            if column + flat.chars().count() <= MAX_WIDTH {
+                out.push_str(&flat);
+                return;
+            }
+        }
+        if let Some(field) = &expr.field {
+            write!(out, "{}: ", field.name).unwrap();
+        }
+        let end = expr.span.end;
+        match &expr.kind {
+            ExprKind::Named(node) => {
+                out.push('(');
+                if let Some(supertype) = &node.supertype {
+                    write!(out, "{}/", supertype.name).unwrap();
+                }
+                out.push_str(&node.kind.name);
+                self.children(&node.children, end, indent + 2, indent, false, out);
+                out.push(')');
+            }
+            ExprKind::Group(children) => {
+                out.push('(');
+                self.children(children, end, indent + 1, indent, true, out);
+                out.push(')');
+            }
+            ExprKind::Alternation(children) => {
+                out.push('[');
+                if !self.children(children, end, indent + 2, indent, false, out) {
+                    out.push('\n');
+                    push_indent(out, indent);
+                }
+                out.push(']');
+            }
+            ExprKind::Missing(_) | ExprKind::Anonymous(_) | ExprKind::Wildcard => {
+                self.flat_kind(expr, out)
+            }
+        }
+        self.suffixes(expr, out);
+    }
+
+    /// Prints each child on its own line. If `inline_first` is set the first
+    /// child directly follows the opening parenthesis.
+    ///
+    /// Comments after the last child are printed before the closing
+    /// parenthesis, which is moved to its own line indented by `close_indent`.
+    /// Returns whether that happened.
+    fn children(
+        &mut self,
+        children: &[Child],
+        end: u32,
+        indent: usize,
+        close_indent: usize,
+        inline_first: bool,
+        out: &mut String,
+    ) -> bool {
+        let mut prev_end = None;
+        let mut trailing_comment = false;
+        for (i, child) in children.iter().enumerate() {
+            let span = child_span(child);
+            if i != 0 || !inline_first || self.has_comments(&(0..span.start)) {
+                out.push('\n');
+                self.comments(span.start, prev_end, indent, out);
+                push_indent(out, indent);
+            }
+            match child {
+                Child::Expr(expr) => self.expr(expr, indent, out),
+                child => self.flat_child(child, out),
+            }
+            prev_end = Some(span.end);
+            trailing_comment = self.trailing_comment(span.end, out);
+        }
+        if !trailing_comment && !self.has_comments(&(0..end)) {
+            return false;
+        }
+        out.push('\n');
+        self.comments(end, prev_end, indent, out);
+        push_indent(out, close_indent);
+        true
+    }
+
+    fn suffixes(&self, expr: &Expr, out: &mut String) {
+        out.push_str(match expr.quantifier {
+            Quantifier::ZeroOrOne => "?",
+            Quantifier::ZeroOrMore => "*",
+            Quantifier::OneOrMore => "+",
+            Quantifier::Zero | Quantifier::One => "",
+        });
+        for capture in &expr.captures {
+            write!(out, " @{}", capture.name).unwrap();
+        }
+    }
+
+    /// Prints an expression on a single line.
+    fn flat(&self, expr: &Expr, out: &mut String) {
+        if let Some(field) = &expr.field {
+            write!(out, "{}: ", field.name).unwrap();
+        }
+        self.flat_kind(expr, out);
+        self.suffixes(expr, out);
+    }
+
+    fn flat_kind(&self, expr: &Expr, out: &mut String) {
+        match &expr.kind {
+            ExprKind::Named(node) => {
+                out.push('(');
+                if let Some(supertype) = &node.supertype {
+                    write!(out, "{}/", supertype.name).unwrap();
+                }
+                out.push_str(&node.kind.name);
+                for child in &node.children {
+                    out.push(' ');
+                    self.flat_child(child, out);
+                }
+                out.push(')');
+            }
+            ExprKind::Missing(name) => {
+                out.push_str("(MISSING");
+                match name {
+                    Some(NodeName::Named(name)) => write!(out, " {}", name.name).unwrap(),
+                    Some(NodeName::Anonymous(str)) => {
+                        write!(out, " {}", self.text(&str.span)).unwrap()
+                    }
+                    None => (),
+                }
+                out.push(')');
+            }
+            ExprKind::Anonymous(str) => out.push_str(self.text(&str.span)),
+            ExprKind::Wildcard => out.push('_'),
+            ExprKind::Group(children) | ExprKind::Alternation(children) => {
+                let (open, close) = match expr.kind {
+                    ExprKind::Group(_) => ('(', ')'),
+                    _ => ('[', ']'),
+                };
+                out.push(open);
+                for (i, child) in children.iter().enumerate() {
+                    if i != 0 {
+                        out.push(' ');
+                    }
+                    self.flat_child(child, out);
+                }
+                out.push(close);
+            }
+        }
+    }
+
+    fn flat_child(&self, child: &Child, out: &mut String) {
+        match child {
+            Child::Expr(expr) => self.flat(expr, out),
+            Child::Anchor(_) => out.push('.'),
+            Child::NegatedField(field) => write!(out, "!{}", field.name).unwrap(),
+            Child::Predicate(predicate) => {
+                write!(out, "(#{}", predicate.name.name).unwrap();
+                for arg in &predicate.args {
+                    match arg {
+                        PredicateArg::Capture(capture) => write!(out, " @{}", capture.name),
+                        PredicateArg::String(str) => write!(out, " {}", self.text(&str.span)),
+                        PredicateArg::Ident(ident) => write!(out, " {}", ident.name),
+                    }
+                    .unwrap();
+                }
+                out.push(')');
+            }
+        }
+    }
+}
+
+/// Alternations and the predicates of a pattern are always written on
+/// separate lines.
+fn must_break(expr: &Expr) -> bool {
+    match &expr.kind {
+        ExprKind::Alternation(_) => true,
+        ExprKind::Group(children)
+            if children
+                .iter()
+                .any(|child| matches!(child, Child::Predicate(_))) =>
+        {
+            true
+        }
+        _ => expr.children().iter().any(|child| match child {
+            Child::Expr(expr) => must_break(expr),
+            _ => false,
+        }),
+    }
+}
+
+fn child_span(child: &Child) -> ast::Span {
+    match child {
+        Child::Expr(expr) => expr.span.clone(),
+        Child::Anchor(span) => span.clone(),
+        Child::NegatedField(field) => field.span.clone(),
+        Child::Predicate(predicate) => predicate.span.clone(),
+    }
+}
+
+fn push_indent(out: &mut String, indent: usize) {
+    out.extend(std::iter::repeat_n(' ', indent));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::format_query;
+
+    #[test]
+    fn format() {
+        let source = r#"; keywords
+[ "def"   "class" ] @keyword
+
+
+(function_definition name:(identifier)@function) ; trailing
+( (identifier) @constant (#match?   @constant "^[A-Z_]+$") )
+(call function: (attribute object: (identifier) @variable attribute: (identifier) @function.method) arguments: (argument_list))
+(class_definition
+  ; the name
+  name: (identifier) @type
+  body: (block . (expression_statement (string) @comment.documentation)))
+"#;
+        let expected = r#"; keywords
+[
+  "def"
+  "class"
+] @keyword
+
+(function_definition name: (identifier) @function) ; trailing
+((identifier) @constant
+ (#match? @constant "^[A-Z_]+$"))
+(call
+  function: (attribute object: (identifier) @variable attribute: (identifier) @function.method)
+  arguments: (argument_list))
+(class_definition
+  ; the name
+  name: (identifier) @type
+  body: (block . (expression_statement (string) @comment.documentation)))
+"#;
+        let formatted = format_query(source).unwrap();
+        assert_eq!(formatted, expected);
+        assert_eq!(format_query(&formatted).unwrap(), formatted);
+    }
+}
@@ -0,0 +1,66 @@
+use crate::{Injection, Language, Layer, Syntax};
+
+/// The layer tree of a [`Syntax`] without the syntax trees.
+///
+/// A snapshot describes which language is used for which part of a document
+/// and can be sent to another process, for example with the `serde` feature.
+/// See [`Syntax::snapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SyntaxSnapshot {
+    pub root: Layer,
+    /// The layers of the syntax tree sorted by their [`Layer`].
+    pub layers: Vec<LayerSnapshot>,
+}
+
+impl SyntaxSnapshot {
+    pub fn layer(&self, layer: Layer) -> Option<&LayerSnapshot> {
+        let i = self
+            .layers
+            .binary_search_by_key(&layer.0, |snapshot| snapshot.layer.0)
+            .ok()?;
+        Some(&self.layers[i])
+    }
+
+    /// Returns the snapshot of the [`SyntaxSnapshot::root`] layer.
+    ///
+    /// This is always `Some` for snapshots taken with [`Syntax::snapshot`] but
+    /// may be `None` for snapshots which were constructed or deserialized.
+    pub fn root_layer(&self) -> Option<&LayerSnapshot> {
+        self.layer(self.root)
+    }
+}
+
+/// A single layer of a [`SyntaxSnapshot`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct LayerSnapshot {
+    pub layer: Layer,
+    pub language: Language,
+    /// The ranges of the document which are parsed by this layer.
+    pub ranges: Vec<tree_sitter::Range>,
+    pub parent: Option<Layer>,
+    /// The **sorted** injections within this layer, see [`crate::LayerData`].
+    pub injections: Vec<Injection>,
+}
+
+impl Syntax {
+    /// Takes a snapshot of the layers of this syntax tree.
+    pub fn snapshot(&self) -> SyntaxSnapshot {
+        let layers = self
+            .layers
+            .iter()
+            .map(|(idx, layer)| LayerSnapshot {
+                layer: Layer(idx as u32),
+                language: layer.language,
+                ranges: layer.ranges.clone(),
+                parent: layer.parent,
+                injections: layer.injections.clone(),
+            })
+            .collect();
+        SyntaxSnapshot {
+            root: self.root,
+            layers,
+        }
+    }
+}
@@ -0,0 +1,160 @@
+use std::fmt;
+
+use ropey::RopeSlice;
+use tree_sitter::{LookaheadIterator, Node, Point, SymbolType};
+
+use crate::{Language, Layer, Range, Syntax};
+
+/// A syntax error found in one of the layers of a [`Syntax`] tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SyntaxError {
+    pub kind: SyntaxErrorKind,
+    /// The language of the layer the error was found in.
+    pub language: Language,
+    pub layer: Layer,
+    /// The range of the error. Unlike the points of injected layers' syntax
+    /// trees, the points are always relative to the start of the document.
+    pub range: tree_sitter::Range,
+    /// The kinds of the tokens which the parser would have accepted at the
+    /// error, deduplicated and sorted.
+    pub expected: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxErrorKind {
+    /// Text that could not be parsed, an `ERROR` node.
+    Unexpected,
+    /// A token that the parser inserted to recover from an error, a `MISSING`
+    /// node. The missing token is the only element of
+    /// [`SyntaxError::expected`].
+    Missing,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.kind, self.expected.as_slice()) {
+            (SyntaxErrorKind::Missing, [token, ..]) => write!(f, "missing `{token}`"),
+            (_, []) => f.write_str("syntax error"),
+            (_, [token]) => write!(f, "syntax error, expected `{token}`"),
+            (_, tokens) => {
+                f.write_str("syntax error, expected one of ")?;
+                for (i, token) in tokens.iter().enumerate() {
+                    if i != 0 {
+                        f.write_str(", ")?;
+                    }
+                    write!(f, "`{token}`")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl Syntax {
+    /// Collects the syntax errors (`ERROR` and `MISSING` nodes) that intersect
+    /// the byte range `range.start..=range.end` from all layers, including
+    /// injections.
+    ///
+    /// Errors are ordered by layer and then by their position within the
+    /// layer. Subtrees without errors are skipped, so this is cheap for mostly
+    /// valid documents.
+    pub fn syntax_errors(&self, source: RopeSlice, range: Range) -> Vec<SyntaxError> {
+        let mut errors = Vec::new();
+        for (idx, layer_data) in self.layers.iter() {
+            let Some(tree) = layer_data.tree() else {
+                continue;
+            };
+            let intersects_range = layer_data
+                .ranges
+                .iter()
+                .any(|r| r.start_byte <= range.end && r.end_byte >= range.start);
+            if !intersects_range {
+                continue;
+            }
+            let layer = Layer(idx as u32);
+            let mut cursor = tree.walk();
+            'walk: loop {
+                let node = cursor.node();
+                if node.has_error()
+                    && node.start_byte() <= range.end
+                    && node.end_byte() >= range.start
+                {
+                    let kind = if node.is_error() {
+                        Some(SyntaxErrorKind::Unexpected)
+                    } else if node.is_missing() {
+                        Some(SyntaxErrorKind::Missing)
+                    } else {
+                        None
+                    };
+                    if let Some(kind) = kind {
+                        errors.push(SyntaxError {
+                            kind,
+                            language: layer_data.language,
+                            layer,
+                            range: document_range(source, &node),
+                            expected: expected_tokens(kind, &node),
+                        });
+                    } else if cursor.goto_first_child() {
+                        continue;
+                    }
+                }
+                while !cursor.goto_next_sibling() {
+                    if !cursor.goto_parent() {
+                        break 'walk;
+                    }
+                }
+            }
+        }
+        errors
+    }
+}
+
+fn document_range(source: RopeSlice, node: &Node) -> tree_sitter::Range {
+    let point = |byte: u32| {
+        let byte = (byte as usize).min(source.len_bytes());
+        let row = source.byte_to_line(byte);
+        Point {
+            row: row as u32,
+            col: (byte - source.line_to_byte(row)) as u32,
+        }
+    };
+    tree_sitter::Range::new(
+        point(node.start_byte()),
+        point(node.end_byte()),
+        node.start_byte(),
+        node.end_byte(),
+    )
+}
+
+fn expected_tokens(kind: SyntaxErrorKind, node: &Node) -> Vec<&'static str> {
+    let grammar = node.grammar();
+    if kind == SyntaxErrorKind::Missing {
+        return grammar
+            .node_kind_for_id(node.kind_id())
+            .into_iter()
+            .collect();
+    }
+    // The tokens which were valid at the error are the ones which were valid
+    // in the parse state of the error's first leaf.
+    let mut leaf = node.clone();
+    while let Some(child) = leaf.child(0) {
+        leaf = child;
+    }
+    let Some(mut lookahead) = LookaheadIterator::new(grammar, leaf.parse_state()) else {
+        return Vec::new();
+    };
+    let mut tokens: Vec<_> = lookahead
+        .by_ref()
+        .filter(|&symbol| {
+            grammar.is_token(symbol)
+                && matches!(
+                    grammar.symbol_type(symbol),
+                    SymbolType::Regular | SymbolType::Anonymous
+                )
+        })
+        .filter_map(|symbol| grammar.node_kind_for_id(symbol))
+        .collect();
+    tokens.sort_unstable();
+    tokens.dedup();
+    tokens
+}
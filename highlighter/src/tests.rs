@@ -3,18 +3,23 @@ use std::cell::RefCell;
 use std::collections::HashSet;
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::time::Duration;
 
 use indexmap::{IndexMap, IndexSet};
 use once_cell::sync::Lazy;
 use once_cell::unsync::OnceCell;
 use skidder::Repo;
-use tree_sitter::{Grammar, InputEdit, Point};
+use tree_sitter::{self as tree, Grammar, InputEdit, Point};
 
 use crate::config::{LanguageConfig, LanguageLoader};
 use crate::fixtures::{check_highlighter_fixture, check_injection_fixture};
 use crate::highlighter::{Highlight, HighlightEvent, Highlighter};
 use crate::injections_query::InjectionLanguageMarker;
-use crate::{Language, Layer, Syntax};
+use crate::query_lint::{lint_query, LintKind, QueryKind};
+use crate::text_object::{CapturedNode, TextObjectQuery};
+use crate::{
+    pretty_print_tree, Error, Language, Layer, LayerData, Syntax, SyntaxErrorKind, UpdateStatus,
+};
 
 const PARSE_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(1);
 
@@ -119,8 +124,6 @@ impl TestLanguageLoader {
         self.languages[name]
     }
 
-    // TODO: remove on first use.
-    #[allow(dead_code)]
     fn overwrite_injections(&mut self, lang: &str, content: String) {
         let lang = self.get(lang);
         self.overwrites[lang.idx()].injections = Some(content);
@@ -551,6 +554,189 @@ fn edit_remove_and_add_injection_layer() {
         .unwrap();
 }
 
+#[test]
+fn update_reports_changed_ranges() {
+    let loader = TestLanguageLoader::new();
+    let before_text = "x = 1\ny = 2\n";
+    let after_text = "x = 1\ny = [2]\n";
+    let edit = InputEdit {
+        start_byte: 10,
+        old_end_byte: 11,
+        new_end_byte: 13,
+        start_point: Point { row: 1, col: 4 },
+        old_end_point: Point { row: 1, col: 5 },
+        new_end_point: Point { row: 1, col: 7 },
+    };
+    let mut syntax = Syntax::new(
+        before_text.into(),
+        loader.get("python"),
+        PARSE_TIMEOUT,
+        &loader,
+    )
+    .unwrap();
+    let changed = syntax
+        .update_with_changed_ranges(after_text.into(), PARSE_TIMEOUT, &[edit], &loader)
+        .unwrap();
+    assert!(
+        changed
+            .iter()
+            .any(|range| range.start <= 10 && range.end >= 13),
+        "the new list must be reported as changed: {changed:?}"
+    );
+    assert!(
+        changed.iter().all(|range| range.start >= 6),
+        "the first line did not change: {changed:?}"
+    );
+
+    // Removing the language from a code fence destroys the rust injection layer. The code in the
+    // fence must be reported as changed.
+    let before_text = "```rust\nfn a() {}\n```\n";
+    let after_text = "```\nfn a() {}\n```\n";
+    let edit = InputEdit {
+        start_byte: 3,
+        old_end_byte: 7,
+        new_end_byte: 3,
+        start_point: Point { row: 0, col: 3 },
+        old_end_point: Point { row: 0, col: 7 },
+        new_end_point: Point { row: 0, col: 3 },
+    };
+    let mut syntax = Syntax::new(
+        before_text.into(),
+        loader.get("markdown"),
+        PARSE_TIMEOUT,
+        &loader,
+    )
+    .unwrap();
+    let changed = syntax
+        .update_with_changed_ranges(after_text.into(), PARSE_TIMEOUT, &[edit], &loader)
+        .unwrap();
+    assert!(
+        changed
+            .iter()
+            .any(|range| range.start <= 4 && range.end >= 14),
+        "the removed injection must be reported as changed: {changed:?}"
+    );
+}
+
+/// Asserts that two syntaxes have the same layers with the same trees.
+fn assert_same_layers(syntax: &Syntax, expected: &Syntax) {
+    fn layers(syntax: &Syntax) -> Vec<&LayerData> {
+        let mut layers: Vec<_> = syntax.layers.iter().map(|(_, layer)| layer).collect();
+        layers.sort_by_key(|layer| (layer.ranges[0].start_byte, layer.language));
+        layers
+    }
+    let (layers, expected) = (layers(syntax), layers(expected));
+    assert_eq!(layers.len(), expected.len());
+    for (layer, expected) in layers.into_iter().zip(expected) {
+        assert_eq!(layer.language, expected.language);
+        assert_eq!(layer.ranges, expected.ranges);
+        let (tree, expected) = (layer.tree().unwrap(), expected.tree().unwrap());
+        assert_eq!(
+            tree.root_node().byte_range(),
+            expected.root_node().byte_range()
+        );
+        assert_eq!(tree::Tree::changed_ranges(tree, expected).count(), 0);
+    }
+}
+
+#[test]
+fn update_resumable_in_time_slices() {
+    let loader = TestLanguageLoader::new();
+    let fence = "```rust\nfn a() -> Vec<u32> { vec![1, 2] }\n```\n\n*text* and `code`\n\n";
+    let before_text = "# title\n";
+    let after_text = format!("# title\n{}", fence.repeat(100));
+    let edit = InputEdit {
+        start_byte: 8,
+        old_end_byte: 8,
+        new_end_byte: after_text.len() as u32,
+        start_point: Point { row: 1, col: 0 },
+        old_end_point: Point { row: 1, col: 0 },
+        new_end_point: Point {
+            row: after_text.lines().count() as u32,
+            col: 0,
+        },
+    };
+    let markdown = loader.get("markdown");
+    let new_syntax = || Syntax::new(before_text.into(), markdown, PARSE_TIMEOUT, &loader).unwrap();
+    let expected =
+        Syntax::new(after_text.as_str().into(), markdown, PARSE_TIMEOUT, &loader).unwrap();
+
+    let mut syntax = new_syntax();
+    let mut slices = 1;
+    let mut status = syntax
+        .update_resumable(after_text.as_str().into(), Duration::ZERO, &[edit], &loader)
+        .unwrap();
+    while status == UpdateStatus::Suspended {
+        assert!(syntax.has_pending_update());
+        slices += 1;
+        status = syntax
+            .update_resumable(after_text.as_str().into(), Duration::ZERO, &[], &loader)
+            .unwrap();
+    }
+    assert!(slices > 1);
+    assert!(!syntax.has_pending_update());
+    assert_same_layers(&syntax, &expected);
+
+    // A clone restarts the suspended parse, a regular update finishes it.
+    let mut syntax = new_syntax();
+    syntax
+        .update_resumable(after_text.as_str().into(), Duration::ZERO, &[edit], &loader)
+        .unwrap();
+    syntax
+        .update_resumable(after_text.as_str().into(), Duration::ZERO, &[], &loader)
+        .unwrap();
+    assert!(syntax.has_pending_update());
+    let mut clone = syntax.clone();
+    clone
+        .update(after_text.as_str().into(), PARSE_TIMEOUT, &[], &loader)
+        .unwrap();
+    assert!(!clone.has_pending_update());
+    assert_same_layers(&clone, &expected);
+
+    // A regular update which times out keeps the update pending.
+    let result = syntax.update(after_text.as_str().into(), Duration::ZERO, &[], &loader);
+    assert_eq!(result, Err(Error::Timeout));
+    assert!(syntax.has_pending_update());
+
+    // Editing the document abandons the suspended update.
+    let removed = InputEdit {
+        start_byte: 0,
+        old_end_byte: 8,
+        new_end_byte: 0,
+        start_point: Point { row: 0, col: 0 },
+        old_end_point: Point { row: 1, col: 0 },
+        new_end_point: Point { row: 0, col: 0 },
+    };
+    syntax
+        .update(after_text[8..].into(), PARSE_TIMEOUT, &[removed], &loader)
+        .unwrap();
+    assert!(!syntax.has_pending_update());
+    let expected = Syntax::new(after_text[8..].into(), markdown, PARSE_TIMEOUT, &loader).unwrap();
+    assert_same_layers(&syntax, &expected);
+}
+
+#[test]
+fn pretty_print_syntax_tree() {
+    let loader = TestLanguageLoader::new();
+    let source = "def f(a):\n    return a\n";
+    let syntax = Syntax::new(source.into(), loader.get("python"), PARSE_TIMEOUT, &loader).unwrap();
+    let mut pretty = String::new();
+    pretty_print_tree(&mut pretty, syntax.tree().root_node()).unwrap();
+    assert_eq!(
+        pretty,
+        "(module
+  (function_definition
+    name: (identifier)
+    parameters: (parameters
+      (identifier))
+    body: (block
+      (return_statement
+        (identifier)))))"
+    );
+    // the pretty printed tree is a valid S-expression
+    tree::assert_tree_matches(syntax.tree(), &pretty);
+}
+
 #[test]
 fn markdown_bold_highlight() {
     let loader = TestLanguageLoader::new();
@@ -733,3 +919,256 @@ fn highlighter_ancestor_highlight_not_replaced_by_child_capture() {
         "both the decorator's @function and `B`'s @constant should be active"
     );
 }
+
+#[test]
+fn syntax_errors_in_injections() {
+    let loader = TestLanguageLoader::new();
+    let source = "# Title\n\n```rust\nfn a() {\n    let x = ;\n}\n```\n\n```rust\nfn b( {}\n```\n";
+    let syntax = Syntax::new(
+        source.into(),
+        loader.get("markdown"),
+        PARSE_TIMEOUT,
+        &loader,
+    )
+    .unwrap();
+    let errors = syntax.syntax_errors(source.into(), 0..source.len() as u32);
+    assert_eq!(errors.len(), 2);
+    assert!(errors
+        .iter()
+        .all(|error| error.language == loader.get("rust")));
+    assert_ne!(errors[0].layer, errors[1].layer);
+
+    let unexpected = &errors[0];
+    assert_eq!(unexpected.kind, SyntaxErrorKind::Unexpected);
+    assert_eq!(
+        unexpected.range.start_byte..unexpected.range.end_byte,
+        36..37
+    );
+    assert_eq!(unexpected.range.start_point, Point { row: 4, col: 10 });
+    // the tokens valid in the parse state of the error's first leaf, without
+    // rules like `type_arguments` or `line_comment`
+    assert_eq!(
+        unexpected.expected,
+        [
+            "!", "(", ")", ",", "..", "...", "..=", "/*", "//", ":", "::", ";", "<", "=", "@", "]",
+            "else", "in", "{", "|", "}"
+        ]
+    );
+    assert!(unexpected
+        .to_string()
+        .starts_with("syntax error, expected one of "));
+
+    let missing = &errors[1];
+    assert_eq!(missing.kind, SyntaxErrorKind::Missing);
+    assert_eq!(missing.range.start_point, Point { row: 9, col: 5 });
+    assert_eq!(missing.to_string(), "missing `)`");
+
+    assert_eq!(
+        syntax.syntax_errors(source.into(), 50..70),
+        std::slice::from_ref(missing)
+    );
+    assert!(syntax.syntax_errors(source.into(), 0..20).is_empty());
+}
+
+#[test]
+fn injection_directives() {
+    let mut loader = TestLanguageLoader::new();
+    loader.overwrite_injections(
+        "markdown",
+        r#"
+(fenced_code_block
+  (info_string
+    (language) @injection.language)
+  (code_fence_content) @injection.content
+  (#gsub! @injection.language "^lang%-(%a+)$" "%1")
+  (#offset! @injection.content 0 2 0 0)
+  (#trim! @injection.content 1 1 1 1)
+  (#set! injection.include-unnamed-children))
+"#
+        .to_string(),
+    );
+    let source = "```lang-rust\n  \n// a\nfn a() {}\n\n```\n";
+    let syntax = Syntax::new(
+        source.into(),
+        loader.get("markdown"),
+        PARSE_TIMEOUT,
+        &loader,
+    )
+    .unwrap();
+    let injections = &syntax.layer(syntax.root()).injections;
+    assert_eq!(injections.len(), 1);
+    let injection = &injections[0];
+    assert_eq!(syntax.layer(injection.layer).language, loader.get("rust"));
+    assert_eq!(
+        &source[injection.range.start as usize..injection.range.end as usize],
+        "// a\nfn a() {}"
+    );
+
+    // offsets which point into a multibyte character
+    loader.overwrite_injections(
+        "markdown",
+        r#"
+(fenced_code_block
+  (info_string
+    (language) @injection.language)
+  (code_fence_content) @injection.content
+  (#offset! @injection.language 0 1 0 0)
+  (#gsub! @injection.language "^ä" "")
+  (#offset! @injection.content 0 1 0 -1)
+  (#trim! @injection.content)
+  (#set! injection.include-unnamed-children))
+"#
+        .to_string(),
+    );
+    let source = "```ärust\né\nfn a() {}\n```\n";
+    let syntax = Syntax::new(
+        source.into(),
+        loader.get("markdown"),
+        PARSE_TIMEOUT,
+        &loader,
+    )
+    .unwrap();
+    let injections = &syntax.layer(syntax.root()).injections;
+    assert_eq!(injections.len(), 1);
+    let injection = &injections[0];
+    assert_eq!(syntax.layer(injection.layer).language, loader.get("rust"));
+    assert_eq!(
+        &source[injection.range.start as usize..injection.range.end as usize],
+        "é\nfn a() {}"
+    );
+}
+
+#[test]
+fn text_object_repeated_captures() {
+    let loader = TestLanguageLoader::new();
+    let grammar = loader.get_config(loader.get("python")).unwrap().grammar;
+    let query =
+        tree::Query::new(grammar, "(comment)+ @comment.around", |_, _| unreachable!()).unwrap();
+    let query = TextObjectQuery { query };
+
+    let source = "# a\nx = 1\n# b\n# c\n";
+    let syntax = Syntax::new(source.into(), loader.get("python"), PARSE_TIMEOUT, &loader).unwrap();
+    let text = ropey::Rope::from_str(source);
+    let mut captures: Vec<_> = query
+        .capture_nodes(
+            "comment.around",
+            syntax.tree().root_node(),
+            text.slice(..),
+            tree::InactiveQueryCursor::default(),
+        )
+        .unwrap()
+        .map(|capture| match capture {
+            CapturedNode::Single(_) => panic!("repeated capture returned a single node"),
+            CapturedNode::Grouped(nodes) => nodes
+                .iter()
+                .map(|node| &source[node.start_byte() as usize..node.end_byte() as usize])
+                .collect::<Vec<_>>(),
+        })
+        .collect();
+    // A match is yielded once for every node it captures.
+    captures.dedup();
+    assert_eq!(captures, [vec!["# a"], vec!["# b", "# c"]]);
+}
+
+#[test]
+fn lint_queries() {
+    let loader = TestLanguageLoader::new();
+    let grammar = loader.get_config(loader.get("python")).unwrap().grammar;
+    let highlight = |mut scope: &str| loop {
+        if matches!(scope, "variable" | "function" | "constant") {
+            break Some(Highlight::new(0));
+        }
+        scope = scope.rsplit_once('.')?.0;
+    };
+    let lint = |kind, source| {
+        lint_query(grammar, kind, source, highlight)
+            .into_iter()
+            .map(|lint| (lint.kind, lint.location.line))
+            .collect::<Vec<_>>()
+    };
+
+    let highlights = r#"(identifier) @variable
+(not_a_node) @variable
+(call function: (identifier) @function.call)
+((identifier) @constant (#set! priority 100))
+(identifier) @variable.other
+(string) @_private @string
+"#;
+    assert_eq!(
+        lint(QueryKind::Highlights, highlights),
+        [
+            (LintKind::ShadowedPattern, 0),
+            (LintKind::UnknownNodeKind, 1),
+            (LintKind::InvalidPredicate, 3),
+            (LintKind::UnknownCapture, 5),
+        ]
+    );
+
+    let injections = r#"((comment) @injection.content
+ (#set! injection.language "comment"))
+((string) @injection.content @string (#set! injection.combined))
+"#;
+    assert_eq!(
+        lint(QueryKind::Injections, injections),
+        [(LintKind::UnknownCapture, 2)]
+    );
+    // identical patterns only shadow each other in highlights
+    let locals = r#"(function_definition) @local.scope
+(identifier) @local.definition.string
+(identifier) @local.reference
+(identifier) @local.reference
+"#;
+    assert_eq!(
+        lint(QueryKind::Locals, locals),
+        [(LintKind::UnknownCapture, 1)]
+    );
+}
+
+#[test]
+fn syntax_snapshot() {
+    let loader = TestLanguageLoader::new();
+    let source = "# Title\n\n```python\nx = 1\n```\n";
+    let markdown = loader.get("markdown");
+    let python = loader.get("python");
+    let syntax = Syntax::new(source.into(), markdown, PARSE_TIMEOUT, &loader).unwrap();
+    let snapshot = syntax.snapshot();
+
+    let root = snapshot.root_layer().unwrap();
+    assert_eq!(root.layer, syntax.root());
+    assert_eq!(root.language, markdown);
+    assert_eq!(root.parent, None);
+    let python_layer = snapshot
+        .layers
+        .iter()
+        .find(|layer| layer.language == python)
+        .unwrap();
+    assert_eq!(python_layer.parent, Some(root.layer));
+    let injection = root
+        .injections
+        .iter()
+        .find(|injection| injection.layer == python_layer.layer)
+        .unwrap();
+    assert_eq!(&source[injection.range.start as usize..][..5], "x = 1");
+    assert_eq!(python_layer.ranges.len(), 1);
+    assert_eq!(python_layer.ranges[0].start_byte, injection.range.start);
+    for layer in &snapshot.layers {
+        assert_eq!(snapshot.layer(layer.layer), Some(layer));
+        assert_eq!(syntax.layer(layer.layer).language, layer.language);
+    }
+    let without_root = crate::SyntaxSnapshot {
+        root: snapshot.root,
+        layers: Vec::new(),
+    };
+    assert_eq!(without_root.root_layer(), None);
+
+    #[cfg(feature = "serde")]
+    {
+        let json = serde_json::to_string(&snapshot).unwrap();
+        let deserialized: crate::SyntaxSnapshot = serde_json::from_str(&json).unwrap();
+        assert_eq!(deserialized, snapshot);
+        let highlight = Highlight::new(3);
+        assert_eq!(serde_json::to_string(&highlight).unwrap(), "3");
+        assert_eq!(serde_json::from_str::<Highlight>("3").unwrap(), highlight);
+        assert!(serde_json::from_str::<Highlight>(&u32::MAX.to_string()).is_err());
+    }
+}
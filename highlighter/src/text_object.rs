@@ -39,8 +39,10 @@ impl TextObjectQuery {
     /// Run the query on the given node and return sub nodes which match given
     /// capture ("function.inside", "class.around", etc).
     ///
-    /// Captures may contain multiple nodes by using quantifiers (+, *, etc),
-    /// and support for this is partial and could use improvement.
+    /// Captures may contain multiple nodes by using quantifiers (+, *, etc).
+    /// Nodes of a capture which is quantified with `+` or `*` in the matching
+    /// pattern are always returned as [`CapturedNode::Grouped`], even if only
+    /// a single node was captured.
     ///
     /// ```query
     /// (comment)+ @capture
@@ -77,12 +79,17 @@ impl TextObjectQuery {
 
         cursor.set_match_limit(TREE_SITTER_MATCH_LIMIT);
         let mut cursor = cursor.execute_query(&self.query, &node, RopeInput::new(slice));
+        let query = &self.query;
         let capture_node = iter::from_fn(move || {
             let (mat, _) = cursor.next_matched_node()?;
-            Some(mat.nodes_for_capture(capture).cloned().collect())
+            let quantifier = query.capture_quantifier(mat.pattern(), capture);
+            let nodes: Vec<_> = mat.nodes_for_capture(capture).cloned().collect();
+            Some((quantifier, nodes))
         })
-        .filter_map(move |nodes: Vec<_>| {
-            if nodes.len() > 1 {
+        .filter_map(move |(quantifier, nodes)| {
+            if nodes.is_empty() {
+                None
+            } else if quantifier.is_repeated() || nodes.len() > 1 {
                 Some(CapturedNode::Grouped(nodes))
             } else {
                 nodes.into_iter().map(CapturedNode::Single).next()
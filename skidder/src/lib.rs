@@ -281,6 +281,7 @@ pub fn build_all_grammars(
     let failed = Mutex::new(Vec::new());
     thread::scope(|scope| {
         for _ in 0..concurrency {
+            #[allow(clippy::while_let_loop)]
             scope.spawn(|| loop {
                 let Some(grammar) = grammars.get(i.fetch_add(1, atomic::Ordering::Relaxed)) else {
                     break;